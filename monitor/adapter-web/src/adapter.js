@@ -124,7 +124,7 @@ async function histProcess(self, range) {
                 headers: {'sess': self.sess.token},
             });
             if(res.status === 200) {
-                const resArr = (await res.json()).map(data => {
+                const resArr = (await res.json()).r.map(data => {
                     data.g = range.group;
                     data.u = range.unit;
                     return data;
@@ -144,6 +144,20 @@ async function histProcess(self, range) {
     }
 }
 
+// The wplace may be reloaded on the server: its units come again with a 'c' message
+function updateGroups(self, map) {
+    const groups = {};
+    for(const groupName in map) if(map.hasOwnProperty(groupName)) {
+        const unitsOld = self.sess.groups[groupName] || {};
+        const units = {};
+        map[groupName].forEach(([unitName]) => {
+            units[unitName] = unitsOld[unitName] || {idx: null, type: null, time: null, val: null};
+        });
+        groups[groupName] = units;
+    }
+    self.sess.groups = groups;
+}
+
 function processConnection(self, map) {
         const resArr = [];
         for(const groupName in map) if(map.hasOwnProperty(groupName)) {
@@ -166,8 +180,9 @@ function connMake(self) {
         tick(self, socket);
     };
     const handleClose = evt => {
-        if(evt.code === 3000) {
-            self.promise.rej(new Error('Unauthorized'));
+        // 3000 unauthorized, 3001 logged out, 3002 revoked, 3003 expired, 3004 evicted: the session is over
+        if(evt.code >= 3000 && evt.code <= 3004) {
+            self.promise.rej(new Error(evt.reason || 'Unauthorized'));
         } else {
             if(self.isConnected) {
                 self.isConnected = false;
@@ -191,6 +206,7 @@ function connMake(self) {
                     self.onConnect();
                 }
                 if(typeof obj.m === 'object') {
+                    updateGroups(self, obj.m);
                     processConnection(self, obj.m);
                 }
             } else if(obj.x === 'p') {
@@ -2,17 +2,19 @@ const sessionBuild = require('./adapter');
 const validator = require('./validator');
 
 
-async function reqSessionMake(path, login, password) {
+async function reqSessionMake(path, login, password, wplaces) {
     const response = await fetch(path, {
         method: 'POST',
         mode: 'cors',
         headers: {'Content-Type': 'application/json'},
-        body: JSON.stringify({'l': login, 'p': password}),
+        body: JSON.stringify({'l': login, 'p': password, 'w': wplaces}),
     });
     if(response.status === 200){
         return await response.text();
     }else if(response.status === 401) {
         throw new Error(`wrong credentials for '${login}' login`);
+    }else if(response.status === 429) {
+        throw new Error(`too many failed attempts for '${login}' login, try again later`);
     } else {
         throw new Error(`http error status=${response.status}; text=${response.statusText}`);
     }
@@ -54,7 +56,7 @@ async function finalize(self) {
         if(!self.credentials) { throw new Error('one of the following must be specified: token by passing to builder.session() or login/password by passing to builder.credentials()'); }
         const password = self.credentials.password;
         self.password = null;
-        self.token = await reqSessionMake(self.path.login, self.credentials.login, password);
+        self.token = await reqSessionMake(self.path.login, self.credentials.login, password, self.wplaces);
         self.groups = await reqWplaceGet(self.path.wplace, self.token);
     } else {
         try {
@@ -63,7 +65,7 @@ async function finalize(self) {
             if(self.credentials) {
                 const password = self.password;
                 self.password = null;
-                self.token = await reqSessionMake(self.path.login, self.login, password);
+                self.token = await reqSessionMake(self.path.login, self.login, password, self.wplaces);
                 self.groups = await reqWplaceGet(self.path.wplace, self.token);
             } else {
                 throw e;
@@ -86,6 +88,11 @@ function credentials(self, login, password) {
     };
 }
 
+// Wplaces to select at login, united if several; the default one of the user if none
+function wplaces(self, names) {
+    self.wplaces = names.map(name => validator.checkString(name, 'wplace'));
+}
+
 function isSecure(self, isSec) {
     self.isSecure = validator.checkBool(isSec, 'isSecure');
 }
@@ -104,12 +111,14 @@ module.exports = function (uri) {
         credentials: null,
         password: null,
         isSecure: true,
+        wplaces: [],
         groups: null,
     };
     const builder = {
         credentials: (login, password) => { credentials(self, login, password); return builder; },
         session: (token) => { session(self, token); return builder; },
         isSecure: (isSec) => { isSecure(self, isSec); return builder; },
+        wplaces: (...names) => { wplaces(self, names); return builder; },
         finalize: async () => finalize(self),
     };
     return builder;
@@ -3,23 +3,33 @@ use std::collections::HashMap;
 use tokio::sync::mpsc::{channel as channel_tokio, Sender, Receiver, error::SendError};
 
 use crate::model::dataflow::{Group, Unit, Update, Data, Record};
+use crate::model::annotation::Annotation;
+use crate::actor::conn::Scope;
 
 
+// WebSocket close frame: code and reason
+pub type Frame = (u16, &'static str);
+
 enum SignalConnIn {
     Req,
     Close,
+    CloseWith(Frame),
     Tick,
     Pong(u64),
     Data(Data<Record<Update>>),
+    Annotation(Annotation, bool),
+    Scope(Scope),
 }
 
 #[derive(Debug)]
 pub enum SignalConnOut{
-    Close,
+    Close(Option<Frame>),
     Tick,
     Pong(u64),
     Data(Group, Unit, Record<Update>),
     DataMap(HashMap<(Group, Unit), Record<Update>>),
+    Annotations(Vec<(Annotation, bool)>),
+    Scope(Scope),
 }
 
 pub struct RxConn {
@@ -29,7 +39,7 @@ pub struct RxConn {
 impl RxConn {
     pub async fn recv(&mut self) -> Option<SignalConnOut> {
         if let Err(_) = self.tx.send(SignalConnIn::Req).await {
-            None
+            self.rx.try_recv().ok()
         } else {
             self.rx.recv().await
         }
@@ -62,6 +72,13 @@ impl TxConn {
             Ok(())
         }
     }
+    pub async fn send_annotation(&self, annotation: Annotation, is_removed: bool) -> Result<(), ()> {
+        if self.tx.send(SignalConnIn::Annotation(annotation, is_removed)).await.is_err() {
+            Err(())
+        } else {
+            Ok(())
+        }
+    }
     pub async fn send_tick(&self) -> Result<(), ()> {
         if let Err(_) = self.tx.send(SignalConnIn::Tick).await {
             Err(())
@@ -87,15 +104,32 @@ impl TxConn {
             Ok(())
         }
     }
+    pub async fn send_scope(&self, scope: Scope) -> Result<(), ()> {
+        if self.tx.send(SignalConnIn::Scope(scope)).await.is_err() {
+            Err(())
+        } else {
+            Ok(())
+        }
+    }
+    pub async fn send_close_with(&self, frame: Frame) -> Result<(), ()> {
+        if self.tx.send(SignalConnIn::CloseWith(frame)).await.is_err() {
+            Err(())
+        } else {
+            Ok(())
+        }
+    }
 }
 
 
 struct ChannelConn {
     is_closed: bool,
+    frame: Option<Frame>,
     is_awaiting: bool,
     pong: Option<u64>,
     tick: Option<()>,
     map: Option<HashMap<(Group, Unit), Record<Update>>>, 
+    annotations: Option<Vec<(Annotation, bool)>>,
+    scope: Option<Scope>,
     rx: Receiver<SignalConnIn>,
     tx: Sender<SignalConnOut>,
 }
@@ -107,7 +141,10 @@ impl ChannelConn {
         }
         if self.is_awaiting {
             self.is_awaiting = false;
-            self.tx.try_send(SignalConnOut::Close);
+            self.tx.try_send(SignalConnOut::Close(self.frame));
+        } else if self.frame.is_some() {
+            // the connection is busy: the frame is left for its next receive
+            let _ = self.tx.try_send(SignalConnOut::Close(self.frame));
         }
     }
 
@@ -116,9 +153,15 @@ impl ChannelConn {
             match signal {
                 SignalConnIn::Req => self.serve_req(),
                 SignalConnIn::Close => self.close(),
+                SignalConnIn::CloseWith(frame) => {
+                    self.frame.get_or_insert(frame);
+                    self.close();
+                },
                 SignalConnIn::Tick => self.serve_tick(),
                 SignalConnIn::Pong(val) => self.serve_pong(val),
                 SignalConnIn::Data(data) => self.serve_data(data),
+                SignalConnIn::Annotation(annotation, is_removed) => self.serve_annotation(annotation, is_removed),
+                SignalConnIn::Scope(scope) => self.serve_scope(scope),
             }
         }
     }
@@ -182,9 +225,33 @@ impl ChannelConn {
         } 
     }
 
+    // Unlike data, every change is queued: a later edit does not make an earlier removal redundant
+    fn serve_annotation(&mut self, annotation: Annotation, is_removed: bool) {
+        if self.is_awaiting {
+            self.is_awaiting = false;
+            if self.tx.try_send(SignalConnOut::Annotations(vec![(annotation, is_removed)])).is_err() {
+                self.close();
+            }
+        } else {
+            self.annotations.get_or_insert_with(Vec::new).push((annotation, is_removed));
+        }
+    }
+
+    // Only the latest scope matters
+    fn serve_scope(&mut self, scope: Scope) {
+        if self.is_awaiting {
+            self.is_awaiting = false;
+            if self.tx.try_send(SignalConnOut::Scope(scope)).is_err() {
+                self.close();
+            }
+        } else {
+            self.scope = Some(scope);
+        }
+    }
+
     fn serve_req(&mut self) {
         if self.is_closed {
-            self.tx.try_send(SignalConnOut::Close);
+            self.tx.try_send(SignalConnOut::Close(self.frame));
         } if let Some(_) = self.tick.take() {
             if let Err(err) = self.tx.try_send(SignalConnOut::Tick) {
                 self.close();
@@ -197,6 +264,14 @@ impl ChannelConn {
             if let Err(err) = self.tx.try_send(SignalConnOut::DataMap(map)) {
                 self.close();
             }
+        } else if let Some(scope) = self.scope.take() {
+            if self.tx.try_send(SignalConnOut::Scope(scope)).is_err() {
+                self.close();
+            }
+        } else if let Some(vec) = self.annotations.take() {
+            if self.tx.try_send(SignalConnOut::Annotations(vec)).is_err() {
+                self.close();
+            }
         } else {
             self.is_awaiting = true;
         }
@@ -211,10 +286,13 @@ pub fn channel_sec() -> (TxConn, RxConn) {
 
     let mut ch = ChannelConn{
         is_closed: false,
+        frame: None,
         is_awaiting: false,
         tick: None,
         pong: None,
         map: None,
+        annotations: None,
+        scope: None,
         rx: rx_in,
         tx: tx_out,
     };
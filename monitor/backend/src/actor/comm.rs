@@ -1,6 +1,8 @@
 use core::slice::{Iter as IterVec};
 use std::{
     collections::{HashMap, HashSet, hash_map::Iter as IterMap},
+    net::IpAddr,
+    str::FromStr,
 };
 
 use tokio::{
@@ -12,14 +14,26 @@ use tokio::{
 };
 use warp::filters::ws::WebSocket;
 
+use crate::config::ConfigWplace;
 use crate::actor::{
     db::{Signal as SignalDb},
+    conn::{FRAME_LOGOUT, FRAME_REVOKED},
 };
+mod store;
+mod guard;
+pub mod watch;
+
+pub use store::Store;
+pub use guard::Guard;
+use store::RecordSession;
 use crate::model::{
-    session::{Token},
+    session::{Token, Policy},
     user::{Login, User},
     dataflow::{Group, Unit, Update, Data, Record},
+    annotation::Annotation,
+    info::{self, InfoGroup},
     wplace::{Name as NameWplace, Wplace},
+    role::{Role, Action},
 };
 
 
@@ -30,11 +44,14 @@ pub enum Signal {
     FromDb(FromDb),
     FromSession(Login, Token, FromSession),
     FromConn(Login, Token, u64, FromConn),
+    FromWatch(FromWatch),
+    SessionsSave,
 }
 #[derive(Debug)]
 pub enum FromDb {
     Datapack(Vec<Data<Record<Update>>>),
     Data(Data<Record<Update>>),
+    Annotation(Annotation, bool),
     Closed,
 }
 #[derive(Debug)]
@@ -47,17 +64,34 @@ pub enum FromConn {
     Closed,
 }
 
+// Reloaded files of users and wplaces; None if the file is removed
+#[derive(Debug)]
+pub enum FromWatch {
+    Wplace(NameWplace, Option<ConfigWplace>),
+    User(Login, Option<(Vec<NameWplace>, Option<Role>)>),
+}
+
 #[derive(Debug)]
 pub enum FromSession {
     SessionHeartbeat,
 }
+// Units of a wplace by group, as they are given to clients
+pub type UnitsWplace = HashMap<Group, Vec<Unit>>;
+
 #[derive(Debug)]
 pub enum FromServer {
-    WplaceGet{login: Login, token: Token, tx: SenderOne<Result<HashMap<Group, Vec<Unit>>, ()>>},
+    WplaceGet{login: Login, token: Token, action: Action, tx: SenderOne<Result<HashMap<Group, Vec<Unit>>, ()>>},
+    WplaceInfo{login: Login, token: Token, tx: SenderOne<Result<HashMap<Group, InfoGroup>, ()>>},
     UnitCheck{login: Login, token: Token, group: Group, unit: Unit, tx: SenderOne<Result<(Group, Unit), ()>>},
-    SessionCheck{login: Login, token: Token, tx: SenderOne<Result<(), ()>>},
-    SessionMake{login: Login, wplace: Option<Wplace>, tx: SenderOne<Result<(Login, Token), Login>>},
+    GroupCheck{login: Login, token: Token, group: Group, tx: SenderOne<Result<(), ()>>},
+    SessionCheck{login: Login, token: Token, action: Action, tx: SenderOne<Result<(), ()>>},
+    SessionMake{login: Login, wplace: NameWplace, parts: Option<Vec<(NameWplace, ConfigWplace)>>, role: Option<Role>, tx: SenderOne<Result<(Login, Token), Login>>},
+    SessionWplace{login: Login, token: Token, wplace: NameWplace, parts: Option<Vec<(NameWplace, ConfigWplace)>>, tx: SenderOne<Result<Option<UnitsWplace>, ()>>},
     WsAdd{login: Login, token: Token, ws: WebSocket, tx: SenderOne<Result<(), WebSocket>>},
+    SessionClose{login: Login, token: Token, tx: SenderOne<Result<(), ()>>},
+    SessionRevoke{login: Login, tx: SenderOne<usize>},
+    LoginCheck{login: Login, ip: Option<IpAddr>, tx: SenderOne<Result<(), ()>>},
+    LoginResult{login: Login, ip: Option<IpAddr>, is_valid: bool},
 }
 pub enum FromAuth {
     Success{token: String, pubs: Vec<(String, Vec<String>)>},
@@ -73,18 +107,23 @@ pub struct Comm {
     map_group: HashMap<Group, HashMap<Unit, HashSet<NameWplace>>>,
     map_wplace: HashMap<NameWplace, Wplace>,
     map_user: HashMap<Login, User>,
-    dur_sess: Duration,
+    info: HashMap<Group, InfoGroup>,
+    policy: Policy,
+    store: Option<Store>,
+    count_saved: usize,
+    guard: Guard,
 }
 
 impl Comm {
     
-    pub fn new(rx: Receiver<Signal>, tx: Sender<Signal>, tx_db: Sender<SignalDb>,  dur_sess: Duration) -> Self {
+    pub fn new(rx: Receiver<Signal>, tx: Sender<Signal>, tx_db: Sender<SignalDb>, info: HashMap<Group, InfoGroup>, policy: Policy, store: Option<Store>, guard: Guard) -> Self {
         Self {
-            rx, tx, tx_db,
+            rx, tx, tx_db, info, guard,
             map_user: HashMap::new(),
             map_group: HashMap::new(),
             map_wplace: HashMap::new(),
-            dur_sess,
+            policy, store,
+            count_saved: 0,
         }
     }
 
@@ -101,33 +140,60 @@ impl Comm {
 
     pub async fn serve(&mut self) {
         // println!("[COMM]: serve started");
+        self.sess_restore().await;
         while let Some(signal) = self.rx.recv().await {
             // println!("[COMM]: signal {:?}", signal);
             match signal {
                 Signal::FromDb(cmd) => self.serve_db(cmd).await,
                 Signal::FromDist(cmd) => todo!(), // TODO: remove this enum variant
                 Signal::FromSession(login, token, cmd) => match cmd {
-                    FromSession::SessionHeartbeat => self.serve_sess_heartbeat(login, token),
+                    FromSession::SessionHeartbeat => self.serve_sess_heartbeat(login, token).await,
                 },
                 Signal::FromConn(login, token, id, cmd) => match cmd {
                     FromConn::Closed => self.serve_conn_closed(login, token, id).await,
                 },
+                Signal::FromWatch(cmd) => self.serve_watch(cmd).await,
+                Signal::SessionsSave => self.sess_save().await,
                 Signal::FromServer(cmd) => match cmd {
-                    FromServer::SessionCheck { login, token, tx } => { 
-                        let _ = tx.send( self.serve_http_sess_check(login, token) ); 
+                    FromServer::SessionCheck { login, token, action, tx } => { 
+                        let _ = tx.send( self.serve_http_sess_check(login, token, action) ); 
                     },
-                    FromServer::SessionMake { login, wplace, tx } => {
-                        let _ = tx.send( self.serve_http_sess_make(login, wplace) );
+                    FromServer::SessionMake { login, wplace, parts, role, tx } => {
+                        let _ = tx.send( self.serve_http_sess_make(login, wplace, parts, role).await );
+                    },
+                    FromServer::SessionWplace { login, token, wplace, parts, tx } => {
+                        let _ = tx.send( self.serve_http_sess_wplace(login, token, wplace, parts).await );
                     },
                     FromServer::WsAdd { login, token, ws, tx } => {
                         let _ = tx.send( self.serve_http_ws_add(login, token, ws) );
                     },
-                    FromServer::WplaceGet { login, token, tx } => {
-                        let _ = tx.send( self.serve_http_wplace_get(login, token) );
+                    FromServer::WplaceGet { login, token, action, tx } => {
+                        let _ = tx.send( self.serve_http_wplace_get(login, token, action) );
+                    },
+                    FromServer::WplaceInfo { login, token, tx } => {
+                        let _ = tx.send( self.serve_http_wplace_info(login, token) );
                     },
                     FromServer::UnitCheck { login, token, group, unit, tx } => {
                         let _ = tx.send( self.serve_http_unit_check(login, token, group, unit) );
                     },
+                    FromServer::GroupCheck { login, token, group, tx } => {
+                        let _ = tx.send( self.serve_http_group_check(login, token, group) );
+                    },
+                    FromServer::SessionClose { login, token, tx } => {
+                        let _ = tx.send( self.serve_http_sess_close(login, token).await );
+                    },
+                    FromServer::SessionRevoke { login, tx } => {
+                        let _ = tx.send( self.serve_http_sess_revoke(login).await );
+                    },
+                    FromServer::LoginCheck { login, ip, tx } => {
+                        let _ = tx.send( if self.guard.check(&login, ip).is_some() { Err(()) } else { Ok(()) } );
+                    },
+                    FromServer::LoginResult { login, ip, is_valid } => {
+                        // a failed attempt is already counted by LoginCheck
+                        if is_valid {
+                            self.guard.succeed(&login, ip);
+                        }
+                    },
                 },
             }
         }
@@ -143,10 +209,35 @@ impl Comm {
                     self.serve_db_data(data).await;
                 }
             },
+            FromDb::Annotation(annotation, is_removed) => self.serve_db_annotation(annotation, is_removed).await,
             FromDb::Closed => self.close(),
         }
     }
 
+    // Group-level annotations go to every workplace with any unit of the group
+    async fn serve_db_annotation(&mut self, annotation: Annotation, is_removed: bool) {
+        let mut set_name: HashSet<NameWplace> = HashSet::new();
+        if let Some(map_unit) = self.map_group.get(&annotation.group) {
+            match annotation.unit.as_ref() {
+                Some(unit) => if let Some(set_unit_wplace) = map_unit.get(unit) {
+                    set_name.extend(set_unit_wplace.iter().cloned());
+                },
+                None => for set_unit_wplace in map_unit.values() {
+                    set_name.extend(set_unit_wplace.iter().cloned());
+                },
+            }
+        }
+        for name in set_name.iter() {
+            if let Some(wplace) = self.map_wplace.get(name) {
+                for login in wplace.iter_login() {
+                    if let Some(user) = self.map_user.get_mut(login) {
+                        user.send_annotation(name, annotation.clone(), is_removed).await;
+                    }
+                }
+            }
+        }
+    }
+
     async fn serve_db_data(&mut self, data: Data<Record<Update>>) {
         match data {
             Data::Single { group, unit, update } => self.serve_data_single(group, unit, update).await,
@@ -164,50 +255,47 @@ impl Comm {
         }
     }
 
-    fn serve_http_wplace_get(&mut self, login: Login, token: Token) -> Result<HashMap<Group, Vec<Unit>>, ()> {
-        if let Some(user) = self.map_user.get_mut(&login) {
-            if user.sess_check(&token) {
-                return if let Some(wplace) = self.map_wplace.get(user.get_name_wplace()) {
-                    let mut map = HashMap::with_capacity(wplace.len_pubtop());
-                    for (group, vec_unit) in wplace.iter_pubtop() {
-                        let mut vec = Vec::with_capacity(vec_unit.len());
-                        for unit in vec_unit {
-                            vec.push(unit.clone())
-                        }
-                        map.insert(group.clone(), vec);
-                    }
-                    Ok(map)
-                } else {
-                    println!("[COMM]: serve_http_wplace_get: expected wplace not found"); // TODO: log this
-                    Err(())
-                };
-            }
+    // The wplace is given for the action, so writes check the role along with the units
+    fn serve_http_wplace_get(&mut self, login: Login, token: Token, action: Action) -> Result<HashMap<Group, Vec<Unit>>, ()> {
+        match self.sess_get(&login, &token) {
+            Some((user, wplace)) if user.permits(wplace.get_role(), action) => Ok(wplace.map_pubtop()),
+            _ => Err(()),
         }
-        Err(())
+    }
+
+    fn serve_http_wplace_info(&mut self, login: Login, token: Token) -> Result<HashMap<Group, InfoGroup>, ()> {
+        let name_wplace = match self.sess_get(&login, &token) {
+            Some((user, wplace)) if user.permits(wplace.get_role(), Action::View) => wplace.get_name().clone(),
+            _ => return Err(()),
+        };
+        self.map_wplace.get(&name_wplace).map(|wplace| info::filter(&self.info, wplace.iter_pubtop())).ok_or(())
     }
 
     fn serve_http_unit_check(&mut self, login: Login, token: Token, group: Group, unit: Unit,) -> Result<(Group, Unit), ()> {
-        if let Some(user) = self.map_user.get_mut(&login) {
-            if user.sess_check(&token) {
-                return if let Some(wplace) = self.map_wplace.get(user.get_name_wplace()) {
-                    if wplace.check_unit(&group, &unit) {
-                        Ok((group, unit))
-                    } else {
-                        Err(())
-                    }
-                } else {
-                    println!("[COMM]: serve_http_wplace_get: expected wplace not found"); // TODO: log this
-                    Err(())
-                };
-            }
+        match self.sess_get(&login, &token) {
+            Some((user, wplace)) if wplace.check_unit(&group, &unit) && user.permits(wplace.get_role(), Action::View) => Ok((group, unit)),
+            _ => Err(()),
+        }
+    }
+
+    // Changes to a whole group are for admins or for those who see every configured unit of it
+    fn serve_http_group_check(&mut self, login: Login, token: Token, group: Group) -> Result<(), ()> {
+        let vec_unit: Vec<Unit> = self.info.get(&group).map(|info| info.units.keys().cloned().collect()).unwrap_or_default();
+        match self.sess_get(&login, &token) {
+            Some((user, wplace)) if user.permits(wplace.get_role(), Action::Admin) => Ok(()),
+            Some((user, wplace)) if user.permits(wplace.get_role(), Action::Write) && !vec_unit.is_empty() && wplace.check_units(&group, vec_unit.iter()) => Ok(()),
+            _ => Err(()),
         }
-        Err(())
     }
 
     fn serve_http_ws_add(&mut self, login: Login, token: Token, ws: WebSocket) -> Result<(), WebSocket> {
         if let Some(user) = self.map_user.get_mut(&login) {
-            if let Some(wplace) = self.map_wplace.get(user.get_name_wplace()) {
-                return if let Err(ws) = user.conn_add(&token, wplace, ws, self.tx_db.clone()) {
+            if let Some(wplace) = user.sess_wplace(&token).and_then(|name| self.map_wplace.get(name)) {
+                if !user.permits(wplace.get_role(), Action::View) {
+                    return Err(ws);
+                }
+                let info = info::filter(&self.info, wplace.iter_pubtop());
+                return if let Err(ws) = user.conn_add(&token, wplace, info, ws, self.tx_db.clone()) {
                     Err(ws)
                 } else {
                     Ok(())
@@ -217,65 +305,386 @@ impl Comm {
         return Err(ws)
     }
 
+    // User and wplace of a valid session
+    fn sess_get(&mut self, login: &Login, token: &Token) -> Option<(&mut User, &Wplace)> {
+        let user = self.map_user.get_mut(login)?;
+        if !user.sess_check(token) {
+            return None;
+        }
+        match user.sess_wplace(token).and_then(|name| self.map_wplace.get(name)) {
+            Some(wplace) => Some((user, wplace)),
+            None => {
+                println!("[COMM]: sess_get: expected wplace not found"); // TODO: log this
+                None
+            },
+        }
+    }
+
 
-    fn serve_http_sess_make(&mut self, login: Login, wplace_opt: Option<Wplace>) -> Result<(Login, Token), Login> {
-        if let Some(user) = self.map_user.get_mut(&login) {
-            Ok((login, user.sess_make()))
-        } else if let Some(mut wplace_new) = wplace_opt {
-            let mut user: User = User::new(login.clone(), wplace_new.get_name().clone(), self.dur_sess, self.tx.clone());
-            let token = user.sess_make();
+    // The role is of the user config at login, so its change applies to all sessions of the user;
+    // the parts of the wplace are needed only if it is not loaded yet
+    async fn serve_http_sess_make(&mut self, login: Login, name_wplace: NameWplace, parts: Option<Vec<(NameWplace, ConfigWplace)>>, role: Option<Role>) -> Result<(Login, Token), Login> {
+        if !self.map_wplace.contains_key(&name_wplace) {
+            match parts {
+                Some(parts) => self.wplace_insert(name_wplace.clone(), parts),
+                None => return Err(login),
+            }
+        }
+        if let Some(wplace) = self.map_wplace.get_mut(&name_wplace) {
+            wplace.add_login(login.clone());
+        }
+        if !self.map_user.contains_key(&login) {
+            self.map_user.insert(login.clone(), User::new(login.clone(), role, self.policy, self.tx.clone()));
+        }
+        let token = match self.map_user.get_mut(&login) {
+            Some(user) => {
+                user.set_role(role);
+                user.sess_make(name_wplace).await
+            },
+            None => return Err(login),
+        };
+        // the evicted sessions may leave their wplaces
+        self.user_sync(&login);
+        Ok((login, token))
+    }
 
-            let wplace = if let Some(wplace) = self.map_wplace.get_mut(wplace_new.get_name()) {
-                wplace
+    // Ok(None) if the wplace is not loaded and its parts are needed
+    async fn serve_http_sess_wplace(&mut self, login: Login, token: Token, name_wplace: NameWplace, parts: Option<Vec<(NameWplace, ConfigWplace)>>) -> Result<Option<UnitsWplace>, ()> {
+        if self.sess_get(&login, &token).is_none() {
+            return Err(());
+        }
+        if !self.map_wplace.contains_key(&name_wplace) {
+            match parts {
+                Some(parts) => self.wplace_insert(name_wplace.clone(), parts),
+                None => return Ok(None),
+            }
+        }
+        let (user, wplace) = match (self.map_user.get_mut(&login), self.map_wplace.get_mut(&name_wplace)) {
+            (Some(user), Some(wplace)) if user.permits(wplace.get_role(), Action::View) => (user, wplace),
+            _ => {
+                self.user_sync(&login);
+                return Err(());
+            },
+        };
+        wplace.add_login(login.clone());
+        let info = info::filter(&self.info, wplace.iter_pubtop());
+        user.sess_switch(&token, wplace, info).await;
+        let map = wplace.map_pubtop();
+        self.user_sync(&login);
+        println!("[INFO] session of \"{}\" switched to wplace \"{}\"", login.as_str(), name_wplace.as_str()); // TODO: log this
+        Ok(Some(map))
+    }
+
+    // Parts are united into one wplace under the name; a single part is the wplace itself
+    fn wplace_insert(&mut self, name: NameWplace, parts: Vec<(NameWplace, ConfigWplace)>) {
+        let vec_wplace: Vec<Wplace> = parts.into_iter()
+            .map(|(name_part, cfg_wplace)| Wplace::from_config(name_part, cfg_wplace, &self.info))
+            .collect();
+        self.map_wplace.insert(name.clone(), Wplace::union(name.clone(), vec_wplace));
+        self.wplace_index(&name);
+    }
+
+    // Units of the wplace are routed to its users
+    fn wplace_index(&mut self, name: &NameWplace) {
+        let wplace = match self.map_wplace.get(name) {
+            Some(wplace) => wplace,
+            None => return,
+        };
+        for (group, vec_unit) in wplace.iter_pubtop() {
+            // let group = self.map_group.entry(group.clone()).or_insert_with(|| HashMap::new());
+            let group_map_unit = if let Some(group_map_unit) = self.map_group.get_mut(group) {
+                group_map_unit
             } else {
                 // TODO: refactor when this will be stable: https://doc.rust-lang.org/std/collections/struct.HashMap.html#method.raw_entry_mut
-                let name = wplace_new.get_name().clone();
-                self.map_wplace.insert(wplace_new.get_name().clone(), wplace_new);
-                self.map_wplace.get_mut(&name).unwrap()
+                let group_map_unit = HashMap::new();
+                self.map_group.insert(group.clone(), group_map_unit);
+                self.map_group.get_mut(group).unwrap()
             };
-            wplace.add_login(login.clone());
-            for (group, vec_unit) in wplace.iter_pubtop() {
-                // let group = self.map_group.entry(group.clone()).or_insert_with(|| HashMap::new());
-                let group_map_unit = if let Some(group_map_unit) = self.map_group.get_mut(group) {
-                    group_map_unit
+            for unit in vec_unit {
+                if let Some(unit) = group_map_unit.get_mut(unit) {
+                    unit.insert(wplace.get_name().clone());
                 } else {
-                    // TODO: refactor when this will be stable: https://doc.rust-lang.org/std/collections/struct.HashMap.html#method.raw_entry_mut
-                    let group_map_unit = HashMap::new();
-                    self.map_group.insert(group.clone(), group_map_unit);
-                    self.map_group.get_mut(group).unwrap()
-                };
-                for unit in vec_unit {
-                    if let Some(unit) = group_map_unit.get_mut(unit) {
-                        unit.insert(wplace.get_name().clone());
-                    } else {
-                        let mut set = HashSet::new();
-                        set.insert(wplace.get_name().clone());
-                        group_map_unit.insert(unit.clone(), set);
-                    }
+                    let mut set = HashSet::new();
+                    set.insert(wplace.get_name().clone());
+                    group_map_unit.insert(unit.clone(), set);
                 }
             }
-            self.map_user.insert(login.clone(), user);
-            Ok((login, token))
-        } else {
-            Err(login)
         }
     }
 
 
-    fn serve_http_sess_check(&mut self, login: Login, token: Token) -> Result<(), ()> {
-        if let Some(user) = self.map_user.get_mut(&login) {
-            if user.sess_check(&token) {
-                return Ok(());
+    // Sessions are restored with their users and wplaces as the files give them now, as at login;
+    // the expired ones and those whose user or wplace is removed are dropped
+    async fn sess_restore(&mut self) {
+        let store = match self.store.take() {
+            Some(store) => store,
+            None => return,
+        };
+        let vec_record = match store.load().await {
+            Ok(vec_record) => vec_record,
+            Err(err) => {
+                println!("[ERR] sessions file {} is not readable: {}", store.path().display(), err); // TODO: log this
+                Vec::new()
+            },
+        };
+        let interval = store.interval();
+        let time_now = chrono::offset::Utc::now().timestamp_millis();
+        let mut count_restored: usize = 0;
+        let mut count_dropped: usize = 0;
+        // the file is rewritten with the next save, also when nothing is restored
+        self.count_saved = vec_record.len();
+        for record in vec_record {
+            let inactive = Duration::from_millis(time_now.saturating_sub(record.time_active).max(0) as u64);
+            // files of older versions have no login time: the session is taken as made at its last use
+            let age = Duration::from_millis(time_now.saturating_sub(record.time_created.unwrap_or(record.time_active)).max(0) as u64);
+            let token = match Token::from_str(&record.token) {
+                Ok(token) => token,
+                Err(_) => continue,
+            };
+            if inactive >= self.policy.idle || self.policy.lifetime.is_some_and(|lifetime| age >= lifetime) {
+                continue;
+            }
+            let login = match Login::from_str(&record.login) {
+                Ok(login) => login,
+                Err(_) => continue,
+            };
+            let wplaces = if record.wplaces.is_empty() { vec![record.wplace] } else { record.wplaces };
+            let cfg_user = match store.user_read(&login).await {
+                Some(cfg_user) if wplaces.iter().all(|wplace| NameWplace::is_file(wplace) && cfg_user.iter_wplace().any(|allowed| allowed == wplace)) => cfg_user,
+                _ => {
+                    count_dropped += 1;
+                    continue;
+                },
+            };
+            let name_wplace = NameWplace::union(&wplaces);
+            if !self.map_wplace.contains_key(&name_wplace) {
+                let mut parts = Vec::with_capacity(wplaces.len());
+                for wplace in wplaces {
+                    if let Some(cfg_wplace) = store.wplace_read(&wplace).await {
+                        parts.push((NameWplace::new(wplace), cfg_wplace));
+                    }
+                }
+                if parts.len() < parts.capacity() {
+                    count_dropped += 1;
+                    continue;
+                }
+                self.wplace_insert(name_wplace.clone(), parts);
+            }
+            if let Some(wplace) = self.map_wplace.get_mut(&name_wplace) {
+                wplace.add_login(login.clone());
+            }
+            if !self.map_user.contains_key(&login) {
+                self.map_user.insert(login.clone(), User::new(login.clone(), cfg_user.role, self.policy, self.tx.clone()));
+            }
+            if let Some(user) = self.map_user.get_mut(&login) {
+                user.set_role(cfg_user.role);
+                user.sess_restore(token, name_wplace, age, inactive);
+            }
+            count_restored += 1;
+        }
+        self.store = Some(store);
+        if count_dropped > 0 {
+            println!("[INFO] {} sessions dropped: their users or wplaces are removed", count_dropped); // TODO: log this
+        }
+        if count_restored > 0 {
+            println!("[INFO] {} sessions restored", count_restored); // TODO: log this
+        }
+        let tx = self.tx.clone();
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            ticker.tick().await;
+            loop {
+                ticker.tick().await;
+                if tx.send(Signal::SessionsSave).await.is_err() {
+                    break;
+                }
             }
+        });
+    }
+
+    async fn sess_save(&mut self) {
+        let store = match self.store.as_ref() {
+            Some(store) => store,
+            None => return,
+        };
+        let time_now = chrono::offset::Utc::now().timestamp_millis();
+        let mut vec_record: Vec<RecordSession> = Vec::new();
+        for (login, user) in self.map_user.iter() {
+            for (token, name_wplace, age, inactive) in user.iter_sess_inactive() {
+                let wplace = match self.map_wplace.get(name_wplace) {
+                    Some(wplace) => wplace,
+                    None => continue,
+                };
+                let mut wplaces: Vec<String> = wplace.iter_part().map(|name| name.as_str().to_string()).collect();
+                wplaces.sort_unstable();
+                vec_record.push(RecordSession {
+                    login: login.to_string(),
+                    token: token.to_string(),
+                    wplace: wplace.get_name().as_str().to_string(),
+                    wplaces,
+                    time_active: time_now - inactive.as_millis() as i64,
+                    time_created: Some(time_now - age.as_millis() as i64),
+                });
+            }
+        }
+        // nothing changed since the file became empty
+        if vec_record.is_empty() && self.count_saved == 0 {
+            return;
+        }
+        match store.save(&vec_record).await {
+            Ok(()) => self.count_saved = vec_record.len(),
+            Err(err) => println!("[ERR] sessions file {} is not written: {}", store.path().display(), err), // TODO: log this
+        }
+    }
+
+    fn serve_http_sess_check(&mut self, login: Login, token: Token, action: Action) -> Result<(), ()> {
+        match self.sess_get(&login, &token) {
+            Some((user, wplace)) if user.permits(wplace.get_role(), action) => Ok(()),
+            _ => Err(()),
+        }
+    }
+
+    // Closed sessions are saved at once, otherwise a restart would bring them back
+    async fn serve_http_sess_close(&mut self, login: Login, token: Token) -> Result<(), ()> {
+        let is_closed = match self.map_user.get_mut(&login) {
+            Some(user) => user.sess_close(&token, FRAME_LOGOUT).await,
+            None => false,
+        };
+        if !is_closed {
+            return Err(());
+        }
+        self.user_sync(&login);
+        self.sess_save().await;
+        Ok(())
+    }
+
+    async fn serve_http_sess_revoke(&mut self, login: Login) -> usize {
+        let count = self.user_revoke(&login).await;
+        if count > 0 {
+            println!("[INFO] {} sessions of \"{}\" revoked", count, login.as_str()); // TODO: log this
+            self.sess_save().await;
+        }
+        count
+    }
+
+    async fn user_revoke(&mut self, login: &Login) -> usize {
+        let count = match self.map_user.get_mut(login) {
+            Some(user) => user.sess_close_all(FRAME_REVOKED).await,
+            None => 0,
+        };
+        self.user_sync(login);
+        count
+    }
+
+    // Live sessions follow the files: a wplace change is pushed to connections of every wplace having it
+    // as a part; sessions in a removed wplace or in one the user may not select anymore are closed
+    async fn serve_watch(&mut self, cmd: FromWatch) {
+        match cmd {
+            FromWatch::Wplace(name, Some(cfg_wplace)) => {
+                let vec_name: Vec<NameWplace> = self.map_wplace.values().filter(|wplace| wplace.has_part(&name)).map(|wplace| wplace.get_name().clone()).collect();
+                if vec_name.is_empty() {
+                    return;
+                }
+                let part = Wplace::from_config(name.clone(), cfg_wplace, &self.info);
+                for name_wplace in vec_name.iter() {
+                    let pubtop_old = match self.map_wplace.get_mut(name_wplace) {
+                        Some(wplace) => wplace.update(&part),
+                        None => continue,
+                    };
+                    self.clear_wplace_pubtop(name_wplace, pubtop_old.iter());
+                    self.wplace_index(name_wplace);
+                    if let Some(wplace) = self.map_wplace.get(name_wplace) {
+                        let info = info::filter(&self.info, wplace.iter_pubtop());
+                        for login in wplace.iter_login() {
+                            if let Some(user) = self.map_user.get_mut(login) {
+                                user.send_scope(wplace, info.clone()).await;
+                            }
+                        }
+                    }
+                }
+                println!("[INFO] wplace \"{}\" reloaded", name.as_str()); // TODO: log this
+            },
+            FromWatch::Wplace(name, None) => {
+                let set_name: HashSet<NameWplace> = self.map_wplace.values().filter(|wplace| wplace.has_part(&name)).map(|wplace| wplace.get_name().clone()).collect();
+                if set_name.is_empty() {
+                    return;
+                }
+                let mut count = 0;
+                for login in self.set_login_wplace(&set_name) {
+                    if let Some(user) = self.map_user.get_mut(&login) {
+                        count += user.sess_close_wplace(&set_name, FRAME_REVOKED).await;
+                    }
+                    self.user_sync(&login);
+                }
+                println!("[INFO] wplace \"{}\" removed: {} sessions closed", name.as_str(), count); // TODO: log this
+                self.sess_save().await;
+            },
+            FromWatch::User(login, Some((vec_allowed, role))) => {
+                let user = match self.map_user.get_mut(&login) {
+                    Some(user) => user,
+                    None => return,
+                };
+                user.set_role(role);
+                let set_name: HashSet<NameWplace> = user.iter_wplace()
+                    .filter(|name| self.map_wplace.get(*name).is_none_or(|wplace| wplace.iter_part().any(|part| !vec_allowed.contains(part))))
+                    .cloned()
+                    .collect();
+                if set_name.is_empty() {
+                    return;
+                }
+                let count = user.sess_close_wplace(&set_name, FRAME_REVOKED).await;
+                self.user_sync(&login);
+                println!("[INFO] wplaces of user \"{}\" changed: {} sessions closed", login.as_str(), count); // TODO: log this
+                self.sess_save().await;
+            },
+            FromWatch::User(login, None) => {
+                let count = self.user_revoke(&login).await;
+                if count > 0 {
+                    println!("[INFO] user \"{}\" removed: {} sessions closed", login.as_str(), count); // TODO: log this
+                    self.sess_save().await;
+                }
+            },
         }
-        Err(())
     }
 
+    fn set_login_wplace(&self, set_name: &HashSet<NameWplace>) -> HashSet<Login> {
+        set_name.iter()
+            .filter_map(|name| self.map_wplace.get(name))
+            .flat_map(|wplace| wplace.iter_login().cloned())
+            .collect()
+    }
+
+    // A login stays only in the wplaces of its sessions and a user only with sessions;
+    // wplaces left without logins are dropped along with their routes
+    fn user_sync(&mut self, login: &Login) {
+        let set_used: HashSet<NameWplace> = match self.map_user.get(login) {
+            Some(user) if !user.is_empty() => user.iter_wplace().cloned().collect(),
+            Some(_) => {
+                self.map_user.remove(login);
+                HashSet::new()
+            },
+            None => HashSet::new(),
+        };
+        let vec_unused: Vec<NameWplace> = self.map_wplace.iter()
+            .filter(|(name, wplace)| !set_used.contains(*name) && wplace.has_login(login))
+            .map(|(name, _)| name.clone())
+            .collect();
+        for name in vec_unused {
+            if let Some(wplace) = self.map_wplace.get_mut(&name) {
+                wplace.remove_login(login);
+                if wplace.is_empty() {
+                    if let Some(wplace) = self.map_wplace.remove(&name) {
+                        self.clear_wplace_pubtop(wplace.get_name(), wplace.iter_pubtop());
+                    }
+                }
+            }
+        }
+    }
 
-    fn serve_sess_heartbeat(&mut self, login: Login, token: Token) {
+    async fn serve_sess_heartbeat(&mut self, login: Login, token: Token) {
         if let Some(user) = self.map_user.get_mut(&login) {
-            if let Err(_) = user.sess_heartbeat(&token) {
-                self.remove_user(&login);
+            if let Err(_) = user.sess_heartbeat(&token).await {
+                self.user_sync(&login);
             }
         }
     }
@@ -291,7 +700,7 @@ impl Comm {
                         let mut login_err_opt: Option<Vec<Login>> = None;
                         for login in wplace.iter_login() {
                             if let Some(user) = self.map_user.get_mut(login) {
-                                user.send_data(Data::Single { group: group.clone(), unit: unit.clone(), update: update.clone() }).await;
+                                user.send_data(name, Data::Single { group: group.clone(), unit: unit.clone(), update: update.clone() }).await;
                             } else {
                                 login_err_opt.get_or_insert_with(|| Vec::with_capacity(wplace.len_login())).push(login.clone());
                             }
@@ -368,7 +777,7 @@ impl Comm {
                         if let Some(user) = self.map_user.get_mut(login) {
                             let mut vec_data = Vec::with_capacity(1);
                             vec_data.push((group.clone(), vec_update.clone()));
-                            user.send_data(Data::Multi { vec: vec_data }).await;
+                            user.send_data(name_wplace, Data::Multi { vec: vec_data }).await;
                         } else {
                             login_err_opt.get_or_insert_with(|| Vec::with_capacity(wplace.len_login())).push(login.clone());
                         }
@@ -395,19 +804,6 @@ impl Comm {
     }
 
 
-    fn remove_user(&mut self, login: &Login) {
-        if let Some(user) = self.map_user.remove(login) {
-            if let Some(wplace) = self.map_wplace.get_mut(user.get_name_wplace()) {
-                wplace.remove_login(&login);
-                if wplace.is_empty() {
-                    if let Some(wplace) = self.map_wplace.remove(user.get_name_wplace()){
-                        self.clear_wplace_pubtop(wplace.get_name(), wplace.iter_pubtop());
-                    }
-                }
-            }
-        }
-    }
-
     fn clear_wplace_pubtop(&mut self, name_wplace: &NameWplace, iter_pubtop: IterMap<Group, HashSet<Unit>>) {
         for (group, vec_unit) in iter_pubtop {
             let mut to_delete = false;
@@ -428,4 +824,5 @@ impl Comm {
         }
     }
 
-}
\ No newline at end of file
+}
+
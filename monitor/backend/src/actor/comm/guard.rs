@@ -0,0 +1,168 @@
+use std::{
+    collections::HashMap,
+    hash::Hash,
+    net::IpAddr,
+};
+
+use tokio::time::{Duration, Instant};
+
+use crate::config::ConfigServeLogin;
+use crate::model::user::Login;
+
+
+// Keys of any login name can be tried, so each counter keeps at most this many, the oldest are forgotten first
+const KEYS_MAX: usize = 10_000;
+
+
+// Failed logins of one key: a login or a client address
+struct Entry {
+    count: u32,
+    lockouts: u32,
+    time_failed: Instant,
+    time_unlock: Option<Instant>,
+}
+impl Entry {
+    // Quiet time is counted from the last failure or the end of the lockout, whichever is later
+    fn is_stale(&self, now: Instant, reset: Duration) -> bool {
+        let time_last = self.time_unlock.map_or(self.time_failed, |time_unlock| time_unlock.max(self.time_failed));
+        now.saturating_duration_since(time_last) >= reset
+    }
+}
+
+
+struct Counter<K> {
+    attempts_max: u32,
+    map: HashMap<K, Entry>,
+}
+impl<K: Hash + Eq + Clone> Counter<K> {
+    fn new(attempts_max: u32) -> Self {
+        Self { attempts_max, map: HashMap::new() }
+    }
+
+    // Time left of the lockout of the key
+    fn locked(&self, key: &K, now: Instant) -> Option<Duration> {
+        let time_unlock = self.map.get(key)?.time_unlock?;
+        Some(time_unlock.saturating_duration_since(now)).filter(|dur| !dur.is_zero())
+    }
+
+    // The attempt is counted as failed until its result is known, so parallel attempts are counted too;
+    // an attempt beyond attempts_max is not let through but triggers the lockout, its number and duration
+    fn attempt(&mut self, key: K, now: Instant, policy: &Policy) -> Result<(), (u32, Duration)> {
+        if self.attempts_max == 0 {
+            return Ok(());
+        }
+        if self.map.len() >= KEYS_MAX && !self.map.contains_key(&key) {
+            self.prune(now, policy.reset);
+            self.evict_oldest();
+        }
+        let entry = self.map.entry(key).or_insert_with(|| Entry{ count: 0, lockouts: 0, time_failed: now, time_unlock: None });
+        if entry.is_stale(now, policy.reset) {
+            entry.count = 0;
+            entry.lockouts = 0;
+        }
+        entry.time_failed = now;
+        if entry.count < self.attempts_max {
+            entry.count += 1;
+            return Ok(());
+        }
+        let dur = policy.lock.saturating_mul(2u32.saturating_pow(entry.lockouts)).min(policy.lock_max);
+        entry.count = 0;
+        entry.lockouts += 1;
+        entry.time_unlock = Some(now + dur);
+        Err((entry.lockouts, dur))
+    }
+
+    fn reset(&mut self, key: &K) {
+        self.map.remove(key);
+    }
+
+    // The attempt turned out to be valid
+    fn release(&mut self, key: &K) {
+        if let Some(entry) = self.map.get_mut(key) {
+            entry.count = entry.count.saturating_sub(1);
+        }
+    }
+
+    fn evict_oldest(&mut self) {
+        let key_oldest = self.map.iter()
+            .filter(|(_, entry)| entry.time_unlock.is_none())
+            .min_by_key(|(_, entry)| entry.time_failed)
+            .or_else(|| self.map.iter().min_by_key(|(_, entry)| entry.time_failed))
+            .map(|(key, _)| key.clone());
+        if let Some(key) = key_oldest {
+            self.map.remove(&key);
+        }
+    }
+
+    fn prune(&mut self, now: Instant, reset: Duration) {
+        self.map.retain(|_, entry| !entry.is_stale(now, reset));
+    }
+}
+
+
+struct Policy {
+    lock: Duration,
+    lock_max: Duration,
+    reset: Duration,
+}
+
+
+// Login attempts are rejected while the login or the client address is locked
+pub struct Guard {
+    policy: Policy,
+    by_login: Counter<Login>,
+    by_ip: Counter<IpAddr>,
+    time_pruned: Instant,
+}
+impl Guard {
+    pub fn new(cfg: &ConfigServeLogin) -> Self {
+        let lock = Duration::from_secs(cfg.lock_sec.max(1));
+        Self {
+            policy: Policy {
+                lock,
+                lock_max: Duration::from_secs(cfg.lock_max_sec).max(lock),
+                reset: Duration::from_secs(cfg.reset_sec.max(1)),
+            },
+            by_login: Counter::new(cfg.attempts_max),
+            by_ip: Counter::new(cfg.attempts_max_ip),
+            time_pruned: Instant::now(),
+        }
+    }
+
+    // Time left till both the login and the address are unlocked; None if the attempt is let through,
+    // then it is counted as failed unless its result is given to succeed
+    pub fn check(&mut self, login: &Login, ip: Option<IpAddr>) -> Option<Duration> {
+        let now = Instant::now();
+        if now.saturating_duration_since(self.time_pruned) >= self.policy.reset {
+            self.by_login.prune(now, self.policy.reset);
+            self.by_ip.prune(now, self.policy.reset);
+            self.time_pruned = now;
+        }
+        let locked_ip = ip.and_then(|ip| self.by_ip.locked(&ip, now));
+        let locked = self.by_login.locked(login, now).max(locked_ip);
+        if locked.is_some() {
+            return locked;
+        }
+        let addr = ip.map(|ip| ip.to_string()).unwrap_or_else(|| "unknown".to_string());
+        if let Err((lockouts, dur)) = self.by_login.attempt(login.clone(), now, &self.policy) {
+            println!("[AUDIT] login \"{}\" locked for {}s: lockout {} after failed attempts, the last from {}", login.as_str(), dur.as_secs(), lockouts, addr); // TODO: log this
+            return Some(dur);
+        }
+        if let Some(ip) = ip {
+            if let Err((lockouts, dur)) = self.by_ip.attempt(ip, now, &self.policy) {
+                println!("[AUDIT] address {} locked for {}s: lockout {} after failed attempts, the last for \"{}\"", addr, dur.as_secs(), lockouts, login.as_str()); // TODO: log this
+                self.by_login.release(login);
+                return Some(dur);
+            }
+        }
+        None
+    }
+
+    // The address is only released from the attempt, so one known password does not open the way to guess others
+    pub fn succeed(&mut self, login: &Login, ip: Option<IpAddr>) {
+        self.by_login.reset(login);
+        if let Some(ip) = ip {
+            self.by_ip.release(&ip);
+        }
+    }
+}
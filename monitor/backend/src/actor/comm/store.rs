@@ -0,0 +1,83 @@
+use std::{
+    io,
+    path::PathBuf,
+};
+
+use serde::{Deserialize, Serialize};
+use tokio::{
+    fs,
+    io::AsyncWriteExt,
+    time::Duration,
+};
+
+use crate::fs as fs_util;
+use crate::config::{ConfigUser, ConfigWplace};
+use crate::model::user::Login;
+
+
+// One saved session; its user and wplaces are read again from their files at restore
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RecordSession {
+    pub login: String,
+    pub token: String,
+    pub wplace: String,
+    // wplaces selected at login or switch; files of older versions have only the wplace
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub wplaces: Vec<String>,
+    // milliseconds since epoch; sessions with open connections are active at the moment of save
+    pub time_active: i64,
+    // milliseconds since epoch of the login
+    #[serde(default)]
+    pub time_created: Option<i64>,
+}
+
+
+pub struct Store {
+    path: PathBuf,
+    interval: Duration,
+    dir_users: PathBuf,
+    dir_wplaces: PathBuf,
+}
+impl Store {
+    pub fn new(path: PathBuf, interval: Duration, dir_users: PathBuf, dir_wplaces: PathBuf) -> Self {
+        Self { path, interval, dir_users, dir_wplaces }
+    }
+
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    pub async fn load(&self) -> io::Result<Vec<RecordSession>> {
+        match fs::read_to_string(&self.path).await {
+            Ok(text) => serde_json::from_str(&text).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err)),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+            Err(err) => Err(err),
+        }
+    }
+
+    // Tokens are credentials: the file is readable by the owner only and replaced at once
+    pub async fn save(&self, records: &[RecordSession]) -> io::Result<()> {
+        let text = serde_json::to_vec(records).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        let mut path_tmp = self.path.clone().into_os_string();
+        path_tmp.push(".tmp");
+        let mut file = fs::OpenOptions::new().write(true).create(true).truncate(true).mode(0o600).open(&path_tmp).await?;
+        file.write_all(&text).await?;
+        file.sync_all().await?;
+        fs::rename(&path_tmp, &self.path).await
+    }
+
+    pub fn path(&self) -> &PathBuf {
+        &self.path
+    }
+
+    // None if the file is removed or not readable, then the session is not restored
+    pub async fn user_read(&self, login: &Login) -> Option<ConfigUser> {
+        let path_file = fs_util::path_extend(&self.dir_users, format!("{}.json", login.as_str())).ok()?;
+        fs_util::file_deser::<ConfigUser>(path_file).await?.ok()
+    }
+
+    pub async fn wplace_read(&self, name: &str) -> Option<ConfigWplace> {
+        let path_file = fs_util::path_extend(&self.dir_wplaces, format!("{}.json", name)).ok()?;
+        fs_util::file_deser::<ConfigWplace>(path_file).await?.ok()
+    }
+}
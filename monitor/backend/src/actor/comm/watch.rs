@@ -0,0 +1,101 @@
+use std::{
+    collections::HashSet,
+    io,
+    path::{Path, PathBuf},
+};
+
+use notify::{Event, RecursiveMode, Watcher};
+use tokio::{
+    sync::mpsc::{Sender, unbounded_channel},
+    time::{timeout, Duration},
+};
+
+use crate::fs;
+use crate::config::{ConfigUser, ConfigWplace};
+use crate::model::{
+    user::Login,
+    wplace::Name as NameWplace,
+};
+use super::{Signal, FromWatch};
+
+
+// Files are written in several steps: changes are applied once a directory is quiet for this time
+const DUR_QUIET: Duration = Duration::from_millis(300);
+
+
+// Changed files of users and wplaces are sent to Comm; a removed file is sent as None,
+// one not readable for other reasons is skipped and the current state is kept
+pub async fn serve(dir_users: PathBuf, dir_wplaces: PathBuf, tx_comm: Sender<Signal>) {
+    let (tx, mut rx) = unbounded_channel::<PathBuf>();
+    let mut watcher = match notify::recommended_watcher(move |res: notify::Result<Event>| {
+        if let Ok(event) = res {
+            for path in event.paths {
+                let _ = tx.send(path);
+            }
+        }
+    }) {
+        Ok(watcher) => watcher,
+        Err(err) => {
+            println!("[WARN] users and wplaces are not reloaded on change: {}", err); // TODO: log this
+            return;
+        },
+    };
+    for dir in [&dir_users, &dir_wplaces] {
+        if let Err(err) = watcher.watch(dir, RecursiveMode::NonRecursive) {
+            println!("[WARN] {} is not reloaded on change: {}", dir.display(), err); // TODO: log this
+        }
+    }
+    while let Some(path) = rx.recv().await {
+        let mut set_path = HashSet::new();
+        set_path.insert(path);
+        while let Ok(Some(path)) = timeout(DUR_QUIET, rx.recv()).await {
+            set_path.insert(path);
+        }
+        for path in set_path {
+            if let Some(cmd) = reload(path, &dir_users, &dir_wplaces).await {
+                if tx_comm.send(Signal::FromWatch(cmd)).await.is_err() {
+                    return;
+                }
+            }
+        }
+    }
+}
+
+
+async fn reload(path: PathBuf, dir_users: &Path, dir_wplaces: &Path) -> Option<FromWatch> {
+    if path.extension().is_none_or(|ext| ext != "json") {
+        return None;
+    }
+    let name = path.file_stem()?.to_str()?.to_string();
+    let dir = path.parent()?;
+    if dir == dir_users {
+        match fs::file_deser_io::<ConfigUser>(path.clone()).await {
+            Ok(Ok(cfg_user)) => Some(FromWatch::User(Login::new(name), Some((cfg_user.iter_wplace().cloned().map(NameWplace::new).collect(), cfg_user.role)))),
+            Ok(Err(_)) => {
+                println!("[ERR] user {} is not reloaded: wrong format", path.display()); // TODO: log this
+                None
+            },
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Some(FromWatch::User(Login::new(name), None)),
+            Err(err) => {
+                println!("[ERR] user {} is not reloaded: {}", path.display(), err); // TODO: log this
+                None
+            },
+        }
+    } else if dir == dir_wplaces {
+        let name_wplace = NameWplace::new(name);
+        match fs::file_deser_io::<ConfigWplace>(path.clone()).await {
+            Ok(Ok(cfg_wplace)) => Some(FromWatch::Wplace(name_wplace, Some(cfg_wplace))),
+            Ok(Err(_)) => {
+                println!("[ERR] wplace {} is not reloaded: wrong format", path.display()); // TODO: log this
+                None
+            },
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Some(FromWatch::Wplace(name_wplace, None)),
+            Err(err) => {
+                println!("[ERR] wplace {} is not reloaded: {}", path.display(), err); // TODO: log this
+                None
+            },
+        }
+    } else {
+        None
+    }
+}
@@ -26,7 +26,7 @@ use warp::{
 
 use crate::{
     actor::{
-        chan::{channel_sec as channel_sec, RxConn, TxConn, SignalConnOut},
+        chan::{channel_sec as channel_sec, RxConn, TxConn, SignalConnOut, Frame},
         comm::{Signal as SignalComm, FromConn as FromConnComm},
         db::{Signal as SignalDb, FromConn as FromConnDb},
     },
@@ -34,10 +34,19 @@ use crate::{
         session::{Token},
         user::{Login}, 
         dataflow::{Group, Unit, Value, Update, Record},
+        annotation::Annotation,
+        info::InfoGroup,
     }
 };
 
 
+// Like 3000 "Unauthorized", these close codes mean the session is over: clients do not reconnect with it
+pub const FRAME_LOGOUT: Frame = (3001, "Logged out");
+pub const FRAME_REVOKED: Frame = (3002, "Session revoked");
+pub const FRAME_EXPIRED: Frame = (3003, "Session expired");
+pub const FRAME_EVICTED: Frame = (3004, "Session limit exceeded");
+
+
 // INPUT PROTOCOL PART
 #[derive(Deserialize)]
 #[serde(tag = "t")]
@@ -57,6 +66,8 @@ pub enum Output {
     CtrlConnected{
         #[serde(rename = "m")]
         map: HashMap<Group, Vec<(Unit, Option<Record<Update>>)>>,
+        #[serde(rename = "i")]
+        info: HashMap<Group, InfoGroup>,
     },
     #[serde(rename = "p")]
     CtrlPing{
@@ -68,6 +79,18 @@ pub enum Output {
         #[serde(rename = "d")]
         data: Vec<DtoRecord>
     },
+    #[serde(rename = "n")]
+    Annotation{
+        #[serde(rename = "n")]
+        annotations: Vec<DtoAnnotation>
+    },
+}
+#[derive(Serialize, Debug)]
+pub struct DtoAnnotation {
+    #[serde(flatten)]
+    annotation: Annotation,
+    #[serde(rename = "r")]
+    is_removed: bool,
 }
 #[derive(Serialize, Debug)]
 pub struct DtoRecord {
@@ -108,12 +131,20 @@ impl DtoUpdate {
         match update {
             Update::Online => Self::Online,
             Update::Offline => Self::Offline,
-            Update::Value{value} => Self::Value{v: value.into_base64()},
+            Update::Value{value, ..} => Self::Value{v: value.into_base64()},
         }
     }
 }
 
 
+// Units served by a connection and their metadata sent in the CtrlConnected message
+#[derive(Debug, Clone)]
+pub struct Scope {
+    pub map: HashMap<Group, Vec<Unit>>,
+    pub info: HashMap<Group, InfoGroup>,
+}
+
+
 pub struct Conn {
     login: Login,
     token: Token,
@@ -125,7 +156,9 @@ pub struct Conn {
     rx: RxConn,
     tx: TxConn,
     tx_actor: Sender<SignalComm>,
-    config: Option<(SplitStream<WebSocket>, HashMap<Group, Vec<Unit>>, Sender<SignalDb>)>
+    frame: Option<Frame>,
+    tx_db: Sender<SignalDb>,
+    config: Option<(SplitStream<WebSocket>, Scope)>
 }
 
 impl Conn {
@@ -135,14 +168,16 @@ impl Conn {
         id: u64,
         ws: WebSocket,
         tx_actor: Sender<SignalComm>,
-        map: HashMap<Group, Vec<Unit>>, 
+        scope: Scope,
         tx_db: Sender<SignalDb>,
     ) -> Self {
         let (tx, rx) = channel_sec();
         let (writer, reader) = ws.split();
         Self { 
             login, token, id, writer, rx, tx, tx_actor,
-            config: Some((reader, map, tx_db)),
+            config: Some((reader, scope)),
+            tx_db,
+            frame: None,
             ping: 0,
             pong: None,
             ping_duration: Duration::from_secs(5),
@@ -157,6 +192,9 @@ impl Conn {
     async fn destruct(mut self) {
         // println!("[CONN]: destruct");
         let _ = self.tx.send_close().await;
+        if let Some((code, reason)) = self.frame {
+            let _ = timeout(Duration::from_secs(5), self.writer.send(Message::close_with(code, reason))).await;
+        }
         let _ = self.writer.close().await;
         if !self.tx_actor.is_closed() {
             let _ = self.tx_actor.send( SignalComm::FromConn(self.login, self.token, self.id, FromConnComm::Closed)).await;
@@ -178,13 +216,11 @@ impl Conn {
     }
 
     async fn init(&mut self) -> Result<(), ()> {
-        if let Some((reader, map, tx_db)) = self.config.take() {
-            let (tx, rx) = channel_one::<HashMap<Group, Vec<(Unit, Option<Record<Update>>)>>>();
-            tx_db.send(SignalDb::FromConn(FromConnDb::Last { map, tx_resp: tx })).await.map_err(|_| ())?;
-            let map = rx.await.map_err(|_| ())?;
+        if let Some((reader, Scope{ map, info })) = self.config.take() {
+            let map = self.last(map).await?;
             tokio::spawn(loop_reader(reader, self.tx.clone()));
             tokio::spawn(loop_heartbeat(self.tx.clone(), self.ping_duration));
-            let _ = self.send_ws(Output::CtrlConnected{map}).await;
+            let _ = self.send_ws(Output::CtrlConnected{map, info}).await;
             let _ = self.send_ws(Output::CtrlPing{val:self.ping }).await;
             return Ok(());
         }
@@ -200,7 +236,12 @@ impl Conn {
                     SignalConnOut::Pong(val) => self.pong = Some(val),
                     SignalConnOut::Data(group, unit, record) => self.serve_data(group, unit, record).await,
                     SignalConnOut::DataMap(map) => self.serve_data_map(map).await,
-                    SignalConnOut::Close => self.close(),
+                    SignalConnOut::Annotations(vec) => self.serve_annotations(vec).await,
+                    SignalConnOut::Scope(scope) => self.serve_scope(scope).await,
+                    SignalConnOut::Close(frame) => {
+                        self.frame = frame;
+                        self.close();
+                    },
                 }
             }
         }        
@@ -219,6 +260,27 @@ impl Conn {
         self.send_ws(Output::Data{data: vec![DtoRecord::new(group, unit, record)]}).await;
     }
 
+    // The wplace is reloaded: the client gets its units as on connect
+    async fn serve_scope(&mut self, Scope{ map, info }: Scope) {
+        match self.last(map).await {
+            Ok(map) => {
+                let _ = self.send_ws(Output::CtrlConnected{map, info}).await;
+            },
+            Err(_) => self.close(),
+        }
+    }
+
+    async fn last(&self, map: HashMap<Group, Vec<Unit>>) -> Result<HashMap<Group, Vec<(Unit, Option<Record<Update>>)>>, ()> {
+        let (tx, rx) = channel_one::<HashMap<Group, Vec<(Unit, Option<Record<Update>>)>>>();
+        self.tx_db.send(SignalDb::FromConn(FromConnDb::Last { map, tx_resp: tx })).await.map_err(|_| ())?;
+        rx.await.map_err(|_| ())
+    }
+
+    async fn serve_annotations(&mut self, vec: Vec<(Annotation, bool)>) {
+        let annotations = vec.into_iter().map(|(annotation, is_removed)| DtoAnnotation{ annotation, is_removed }).collect();
+        let _ = self.send_ws(Output::Annotation{annotations}).await;
+    }
+
     async fn serve_tick(&mut self) {
         if let Some(pong) = self.pong {
             if self.ping == pong {
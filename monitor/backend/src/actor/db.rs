@@ -1,6 +1,12 @@
-use std::collections::HashMap;
+use std::{
+    collections::{HashMap, HashSet},
+    path::PathBuf,
+    time::Duration,
+};
 
 use rusqlite::Connection;
+use tokio::runtime::{Builder as RuntimeBuilder, Runtime};
+use tokio::time::{timeout_at, Instant};
 use tokio::sync::{
     mpsc::{channel, Sender, Receiver},
     oneshot::{Sender as OneSender},
@@ -8,12 +14,28 @@ use tokio::sync::{
 
 mod repo_data;
 mod transacrion;
+pub mod repo_prune;
+pub mod repo_unit;
+pub mod repo_export;
+pub mod repo_import;
+pub mod repo_recompress;
+pub mod backup;
+pub mod repo_aggregate;
+mod spill;
+mod repo_read;
+pub mod read;
+mod repo_annotation;
 
 use transacrion::Transaction;
 use repo_data::RepoData;
-use crate::config::{ ConfigServeGroup, ConfigServeDb};
+use repo_annotation::RepoAnnotation;
+use spill::{Spill, SpillRecord};
+use crate::config::{ ConfigServeGroup, ConfigServeDb, ConfigServeDbBackup};
 use crate::model::{
-    dataflow::{Group, Unit, Data, Update, Record}
+    dataflow::{Group, Unit, Data, Update, Record},
+    annotation::Annotation,
+    compress::Dictionaries,
+    user::Login,
 };
 use crate::actor::{
     comm::{Signal as SignalComm, FromDb as FromDbComm},
@@ -37,25 +59,67 @@ pub enum FromDist{
 }
 #[derive(Debug)]
 pub enum FromServer{
-    Get{group: Group, unit: Unit, idx_min: u64, idx_max: u64, tx_resp: OneSender<Result<Vec<Record<Update>>, ()>>},
     Last{map: HashMap<Group, Vec<Unit>>, tx_resp: OneSender<HashMap<Group, Vec<(Unit, Option<Record<Update>>)>>>},
+    Backup{tx_resp: OneSender<Result<PathBuf, ()>>},
+    AnnotationAdd{group: Group, unit: Option<Unit>, time_min: Option<i64>, time_max: Option<i64>, text: String, author: Login, tx_resp: OneSender<Result<Option<Annotation>, ()>>},
+    AnnotationEdit{id: u64, time_min: Option<i64>, time_max: Option<i64>, text: String, tx_resp: OneSender<Result<Option<Annotation>, ()>>},
+    AnnotationRemove{id: u64, tx_resp: OneSender<Result<Option<Annotation>, ()>>},
+}
+
+const RETRY_DELAY_MIN: Duration = Duration::from_secs(1);
+const RETRY_DELAY_MAX: Duration = Duration::from_secs(60);
+
+enum Recv {
+    Signal(Signal),
+    Timeout,
+    Closed,
 }
 
 pub struct Db<'a> {
+    conn: &'a Connection,
     transaction_count_max: usize,
+    commit_interval: Option<Duration>,
     repo_data: RepoData<'a>,
+    repo_annotation: RepoAnnotation<'a>,
     tx_comm: Sender<SignalComm>,
     rx: Receiver<Signal>,
     transacrion: Transaction<'a>,
+    backup: Option<ConfigServeDbBackup>,
+    backup_next: Option<Instant>,
+    spill: Spill,
+    retry_next: Option<Instant>,
+    retry_delay: Duration,
+    // Db runs on its own thread, this runtime only drives timers of the receive loop
+    runtime: Runtime,
 }
 impl <'a> Db<'a> {
-    pub fn new(conn: &'a Connection, rx: Receiver<Signal>, tx_comm: Sender<SignalComm>, cfg: ConfigServeDb, cfg_groups: &HashMap<Group, ConfigServeGroup>) -> Self {
+    pub fn new(conn: &'a Connection, rx: Receiver<Signal>, tx_comm: Sender<SignalComm>, cfg: ConfigServeDb, cfg_groups: &HashMap<Group, ConfigServeGroup>, dictionaries: &Dictionaries) -> Self {
+        // readers of the read pool work next to the writer only with the WAL journal
+        let _: String = conn.pragma_update_and_check(None, "journal_mode", "WAL", |row| row.get(0)).expect("unable to switch the database to WAL journal mode");
+        let runtime = RuntimeBuilder::new_current_thread().enable_time().build().expect("unable to build Db runtime");
+        let backup_next = cfg.backup.as_ref()
+            .filter(|cfg_backup| !cfg_backup.interval.is_zero())
+            .map(|cfg_backup| Instant::now() + cfg_backup.interval);
+        let spill = Spill::new(cfg.spill, &cfg.file);
+        // records left by the previous process are retried right away
+        let retry_next = if spill.len() > 0 { Some(Instant::now()) } else { None };
+        let mut repo_data = RepoData::new(conn, cfg_groups, dictionaries, cfg.meta, cfg.max_bytes);
+        spill_reserve(&spill, &mut repo_data);
         Self {
+            conn,
             transaction_count_max: cfg.tx_count_max,
+            commit_interval: if cfg.commit_interval_ms > 0 { Some(Duration::from_millis(cfg.commit_interval_ms)) } else { None },
             transacrion: Transaction::new(conn),
-            repo_data: RepoData::new(conn, cfg_groups),
+            repo_data,
+            repo_annotation: RepoAnnotation::new(conn),
             tx_comm,
             rx,
+            backup: cfg.backup,
+            backup_next,
+            spill,
+            retry_next,
+            retry_delay: RETRY_DELAY_MIN,
+            runtime,
         }
     }
 
@@ -74,12 +138,50 @@ impl <'a> Db<'a> {
     }
 
     pub fn serve(&mut self) {
-        while let Some(signal) = self.rx.blocking_recv() {
-            self.serve_match(signal);
+        // quotas may be lowered in the config since the last run
+        self.repo_data.overflow_resolve();
+        loop {
+            let deadline = match (self.backup_next, self.retry_next) {
+                (Some(backup_next), Some(retry_next)) => Some(backup_next.min(retry_next)),
+                (backup_next, retry_next) => backup_next.or(retry_next),
+            };
+            match self.recv_until(deadline) {
+                Recv::Signal(signal) => self.serve_match(signal),
+                Recv::Timeout => self.serve_timers(),
+                Recv::Closed => break,
+            }
         }
         self.destruct();
     }
 
+    fn serve_timers(&mut self) {
+        let now = Instant::now();
+        if self.backup_next.is_some_and(|backup_next| backup_next <= now) {
+            self.serve_backup_timer();
+        }
+        if self.retry_next.is_some_and(|retry_next| retry_next <= now) {
+            self.serve_spill_retry();
+        }
+    }
+
+    fn recv_until(&mut self, deadline: Option<Instant>) -> Recv {
+        let rx = &mut self.rx;
+        self.runtime.block_on(async move {
+            let signal_opt = if let Some(deadline) = deadline {
+                match timeout_at(deadline, rx.recv()).await {
+                    Ok(signal_opt) => signal_opt,
+                    Err(_) => return Recv::Timeout,
+                }
+            } else {
+                rx.recv().await
+            };
+            match signal_opt {
+                Some(signal) => Recv::Signal(signal),
+                None => Recv::Closed,
+            }
+        })
+    }
+
     fn serve_match(&mut self, signal: Signal) {
         match signal {
             Signal::FromDist(cmd) => match cmd {
@@ -87,8 +189,20 @@ impl <'a> Db<'a> {
                 FromDist::Closed => self.close(),
             },
             Signal::FromServer(cmd) => match cmd {
-                FromServer::Get { group, unit, idx_min, idx_max, tx_resp } => self.serve_server_get(tx_resp, group, unit, idx_min, idx_max),
                 FromServer::Last { map, tx_resp } => self.serve_last(map, tx_resp),
+                FromServer::Backup { tx_resp } => self.serve_server_backup(tx_resp),
+                FromServer::AnnotationAdd { group, unit, time_min, time_max, text, author, tx_resp } => {
+                    let res = self.repo_annotation.add(&group, unit.as_ref(), time_min, time_max, &text, author.as_str());
+                    self.serve_server_annotation(tx_resp, res, false);
+                },
+                FromServer::AnnotationEdit { id, time_min, time_max, text, tx_resp } => {
+                    let res = self.repo_annotation.edit(id, time_min, time_max, &text);
+                    self.serve_server_annotation(tx_resp, res, false);
+                },
+                FromServer::AnnotationRemove { id, tx_resp } => {
+                    let res = self.repo_annotation.remove(id);
+                    self.serve_server_annotation(tx_resp, res, true);
+                },
             },
             Signal::FromConn(cmd) => match cmd {
                 FromConn::Last { map, tx_resp } => self.serve_last(map, tx_resp),
@@ -105,6 +219,7 @@ impl <'a> Db<'a> {
         let mut done_left = self.transaction_count_max;
         let mut signal_next: Option<Signal> = None;
         let mut datapack = Datapack::new();
+        let deadline = self.commit_interval.map(|commit_interval| Instant::now() + commit_interval);
         self.transacrion.begin(); // TODO: LOG
         if let Some((count, data_record)) = self.repo_data.data_push(data){
             done_left = done_left.checked_sub(count).unwrap_or(0);
@@ -114,24 +229,35 @@ impl <'a> Db<'a> {
             if done_left == 0 {
                 break;
             }
-            if let Ok(signal) = self.rx.try_recv() {
-                if let Signal::FromDist(FromDist::Data(data)) = signal {
-                    if let Some((count, data_record)) = self.repo_data.data_push(data) {
-                        done_left = done_left.checked_sub(count).unwrap_or(0);
-                        datapack.push(data_record);
-                    }
-                    done_left -= 1;
-                } else {
-                    signal_next = Some(signal);
-                    break;
+            let signal = if let Ok(signal) = self.rx.try_recv() {
+                signal
+            } else if let Some(deadline) = deadline {
+                // channel is drained, but the batch stays open until the commit interval ends
+                match self.recv_until(Some(deadline)) {
+                    Recv::Signal(signal) => signal,
+                    Recv::Timeout | Recv::Closed => break,
                 }
             } else {
                 break;
+            };
+            if let Signal::FromDist(FromDist::Data(data)) = signal {
+                if let Some((count, data_record)) = self.repo_data.data_push(data) {
+                    done_left = done_left.checked_sub(count).unwrap_or(0);
+                    datapack.push(data_record);
+                }
+                done_left = done_left.saturating_sub(1);
+            } else {
+                // any other signal, including FromDist::Closed on shutdown, commits the batch first
+                signal_next = Some(signal);
+                break;
             }
         }
-        if let Err(_) = self.transacrion.commit() {
+        if let Err(err) = self.transacrion.commit() {
+            println!("[ERR] Db: commit error: {}", err); // TODO: log this
+            let _ = self.transacrion.rollback();
             datapack.mark_unsaved();
         }
+        self.spill_push(&datapack);
         self.send_datapack(datapack);
         self.repo_data.overflow_resolve();
         if let Some(signal) = signal_next {
@@ -139,11 +265,115 @@ impl <'a> Db<'a> {
         }
     }
 
-    fn serve_server_get(&mut self, tx_resp: OneSender<Result<Vec<Record<Update>>, ()>>, group: Group, unit: Unit, idx_min: u64, idx_max: u64) {
-        let res = self.repo_data.data_get(&group, &unit, idx_min, idx_max);
+    fn spill_push(&mut self, datapack: &Datapack) {
+        let vec_spill = datapack.unsaved();
+        if vec_spill.is_empty() {
+            return;
+        }
+        match self.spill.push(vec_spill) {
+            Ok(0) => {},
+            Ok(count_dropped) => println!("[ERR] Db: spill buffer is full, {} records are lost", count_dropped), // TODO: log this
+            Err(err) => println!("[ERR] Db: spill buffer is not writable, records are lost: {}", err), // TODO: log this
+        }
+        if self.retry_next.is_none() && self.spill.len() > 0 {
+            self.retry_next = Some(Instant::now() + self.retry_delay);
+        }
+    }
+
+    fn serve_spill_retry(&mut self) {
+        let res = self.spill.load().map_err(|err| err.to_string())
+            .and_then(|vec_spill| self.spill_save(vec_spill).map_err(|err| err.to_string()));
+        match res {
+            Ok(vec_data) => {
+                if let Err(err) = self.spill.clear() {
+                    println!("[ERR] Db: spill buffer is not cleared, its records will be saved again: {}", err); // TODO: log this
+                }
+                println!("[INFO] Db: {} records from spill buffer are saved", vec_data.len()); // TODO: log this
+                self.retry_next = None;
+                self.retry_delay = RETRY_DELAY_MIN;
+                if !vec_data.is_empty() {
+                    // clients get the same records once more, now with is_saved=true
+                    self.send_comm(FromDbComm::Datapack(vec_data));
+                }
+            },
+            Err(err) => {
+                let _ = self.transacrion.rollback();
+                self.retry_delay = (self.retry_delay * 2).min(RETRY_DELAY_MAX);
+                self.retry_next = Some(Instant::now() + self.retry_delay);
+                println!("[WARN] Db: spill buffer retry failed, next in {}s: {}", self.retry_delay.as_secs(), err); // TODO: log this
+            },
+        }
+    }
+
+    // Units get back their state if the records are not committed, so a retry does not count them twice
+    fn spill_save(&mut self, vec_spill: Vec<SpillRecord>) -> Result<Vec<Data<Record<Update>>>, rusqlite::Error> {
+        let id_units: HashSet<u32> = vec_spill.iter().filter_map(|spill_record| self.repo_data.unit_id(&spill_record.group, &spill_record.unit)).collect();
+        let state_saved = self.repo_data.state_save(id_units.into_iter());
+        let res = self.spill_insert(vec_spill);
+        if res.is_err() {
+            self.repo_data.state_load(state_saved);
+        }
+        res
+    }
+
+    fn spill_insert(&mut self, vec_spill: Vec<SpillRecord>) -> Result<Vec<Data<Record<Update>>>, rusqlite::Error> {
+        let mut vec_data = Vec::with_capacity(vec_spill.len());
+        self.transacrion.begin()?;
+        for spill_record in vec_spill {
+            let mut record = spill_record.to_record();
+            if let Some(id_unit) = self.repo_data.unit_id(&spill_record.group, &spill_record.unit) {
+                self.repo_data.data_restore(id_unit, &record)?;
+                record.is_saved = true;
+                vec_data.push(Data::Single { group: spill_record.group, unit: spill_record.unit, update: record });
+            } else {
+                println!("[WARN] Db: spilled record of \"{}\" / \"{}\" is dropped: the unit is not configured", spill_record.group.to_str(), spill_record.unit.to_str()); // TODO: log this
+            }
+        }
+        self.transacrion.commit()?;
+        Ok(vec_data)
+    }
+
+    fn serve_server_backup(&mut self, tx_resp: OneSender<Result<PathBuf, ()>>) {
+        let res = self.backup_make().ok_or(());
         let _ = tx_resp.send(res);
     }
 
+    // Changed annotation is pushed to the connections of every workplace which sees it
+    fn serve_server_annotation(&mut self, tx_resp: OneSender<Result<Option<Annotation>, ()>>, res: Result<Option<Annotation>, rusqlite::Error>, is_removed: bool) {
+        match res {
+            Ok(annotation_opt) => {
+                if let Some(annotation) = annotation_opt.as_ref() {
+                    self.send_comm(FromDbComm::Annotation(annotation.clone(), is_removed));
+                }
+                let _ = tx_resp.send(Ok(annotation_opt));
+            },
+            Err(err) => {
+                println!("[ERR] Db: annotation: rusqlite error: {}", err); // TODO: log this
+                let _ = tx_resp.send(Err(()));
+            },
+        }
+    }
+
+    fn serve_backup_timer(&mut self) {
+        self.backup_make();
+        self.backup_next = self.backup.as_ref().map(|cfg_backup| Instant::now() + cfg_backup.interval);
+    }
+
+    // Called between transactions only, so the snapshot never contains a half-written batch
+    fn backup_make(&mut self) -> Option<PathBuf> {
+        let cfg_backup = self.backup.as_ref()?;
+        match backup::snapshot(self.conn, &cfg_backup.dir, cfg_backup.keep) {
+            Ok(path) => {
+                println!("[INFO] database snapshot is saved to {}", path.display()); // TODO: log this
+                Some(path)
+            },
+            Err(err) => {
+                println!("[ERR] database snapshot failed: {}", err); // TODO: log this
+                None
+            },
+        }
+    }
+
     fn send_datapack(&mut self, datapack: Datapack) {
         match datapack.records {
             Records::Single(single_data) => self.send_comm(FromDbComm::Data(single_data)),
@@ -155,6 +385,22 @@ impl <'a> Db<'a> {
 }
 
 
+// Ids of the spilled records are taken before any new record gets one
+fn spill_reserve(spill: &Spill, repo_data: &mut RepoData) {
+    if spill.len() == 0 {
+        return;
+    }
+    match spill.load() {
+        Ok(vec_spill) => for spill_record in vec_spill {
+            if let Some(id_unit) = repo_data.unit_id(&spill_record.group, &spill_record.unit) {
+                repo_data.data_reserve(id_unit, &spill_record.to_record());
+            }
+        },
+        Err(err) => println!("[WARN] Db: spill buffer is not readable, its ids are not reserved: {}", err), // TODO: log this
+    }
+}
+
+
 enum Records {
     None,
     Single(Data<Record<Update>>),
@@ -186,6 +432,37 @@ impl Datapack {
         };
     }
 
+    fn unsaved(&self) -> Vec<SpillRecord> {
+        let mut vec_res = Vec::new();
+        match &self.records {
+            Records::Single(single_data) => Self::unsaved_data(single_data, &mut vec_res),
+            Records::Multi(vec_data) => {
+                for single_data in vec_data {
+                    Self::unsaved_data(single_data, &mut vec_res)
+                }
+            },
+            Records::None => {},
+        }
+        vec_res
+    }
+
+    fn unsaved_data(data: &Data<Record<Update>>, vec_res: &mut Vec<SpillRecord>) {
+        match data {
+            Data::Single { group, unit, update } => if !update.is_saved {
+                vec_res.push(SpillRecord::new(group, unit, update));
+            },
+            Data::Multi { vec } => {
+                for (group, vec_unit) in vec {
+                    for (unit, record) in vec_unit {
+                        if !record.is_saved {
+                            vec_res.push(SpillRecord::new(group, unit, record));
+                        }
+                    }
+                }
+            },
+        }
+    }
+
     fn mark_unsaved(&mut self) {
         match &mut self.records {
             Records::Single(single_data) => Self::mark_unsaved_data(single_data),
@@ -0,0 +1,75 @@
+use std::{
+    fmt, io, thread,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use chrono::Utc;
+use rusqlite::{Connection, Error as SqlErr, backup::{Backup, StepResult}};
+
+
+const PREFIX: &str = "snapshot-";
+const SUFFIX: &str = ".db";
+
+// Consistent copy of the database made with SQLite online backup API; the oldest snapshots beyond `keep` are removed
+pub fn snapshot(conn: &Connection, dir: &Path, keep: usize) -> Result<PathBuf, BackupError> {
+    let path = dir.join(format!("{}{}{}", PREFIX, Utc::now().format("%Y%m%dT%H%M%S%.3fZ"), SUFFIX));
+    snapshot_to(conn, &path)?;
+    rotate(dir, keep)?;
+    Ok(path)
+}
+
+pub fn snapshot_to(conn: &Connection, path: &Path) -> Result<(), BackupError> {
+    let mut conn_dst = Connection::open(path)?;
+    let backup = Backup::new(conn, &mut conn_dst)?;
+    loop {
+        match backup.step(-1)? {
+            StepResult::Done => return Ok(()),
+            _ => thread::sleep(Duration::from_millis(10)),
+        }
+    }
+}
+
+fn rotate(dir: &Path, keep: usize) -> Result<(), BackupError> {
+    let mut vec_name: Vec<String> = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let name = entry?.file_name().to_string_lossy().to_string();
+        if name.starts_with(PREFIX) && name.ends_with(SUFFIX) {
+            vec_name.push(name);
+        }
+    }
+    // timestamps in names sort lexicographically
+    vec_name.sort();
+    let count_rm = vec_name.len().saturating_sub(keep);
+    for name in vec_name.iter().take(count_rm) {
+        std::fs::remove_file(dir.join(name))?;
+    }
+    Ok(())
+}
+
+
+#[derive(Debug)]
+pub enum BackupError {
+    Sql(SqlErr),
+    Io(io::Error),
+    NotConfigured,
+}
+impl From<SqlErr> for BackupError {
+    fn from(err: SqlErr) -> Self {
+        Self::Sql(err)
+    }
+}
+impl From<io::Error> for BackupError {
+    fn from(err: io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+impl fmt::Display for BackupError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BackupError::Sql(err) => write!(f, "database error: {}", err),
+            BackupError::Io(err) => write!(f, "io error: {}", err),
+            BackupError::NotConfigured => write!(f, "'db.backup' config is not given and no output file is provided"),
+        }
+    }
+}
@@ -0,0 +1,158 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use rusqlite::{Connection, OpenFlags};
+use tokio::sync::{
+    mpsc::Receiver,
+    oneshot::{Sender as OneSender},
+};
+
+use super::repo_read::RepoRead;
+use super::repo_aggregate::{RepoAggregate, Window, Bucket};
+use crate::model::annotation::Annotation;
+use crate::model::compress::Dictionaries;
+use crate::model::dataflow::{Group, Unit, Update, Record};
+
+
+#[derive(Debug)]
+pub enum Signal {
+    FromServer(FromServer),
+}
+#[derive(Debug)]
+pub enum FromServer{
+    Get{group: Group, unit: Unit, idx_min: u64, idx_max: u64, tx_resp: OneSender<Result<Vec<Record<Update>>, ()>>},
+    GetTime{group: Group, unit: Unit, range: RangeTime, tx_resp: OneSender<Result<Vec<Record<Update>>, ()>>},
+    Aggregate{group: Group, units: Vec<Unit>, window: Window, percentiles: Vec<f64>, tx_resp: OneSender<Result<HashMap<Unit, Vec<Bucket>>, ()>>},
+    Annotations{group: Group, unit: Option<Unit>, time_min: Option<i64>, time_max: Option<i64>, tx_resp: OneSender<Result<Vec<Annotation>, ()>>},
+    AnnotationGet{id: u64, tx_resp: OneSender<Result<Option<Annotation>, ()>>},
+}
+
+// Records with time within [time_min, time_max] ordered by (time, id_record), starting after the 'after' pair
+#[derive(Debug)]
+pub struct RangeTime {
+    pub time_min: Option<i64>,
+    pub time_max: Option<i64>,
+    pub after: Option<(i64, u64)>,
+    pub limit: u32,
+}
+
+
+// Workers share one receiver, each of them serves queries on its own read-only connection;
+// with the WAL journal they neither block the writer nor are blocked by it
+pub fn spawn(file: &str, count: usize, rx: Receiver<Signal>, dictionaries: &Dictionaries) {
+    let rx = Arc::new(Mutex::new(rx));
+    for _ in 0..count {
+        let rx = rx.clone();
+        let file = file.to_string();
+        let dictionaries = dictionaries.clone();
+        std::thread::spawn(move || {
+            let conn = Connection::open_with_flags(&file, OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX)
+                .expect("unable to open a database file for reading");
+            let mut read = Read::new(&conn, &dictionaries);
+            read.serve(&rx);
+        });
+    }
+}
+
+
+struct Read<'a> {
+    repo_read: RepoRead<'a>,
+    repo_aggregate: RepoAggregate<'a>,
+}
+impl <'a>Read<'a> {
+    fn new(conn: &'a Connection, dictionaries: &'a Dictionaries) -> Self {
+        Self {
+            repo_read: RepoRead::new(conn, dictionaries),
+            repo_aggregate: RepoAggregate::new(conn, dictionaries),
+        }
+    }
+
+    fn serve(&mut self, rx: &Mutex<Receiver<Signal>>) {
+        loop {
+            let signal_opt = match rx.lock() {
+                Ok(mut rx_guard) => rx_guard.blocking_recv(),
+                Err(_) => None,
+            };
+            match signal_opt {
+                Some(signal) => self.serve_match(signal),
+                None => break,
+            }
+        }
+    }
+
+    fn serve_match(&mut self, signal: Signal) {
+        match signal {
+            Signal::FromServer(cmd) => match cmd {
+                FromServer::Get { group, unit, idx_min, idx_max, tx_resp } => self.serve_server_get(tx_resp, group, unit, idx_min, idx_max),
+                FromServer::GetTime { group, unit, range, tx_resp } => self.serve_server_get_time(tx_resp, group, unit, range),
+                FromServer::Aggregate { group, units, window, percentiles, tx_resp } => self.serve_server_aggregate(tx_resp, group, units, window, percentiles),
+                FromServer::Annotations { group, unit, time_min, time_max, tx_resp } => self.serve_server_annotations(tx_resp, group, unit, time_min, time_max),
+                FromServer::AnnotationGet { id, tx_resp } => self.serve_server_annotation_get(tx_resp, id),
+            },
+        }
+    }
+
+    fn serve_server_get(&mut self, tx_resp: OneSender<Result<Vec<Record<Update>>, ()>>, group: Group, unit: Unit, idx_min: u64, idx_max: u64) {
+        let res = self.unit_id(&group, &unit).and_then(|id_unit| {
+            self.repo_read.data_get(id_unit, idx_min, idx_max).map_err(|err| {
+                println!("[Read] data_get: rusqlite error: {}", err); // TODO: log this
+            })
+        });
+        let _ = tx_resp.send(res);
+    }
+
+    fn serve_server_get_time(&mut self, tx_resp: OneSender<Result<Vec<Record<Update>>, ()>>, group: Group, unit: Unit, range: RangeTime) {
+        let res = self.unit_id(&group, &unit).and_then(|id_unit| {
+            self.repo_read.data_get_time(id_unit, &range).map_err(|err| {
+                println!("[Read] data_get_time: rusqlite error: {}", err); // TODO: log this
+            })
+        });
+        let _ = tx_resp.send(res);
+    }
+
+    fn serve_server_aggregate(&mut self, tx_resp: OneSender<Result<HashMap<Unit, Vec<Bucket>>, ()>>, group: Group, units: Vec<Unit>, window: Window, percentiles: Vec<f64>) {
+        let mut map_res = HashMap::with_capacity(units.len());
+        for unit in units {
+            let res = self.unit_id(&group, &unit).and_then(|id_unit| {
+                self.repo_aggregate.aggregate(id_unit, &window, &percentiles).map_err(|err| {
+                    println!("[Read] aggregate: rusqlite error: {}", err); // TODO: log this
+                })
+            });
+            match res {
+                Ok(vec_bucket) => { map_res.insert(unit, vec_bucket); },
+                Err(_) => {
+                    let _ = tx_resp.send(Err(()));
+                    return;
+                },
+            }
+        }
+        let _ = tx_resp.send(Ok(map_res));
+    }
+
+    fn serve_server_annotations(&mut self, tx_resp: OneSender<Result<Vec<Annotation>, ()>>, group: Group, unit: Option<Unit>, time_min: Option<i64>, time_max: Option<i64>) {
+        let res = self.repo_read.annotation_list(&group, unit.as_ref(), time_min, time_max).map_err(|err| {
+            println!("[Read] annotation_list: rusqlite error: {}", err); // TODO: log this
+        });
+        let _ = tx_resp.send(res);
+    }
+
+    fn serve_server_annotation_get(&mut self, tx_resp: OneSender<Result<Option<Annotation>, ()>>, id: u64) {
+        let res = self.repo_read.annotation_get(id).map_err(|err| {
+            println!("[Read] annotation_get: rusqlite error: {}", err); // TODO: log this
+        });
+        let _ = tx_resp.send(res);
+    }
+
+    fn unit_id(&mut self, group: &Group, unit: &Unit) -> Result<u32, ()> {
+        match self.repo_read.unit_id(group, unit) {
+            Ok(Some(id_unit)) => Ok(id_unit),
+            Ok(None) => Err(()),
+            Err(err) => {
+                println!("[Read] unit_id: rusqlite error: {}", err); // TODO: log this
+                Err(())
+            },
+        }
+    }
+}
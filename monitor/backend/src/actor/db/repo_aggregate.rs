@@ -0,0 +1,159 @@
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+use rusqlite::{Connection, Statement, named_params, Error as SqlErr, OptionalExtension};
+
+use crate::model::compress::Dictionaries;
+use crate::model::dataflow::Update;
+
+
+// Window [time_min, time_max) split into buckets of 'bucket' ms, the last one may be shorter
+#[derive(Debug, Clone)]
+pub struct Window {
+    pub time_min: i64,
+    pub time_max: i64,
+    pub bucket: i64,
+}
+impl Window {
+    // Rounded up without adding to the length, so no bucket size overflows it
+    pub fn count_buckets(&self) -> i64 {
+        let len = self.time_max - self.time_min;
+        len / self.bucket + if len % self.bucket != 0 { 1 } else { 0 }
+    }
+}
+
+// Values are taken into account only if their text is a number; online ratio is the share of
+// the bucket time the unit spent online, Value records count as online
+#[derive(Serialize, Debug)]
+pub struct Bucket {
+    pub time: i64,
+    pub count: u64,
+    pub count_value: u64,
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+    pub avg: Option<f64>,
+    pub percentiles: BTreeMap<String, f64>,
+    pub online_ratio: f64,
+    #[serde(skip)]
+    values: Vec<f64>,
+    #[serde(skip)]
+    online_ms: i64,
+}
+
+pub struct RepoAggregate<'a> {
+    dictionaries: &'a Dictionaries,
+    stmt_data_window: Statement<'a>,
+    stmt_data_before: Statement<'a>,
+}
+impl <'a>RepoAggregate<'a> {
+    pub fn new(conn: &'a Connection, dictionaries: &'a Dictionaries) -> Self {
+        Self {
+            dictionaries,
+            stmt_data_window: prepare::stmt_data_window(conn),
+            stmt_data_before: prepare::stmt_data_before(conn),
+        }
+    }
+
+    pub fn aggregate(&mut self, id_unit: u32, window: &Window, percentiles: &[f64]) -> Result<Vec<Bucket>, SqlErr> {
+        let mut vec_bucket: Vec<Bucket> = (0..window.count_buckets()).map(|idx| Bucket {
+            time: window.time_min + idx * window.bucket,
+            count: 0,
+            count_value: 0,
+            min: None,
+            max: None,
+            avg: None,
+            percentiles: BTreeMap::new(),
+            online_ratio: 0.0,
+            values: Vec::new(),
+            online_ms: 0,
+        }).collect();
+        let type_before: Option<u8> = self.stmt_data_before.query_row(named_params! {":id_unit": &id_unit, ":time_min": &window.time_min}, |row| row.get(0)).optional()?;
+        let mut is_online = type_before.is_some_and(|upd_type| Update::from_ser(upd_type, None, self.dictionaries).is_ok_and(|update| update.is_online()));
+        let mut time_since = window.time_min;
+        let mut rows = self.stmt_data_window.query(named_params! {":id_unit": &id_unit, ":time_min": &window.time_min, ":time_max": &window.time_max})?;
+        while let Some(row) = rows.next()? {
+            let time: i64 = row.get(0)?;
+            let upd_type: u8 = row.get(1)?;
+            let upd_val: Option<Vec<u8>> = row.get(2)?;
+            // a value not decompressed is counted as a record, but not as a number
+            let update_res = Update::from_ser(upd_type, upd_val, self.dictionaries);
+            if is_online {
+                online_add(&mut vec_bucket, window, time_since, time);
+            }
+            is_online = update_res.as_ref().map_or(true, |update| update.is_online());
+            time_since = time;
+            let bucket = &mut vec_bucket[((time - window.time_min) / window.bucket) as usize];
+            bucket.count += 1;
+            if let Some(value) = update_res.ok().as_ref().and_then(value_number) {
+                bucket.values.push(value);
+            }
+        }
+        if is_online {
+            online_add(&mut vec_bucket, window, time_since, window.time_max);
+        }
+        for bucket in vec_bucket.iter_mut() {
+            bucket_finish(bucket, window, percentiles);
+        }
+        Ok(vec_bucket)
+    }
+}
+
+
+fn value_number(update: &Update) -> Option<f64> {
+    if let Update::Value{value, ..} = update {
+        let number: f64 = std::str::from_utf8(value.as_bytes()).ok()?.trim().parse().ok()?;
+        if number.is_finite() {
+            return Some(number);
+        }
+    }
+    None
+}
+
+fn online_add(vec_bucket: &mut [Bucket], window: &Window, time_from: i64, time_to: i64) {
+    let idx_first = ((time_from - window.time_min) / window.bucket) as usize;
+    for bucket in vec_bucket.iter_mut().skip(idx_first) {
+        let bucket_end = (bucket.time + window.bucket).min(window.time_max);
+        if bucket.time >= time_to {
+            break;
+        }
+        bucket.online_ms += bucket_end.min(time_to) - bucket.time.max(time_from);
+    }
+}
+
+fn bucket_finish(bucket: &mut Bucket, window: &Window, percentiles: &[f64]) {
+    let len = (bucket.time + window.bucket).min(window.time_max) - bucket.time;
+    bucket.online_ratio = bucket.online_ms as f64 / len as f64;
+    let mut values = std::mem::take(&mut bucket.values);
+    if values.is_empty() {
+        return;
+    }
+    values.sort_by(|a, b| a.total_cmp(b));
+    bucket.count_value = values.len() as u64;
+    bucket.min = values.first().copied();
+    bucket.max = values.last().copied();
+    bucket.avg = Some(values.iter().sum::<f64>() / values.len() as f64);
+    // nearest-rank method
+    for p in percentiles {
+        let rank = (p / 100.0 * values.len() as f64).ceil() as usize;
+        bucket.percentiles.insert(p.to_string(), values[rank.clamp(1, values.len()) - 1]);
+    }
+}
+
+
+mod prepare {
+    use rusqlite::{Connection, Statement, Error};
+
+    pub fn stmt_data_window<'a>(conn: &'a Connection) -> Statement<'a> {
+        unwrap(conn.prepare("SELECT time, type, val FROM data WHERE fk_data_unit = :id_unit AND time >= :time_min AND time < :time_max ORDER BY time, id_record"))
+    }
+    pub fn stmt_data_before<'a>(conn: &'a Connection) -> Statement<'a> {
+        unwrap(conn.prepare("SELECT type FROM data WHERE fk_data_unit = :id_unit AND time < :time_min ORDER BY time DESC, id_record DESC LIMIT 1"))
+    }
+
+    fn unwrap(res: Result<Statement, Error>) -> Statement {
+        match res {
+            Ok(stmt) => stmt,
+            Err(err) => panic!("Rusqlite: RepoAggregate: prepare statement error: {}", err),
+        }
+    }
+}
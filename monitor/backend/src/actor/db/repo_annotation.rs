@@ -0,0 +1,126 @@
+use rusqlite::{Connection, Statement, Row, named_params, Error as SqlErr, OptionalExtension};
+
+use super::repo_unit::RepoUnit;
+use crate::model::annotation::Annotation;
+use crate::model::dataflow::{Group, Unit};
+
+
+// Columns expected by annotation_from_row, shared with the read pool
+pub const SELECT_ANNOTATION: &str = "SELECT a.id, g.name, u.name, a.time_min, a.time_max, a.text, a.author, a.time_created, a.time_modified
+    FROM annotations a JOIN groups g ON g.id = a.fk_annotation_group LEFT JOIN units u ON u.id = a.fk_annotation_unit";
+
+pub fn annotation_from_row(row: &Row) -> Result<Annotation, SqlErr> {
+    let unit: Option<String> = row.get(2)?;
+    Ok(Annotation {
+        id: row.get(0)?,
+        group: Group::new(row.get(1)?),
+        unit: unit.map(Unit::new),
+        time_min: row.get(3)?,
+        time_max: row.get(4)?,
+        text: row.get(5)?,
+        author: row.get(6)?,
+        time_created: row.get(7)?,
+        time_modified: row.get(8)?,
+    })
+}
+
+
+pub struct RepoAnnotation<'a> {
+    repo_unit: RepoUnit<'a>,
+    stmt_annotation_add: Statement<'a>,
+    stmt_annotation_edit: Statement<'a>,
+    stmt_annotation_rm: Statement<'a>,
+    stmt_annotation_get: Statement<'a>,
+}
+impl <'a>RepoAnnotation<'a> {
+    pub fn new(conn: &'a Connection) -> Self {
+        Self {
+            repo_unit: RepoUnit::new(conn),
+            stmt_annotation_add: prepare::stmt_annotation_add(conn),
+            stmt_annotation_edit: prepare::stmt_annotation_edit(conn),
+            stmt_annotation_rm: prepare::stmt_annotation_rm(conn),
+            stmt_annotation_get: prepare::stmt_annotation_get(conn),
+        }
+    }
+
+    // None if the group or the unit is not known to the database
+    pub fn add(&mut self, group: &Group, unit: Option<&Unit>, time_min: Option<i64>, time_max: Option<i64>, text: &str, author: &str) -> Result<Option<Annotation>, SqlErr> {
+        let id_group = match self.repo_unit.group_id(group)? {
+            Some(id_group) => id_group,
+            None => return Ok(None),
+        };
+        let id_unit = match unit {
+            Some(unit) => match self.repo_unit.unit_id(group, unit)? {
+                Some(id_unit) => Some(id_unit),
+                None => return Ok(None),
+            },
+            None => None,
+        };
+        let time = chrono::offset::Utc::now().timestamp_millis();
+        let id = self.stmt_annotation_add.insert(named_params! {
+            ":id_group": id_group,
+            ":id_unit": id_unit,
+            ":time_min": time_min,
+            ":time_max": time_max,
+            ":text": text,
+            ":author": author,
+            ":time": time,
+        })?;
+        self.get(id as u64)
+    }
+
+    pub fn edit(&mut self, id: u64, time_min: Option<i64>, time_max: Option<i64>, text: &str) -> Result<Option<Annotation>, SqlErr> {
+        let count = self.stmt_annotation_edit.execute(named_params! {
+            ":id": id,
+            ":time_min": time_min,
+            ":time_max": time_max,
+            ":text": text,
+            ":time": chrono::offset::Utc::now().timestamp_millis(),
+        })?;
+        if count == 0 {
+            return Ok(None);
+        }
+        self.get(id)
+    }
+
+    // Returns the removed annotation
+    pub fn remove(&mut self, id: u64) -> Result<Option<Annotation>, SqlErr> {
+        let annotation_opt = self.get(id)?;
+        if annotation_opt.is_some() {
+            self.stmt_annotation_rm.execute(named_params! {":id": id})?;
+        }
+        Ok(annotation_opt)
+    }
+
+    pub fn get(&mut self, id: u64) -> Result<Option<Annotation>, SqlErr> {
+        self.stmt_annotation_get.query_row(named_params! {":id": id}, annotation_from_row).optional()
+    }
+}
+
+
+mod prepare {
+    use rusqlite::{Connection, Statement, Error};
+
+    use super::SELECT_ANNOTATION;
+
+    pub fn stmt_annotation_add<'a>(conn: &'a Connection) -> Statement<'a> {
+        unwrap(conn.prepare("INSERT INTO annotations (fk_annotation_group, fk_annotation_unit, time_min, time_max, text, author, time_created, time_modified)
+            VALUES (:id_group, :id_unit, :time_min, :time_max, :text, :author, :time, :time)"))
+    }
+    pub fn stmt_annotation_edit<'a>(conn: &'a Connection) -> Statement<'a> {
+        unwrap(conn.prepare("UPDATE annotations SET time_min = :time_min, time_max = :time_max, text = :text, time_modified = :time WHERE id = :id"))
+    }
+    pub fn stmt_annotation_rm<'a>(conn: &'a Connection) -> Statement<'a> {
+        unwrap(conn.prepare("DELETE FROM annotations WHERE id = :id"))
+    }
+    pub fn stmt_annotation_get<'a>(conn: &'a Connection) -> Statement<'a> {
+        unwrap(conn.prepare(&format!("{} WHERE a.id = :id", SELECT_ANNOTATION)))
+    }
+
+    fn unwrap(res: Result<Statement, Error>) -> Statement {
+        match res {
+            Ok(stmt) => stmt,
+            Err(err) => panic!("Rusqlite: RepoAnnotation: prepare statement error: {}", err),
+        }
+    }
+}
@@ -2,27 +2,49 @@ use std::collections::{HashMap, VecDeque};
 
 use rusqlite::{Connection, Statement, named_params, Error as SqlErr, ErrorCode as SqlErrorCode, ffi::Error as SqlErrInner, OptionalExtension};
 
-use crate::config::ConfigServeGroup;
+use super::repo_unit::{RepoUnit, RenameError};
+use crate::config::{ConfigServeGroup, ConfigCompress};
 use crate::model::dataflow::{Group, Unit, Data, Update, Record};
+use crate::model::compress::{Codec, Dictionaries};
 
 struct StateUnit {
     count: u64,
     count_min: u64,
     count_max: u64,
+    bytes: u64,
+    bytes_max: Option<u64>,
     record_last: Option<Record<Update>>,
+    codec: Option<Codec>,
+}
+
+// Size quotas trim down to this share of the limit, so trimming is not repeated on every insert
+const BYTES_KEEP_PERCENT: u64 = 75;
+
+// Counts, sizes and last records of the units before a transaction, put back if it fails
+pub struct StateSaved {
+    units: Vec<(u32, u64, u64, Option<Record<Update>>)>,
+    bytes: u64,
 }
 
 pub struct RepoData<'a> {
-    stmt_data_get: Statement<'a>,
     stmt_data_rm_old_count: Statement<'a>,
+    stmt_data_rm_old_bytes: Statement<'a>,
+    stmt_data_rm_old_bytes_total: Statement<'a>,
+    stmt_data_get_count: Statement<'a>,
+    stmt_data_get_count_all: Statement<'a>,
     stmt_data_push: Statement<'a>,
     map_group: HashMap<Group, HashMap<Unit, u32>>,
     map_state: HashMap<u32, StateUnit>, // <id_unit, StateUnit>
     id_units_overflowed: VecDeque<u32>,
+    is_meta: bool,
+    // value bytes of all the units and the global cap
+    bytes: u64,
+    bytes_max: Option<u64>,
 }
 impl <'a>RepoData <'a> {
-    pub fn new(conn: &'a Connection, cfg_groups: &HashMap<Group, ConfigServeGroup>) -> Self {
-        let mut count_units: usize = 0;
+    pub fn new(conn: &'a Connection, cfg_groups: &HashMap<Group, ConfigServeGroup>, dictionaries: &Dictionaries, is_meta: bool, bytes_max: Option<u64>) -> Self {
+        let count_units: usize = cfg_groups.values().map(|cfg_group| cfg_group.units.len()).sum();
+        let mut bytes_total: u64 = 0;
         let (id_group_max, id_unit_max) = prepare::init(conn);
         let mut id_group_new = if let Some(id) = id_group_max { id + 1 } else { 0 };
         let mut id_unit_new = if let Some(id) = id_unit_max { id + 1 } else { 0 };
@@ -33,9 +55,11 @@ impl <'a>RepoData <'a> {
         let mut stmt_unit_get = prepare::stmt_unit_get(conn);
         let mut stmt_data_get_last = prepare::stmt_data_get_last(conn);
         let mut stmt_data_get_count = prepare::stmt_data_get_count(conn);
+        let mut repo_unit = RepoUnit::new(conn);
 
         let mut map_group = HashMap::new();
         let mut map_state = HashMap::new();
+        let mut id_units_overflowed = VecDeque::with_capacity(count_units);
         
         for (group, cfg_group) in cfg_groups {
             match stmt_group_set.execute(named_params! {":id": &id_group_new, ":name": group.to_str()}) {
@@ -47,9 +71,20 @@ impl <'a>RepoData <'a> {
                 let id: u32 = row.get(0)?;
                 Ok(id)
             }));
-            count_units += cfg_group.units.len();
             let mut map_units: HashMap<Unit, u32> = HashMap::with_capacity(cfg_group.units.len());
             for (unit, cfg_unit) in cfg_group.units.iter() {
+                for alias in cfg_unit.aliases.iter() {
+                    let group_alias = alias.group.as_ref().unwrap_or(group);
+                    match repo_unit.unit_rename(group_alias, &alias.unit, group, unit) {
+                        Ok(_) => {
+                            println!("[INFO] unit \"{}\" / \"{}\" renamed to \"{}\" / \"{}\"", group_alias.to_str(), alias.unit.to_str(), group.to_str(), unit.to_str());
+                            break;
+                        },
+                        Err(RenameError::SourceMissing) | Err(RenameError::Unchanged) => {},
+                        Err(RenameError::TargetExists) => break,
+                        Err(err) => panic!("Rusqlite: RepoData: counstructor: unit rename error: {}", err),
+                    }
+                }
                 match stmt_unit_set.execute(named_params! {":id": &id_unit_new, ":id_group": &id_group, ":name": unit.to_str()}) {
                     Ok(_) => { id_unit_new += 1 },
                     Err(SqlErr::SqliteFailure(SqlErrInner{code: SqlErrorCode::ConstraintViolation, extended_code: 2067}, _)) => {},
@@ -65,7 +100,11 @@ impl <'a>RepoData <'a> {
                     let time: i64 = row.get(1)?;
                     let upd_type: u8 = row.get(2)?;
                     let upd_val: Option<Vec<u8>> = row.get(3)?;
-                    let update = Update::from_ser(upd_type, upd_val);
+                    // the last record is kept for its id; a value not decompressed is told as online instead of its frame
+                    let update = Update::from_ser(upd_type, upd_val, dictionaries).unwrap_or_else(|err| {
+                        println!("[ERR] last record {} of \"{}\" / \"{}\" is not decompressed: {}", id_record, group.to_str(), unit.to_str(), err);
+                        Update::Online
+                    });
                     Ok(Record{
                         id: id_record,
                         is_saved: true,
@@ -74,24 +113,34 @@ impl <'a>RepoData <'a> {
                     })
                 }).optional());
                 let state = if let Some(record_last) = record_last_opt {
-                    let id_record_count = prepare::unwrap(stmt_data_get_count.query_row(named_params! {":id_unit": &id_unit}, |row| {
+                    let (id_record_count, bytes) = prepare::unwrap(stmt_data_get_count.query_row(named_params! {":id_unit": &id_unit}, |row| {
                         let id: u64 = row.get(0)?;
-                        Ok(id)
+                        let bytes: u64 = row.get(1)?;
+                        Ok((id, bytes))
                     }));
+                    bytes_total += bytes;
                     StateUnit{
                         count_min: cfg_unit.count_min, 
                         count_max: cfg_unit.count_max,
                         count: id_record_count,
+                        bytes,
+                        bytes_max: cfg_unit.max_bytes,
                         record_last: Some(record_last),
+                        codec: cfg_unit.compress.as_ref().map(codec_new),
                     }
                 } else {
                     StateUnit{
                         count_min: cfg_unit.count_min, 
                         count_max: cfg_unit.count_max,
                         count: 0,
+                        bytes: 0,
+                        bytes_max: cfg_unit.max_bytes,
                         record_last: None,
+                        codec: cfg_unit.compress.as_ref().map(codec_new),
                     }
                 };
+                // quotas lowered in the config are applied at once
+                if state.is_overflowed() { id_units_overflowed.push_back(id_unit) }
                 map_units.insert(unit.clone(), id_unit);
                 map_state.insert(id_unit, state);
             }
@@ -101,16 +150,24 @@ impl <'a>RepoData <'a> {
         Self { 
             map_group,
             map_state,
-            id_units_overflowed: VecDeque::with_capacity(count_units),
-            stmt_data_get: prepare::stmt_data_get(conn),
+            id_units_overflowed,
+            is_meta,
+            bytes: bytes_total,
+            bytes_max,
             stmt_data_rm_old_count: prepare::stmt_data_rm_old_count(conn),
+            stmt_data_rm_old_bytes: prepare::stmt_data_rm_old_bytes(conn),
+            stmt_data_rm_old_bytes_total: prepare::stmt_data_rm_old_bytes_total(conn),
+            stmt_data_get_count,
+            stmt_data_get_count_all: prepare::stmt_data_get_count_all(conn),
             stmt_data_push: prepare::stmt_data_push(conn),
         }
     }
 
+    // Broker metadata is dropped here when it is not stored, so neither the last records nor the live updates have it
     pub fn data_push(&mut self, data: Data<Update>) -> Option<(usize, Data<Record<Update>>)> {
         match data {
             Data::Single { group, unit, update } => {
+                let update = if self.is_meta { update } else { update.without_meta() };
                 if let Some(map_units) = self.map_group.get(&group) {
                     if let Some(id_unit) = map_units.get(&unit) {
                         if let Some(state_unit) = self.map_state.get_mut(id_unit) {
@@ -156,59 +213,127 @@ impl <'a>RepoData <'a> {
             // println!("[DB] overflow_resolve id_unit={}", id_unit);
             if let Some(state_unit) = self.map_state.get_mut(&id_unit) {
                 // println!("[DB] overflow_resolve state_unit={} count={}", id_unit, state_unit.count);
-                if let Err (err) = self.stmt_data_rm_old_count.execute(named_params! {
-                    ":id_unit": &id_unit,
-                    ":offset": &state_unit.count_min,
-                }) {
-                    // TODO: log error: SQL error
-                    println!("Db:RepoData:overflow_resolve error: {}", err);
-                } else {
-                    state_unit.count = state_unit.count.checked_sub(state_unit.count_min).unwrap_or(0);
+                if state_unit.count >= state_unit.count_max {
+                    if let Err (err) = self.stmt_data_rm_old_count.execute(named_params! {
+                        ":id_unit": &id_unit,
+                        ":offset": &state_unit.count_min,
+                    }) {
+                        // TODO: log error: SQL error
+                        println!("Db:RepoData:overflow_resolve error: {}", err);
+                    } else {
+                        state_unit.count = state_unit.count.checked_sub(state_unit.count_min).unwrap_or(0);
+                    }
+                }
+                if let Some(bytes_max) = state_unit.bytes_max.filter(|bytes_max| state_unit.bytes > *bytes_max) {
+                    let bytes_keep = bytes_max.saturating_mul(BYTES_KEEP_PERCENT) / 100;
+                    match self.stmt_data_rm_old_bytes.execute(named_params! {":id_unit": &id_unit, ":bytes_keep": &bytes_keep}) {
+                        Ok(count_rm) => {
+                            let (group, unit) = unit_name(&self.map_group, id_unit);
+                            println!("[WARN] \"{}\" / \"{}\": {} oldest records removed to fit max_bytes={}", group, unit, count_rm, bytes_max); // TODO: log this
+                        },
+                        Err(err) => println!("Db:RepoData:overflow_resolve bytes error: {}", err), // TODO: log this
+                    }
+                }
+                // both trims change the size, and the count is only approximate after the count trim
+                match self.stmt_data_get_count.query_row(named_params! {":id_unit": &id_unit}, |row| Ok((row.get::<_, u64>(0)?, row.get::<_, u64>(1)?))) {
+                    Ok((count, bytes)) => {
+                        self.bytes = (self.bytes + bytes).saturating_sub(state_unit.bytes);
+                        state_unit.count = count;
+                        state_unit.bytes = bytes;
+                    },
+                    Err(err) => println!("Db:RepoData:overflow_resolve count error: {}", err), // TODO: log this
                 }
             } else {
                 // TODO: log error: unsynchronised
                 println!("Db:RepoData:overflow_resolve unsynchronised error");
             }
         }
+        if let Some(bytes_max) = self.bytes_max.filter(|bytes_max| self.bytes > *bytes_max) {
+            self.overflow_resolve_total(bytes_max);
+        }
     }
 
-    pub fn data_get(&mut self, group: &Group, unit: &Unit, idx_min: u64, idx_max: u64) -> Result<Vec<Record<Update>>, ()> {
-        if let Some(map_unit) = self.map_group.get(group) {
-            if let Some(id_unit) = map_unit.get(unit) {
-                // :id_unit :id_record_min :id_record_max
-                let iter_res = self.stmt_data_get.query_map(named_params! {
-                    ":id_unit": id_unit,
-                    ":id_record_min": idx_min,
-                    ":id_record_max": idx_max,
-                }, |row| {
-                    // id_record, time, type, val
-                    let id_record: u64 = row.get(0)?;
-                    let time: i64 = row.get(1)?;
-                    let upd_type: u8 = row.get(2)?;
-                    let upd_val: Option<Vec<u8>> = row.get(3)?;
-                    let update = Update::from_ser(upd_type, upd_val);
-                    Ok(Record{
-                        id: id_record,
-                        is_saved: true,
-                        time: time,
-                        val: update,
-                    })
-                });
+    // The oldest records of all the units are deleted until the database fits the global cap
+    fn overflow_resolve_total(&mut self, bytes_max: u64) {
+        let bytes_keep = bytes_max.saturating_mul(BYTES_KEEP_PERCENT) / 100;
+        match self.stmt_data_rm_old_bytes_total.execute(named_params! {":bytes_keep": &bytes_keep}) {
+            Ok(count_rm) => println!("[WARN] database: {} oldest records removed to fit max_bytes={}", count_rm, bytes_max), // TODO: log this
+            Err(err) => {
+                println!("Db:RepoData:overflow_resolve_total error: {}", err); // TODO: log this
+                return;
+            },
+        }
+        let mut map_count: HashMap<u32, (u64, u64)> = HashMap::with_capacity(self.map_state.len());
+        let res = self.stmt_data_get_count_all.query_map([], |row| Ok((row.get::<_, u32>(0)?, row.get::<_, u64>(1)?, row.get::<_, u64>(2)?)))
+            .and_then(|rows| {
+                for row in rows {
+                    let (id_unit, count, bytes) = row?;
+                    map_count.insert(id_unit, (count, bytes));
+                }
+                Ok(())
+            });
+        if let Err(err) = res {
+            println!("Db:RepoData:overflow_resolve_total count error: {}", err); // TODO: log this
+            return;
+        }
+        self.bytes = 0;
+        for (id_unit, state_unit) in self.map_state.iter_mut() {
+            let (count, bytes) = map_count.get(id_unit).copied().unwrap_or((0, 0));
+            state_unit.count = count;
+            state_unit.bytes = bytes;
+            self.bytes += bytes;
+        }
+    }
 
-                match iter_res {
-                    Ok(iter) => {
-                        let vec: Vec<Record<Update>> = iter.filter_map(|record_res| record_res.ok()).collect();
-                        return Ok(vec);
-                    },
-                    Err(err) => {
-                        println!("[RepoData] data_get: rusqlite error: {}", err);
-                        return Err(());
-                    },
+    pub fn unit_id(&self, group: &Group, unit: &Unit) -> Option<u32> {
+        self.map_group.get(group).and_then(|map_unit| map_unit.get(unit)).copied()
+    }
+
+    // Inserts the record which was not saved before, e.g. because its transaction failed;
+    // a record already stored with the same id is kept
+    pub fn data_restore(&mut self, id_unit: u32, record: &Record<Update>) -> Result<(), SqlErr> {
+        self.data_reserve(id_unit, record);
+        let count = self.data_insert(id_unit, record)?;
+        if let Some(state_unit) = self.map_state.get_mut(&id_unit) {
+            state_unit.count += count as u64;
+            if let Some(record_last) = state_unit.record_last.as_mut() {
+                if record_last.id == record.id {
+                    record_last.is_saved = true;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // New records of the unit get ids after the one of the record, which is not saved yet
+    pub fn data_reserve(&mut self, id_unit: u32, record: &Record<Update>) {
+        if let Some(state_unit) = self.map_state.get_mut(&id_unit) {
+            if state_unit.record_last.as_ref().is_none_or(|record_last| record_last.id < record.id) {
+                let mut record = record.clone();
+                if !self.is_meta {
+                    record.val = record.val.without_meta();
                 }
+                state_unit.record_last = Some(record);
             }
         }
-        // TODO: check all 'else'
-        Err(()) // bad request
+    }
+
+    pub fn state_save(&self, id_units: impl Iterator<Item = u32>) -> StateSaved {
+        let units = id_units.filter_map(|id_unit| self.map_state.get(&id_unit).map(|state_unit| {
+            (id_unit, state_unit.count, state_unit.bytes, state_unit.record_last.clone())
+        })).collect();
+        StateSaved{ units, bytes: self.bytes }
+    }
+
+    pub fn state_load(&mut self, state_saved: StateSaved) {
+        for (id_unit, count, bytes, record_last) in state_saved.units {
+            if let Some(state_unit) = self.map_state.get_mut(&id_unit) {
+                state_unit.count = count;
+                state_unit.bytes = bytes;
+                state_unit.record_last = record_last;
+            }
+        }
+        self.bytes = state_saved.bytes;
     }
 
     pub fn data_last(&self, map: HashMap<Group, Vec<Unit>>) -> HashMap<Group, Vec<(Unit, Option<Record<Update>>)>> {
@@ -243,6 +368,7 @@ impl <'a>RepoData <'a> {
         if let Some(map_units) = self.map_group.get(group) {
             let mut vec_insert = Vec::with_capacity(vec_unit.len());
             for (unit, update) in vec_unit {
+                let update = if self.is_meta { update } else { update.without_meta() };
                 if let Some(id_unit) = map_units.get(&unit) {
                     if let Some(state_unit) = self.map_state.get_mut(id_unit) {
                         let id_record = if let Some(record_last) = state_unit.record_last.as_ref() { record_last.id + 1 } else { 0 };
@@ -269,14 +395,7 @@ impl <'a>RepoData <'a> {
     }
 
     fn data_push_single(&mut self, id_unit: u32, record: &mut Record<Update>) {
-        let (upd_type, upd_val) = record.val.to_ser();
-        match self.stmt_data_push.execute(named_params! {
-            ":id_unit": id_unit, 
-            ":id_record": record.id,
-            ":time": record.time,
-            ":type": upd_type,
-            ":value": upd_val,
-        }) {
+        match self.data_insert(id_unit, record) {
             Ok(_) => record.is_saved = true,
             Err(err) => {
                 // TODO: LOG
@@ -285,6 +404,65 @@ impl <'a>RepoData <'a> {
         }
     }
 
+    // Quotas account for the stored value only, the same way as LENGTH(val) does
+    fn data_insert(&mut self, id_unit: u32, record: &Record<Update>) -> Result<usize, SqlErr> {
+        let codec = self.map_state.get_mut(&id_unit).and_then(|state_unit| state_unit.codec.as_mut());
+        let (upd_type, upd_val) = record.val.to_ser_compressed(codec);
+        let meta = if self.is_meta { record.val.meta() } else { None };
+        let count = self.stmt_data_push.execute(named_params! {
+            ":id_unit": id_unit, 
+            ":id_record": record.id,
+            ":time": record.time,
+            ":type": upd_type,
+            ":value": upd_val,
+            ":topic": meta.map(|meta| meta.topic.as_str()),
+            ":qos": meta.map(|meta| meta.qos),
+            ":retain": meta.map(|meta| meta.retain),
+            ":broker": meta.map(|meta| meta.broker.as_str()),
+        })?;
+        if let Some(state_unit) = self.map_state.get_mut(&id_unit) {
+            let bytes = upd_val.map_or(0, |val| val.len() as u64);
+            state_unit.bytes += bytes;
+            self.bytes += bytes;
+            let is_overflowed = state_unit.bytes_max.is_some_and(|bytes_max| state_unit.bytes > bytes_max);
+            if is_overflowed && !self.id_units_overflowed.contains(&id_unit) { self.id_units_overflowed.push_back(id_unit) }
+        }
+        Ok(count)
+    }
+
+}
+
+impl StateUnit {
+    fn is_overflowed(&self) -> bool {
+        self.count >= self.count_max || self.bytes_max.is_some_and(|bytes_max| self.bytes > bytes_max)
+    }
+}
+
+// Reverse lookup, only used for warnings
+fn unit_name(map_group: &HashMap<Group, HashMap<Unit, u32>>, id_unit: u32) -> (&str, &str) {
+    map_group.iter()
+        .find_map(|(group, map_units)| map_units.iter()
+            .find(|(_, id)| **id == id_unit)
+            .map(|(unit, _)| (group.to_str(), unit.to_str())))
+        .unwrap_or(("?", "?"))
+}
+
+fn codec_new(cfg: &ConfigCompress) -> Codec {
+    match Codec::new(cfg) {
+        Ok(codec) => codec,
+        Err(err) => panic!("RepoData: zstd compressor error: {}", err),
+    }
+}
+
+pub fn init_schema(conn: &Connection) {
+    prepare::init(conn);
+}
+
+// Commands opening the database read-only leave migrations to 'serve'; they expect the schema init_schema makes
+pub fn schema_is_current(conn: &Connection) -> Result<bool, SqlErr> {
+    let count_objects: u32 = conn.query_row("SELECT COUNT(*) FROM sqlite_master WHERE name IN ('groups', 'units', 'data', 'annotations', 'index_data_unit_record')", [], |row| row.get(0))?;
+    let count_columns: u32 = conn.query_row("SELECT COUNT(*) FROM pragma_table_info('data') WHERE name IN ('topic', 'qos', 'retain', 'broker')", [], |row| row.get(0))?;
+    Ok(count_objects == 5 && count_columns == 4)
 }
 
 mod prepare {
@@ -305,21 +483,32 @@ mod prepare {
     }
 
     pub fn stmt_data_push<'a>(conn: &'a Connection) -> Statement<'a> {
-        unwrap(conn.prepare("INSERT INTO data (fk_data_unit, id_record, time, type, val) VALUES (:id_unit, :id_record, :time, :type, :value)"))
-    }
-    pub fn stmt_data_get<'a>(conn: &'a Connection) -> Statement<'a> {
-        unwrap(conn.prepare("SELECT id_record, time, type, val FROM data WHERE fk_data_unit = :id_unit AND id_record >= :id_record_min AND id_record <= :id_record_max"))
+        unwrap(conn.prepare("INSERT OR IGNORE INTO data (fk_data_unit, id_record, time, type, val, topic, qos, retain, broker)
+            VALUES (:id_unit, :id_record, :time, :type, :value, :topic, :qos, :retain, :broker)"))
     }
     pub fn stmt_data_get_last<'a>(conn: &'a Connection) -> Statement<'a> {
         unwrap(conn.prepare("SELECT id_record, time, type, val FROM data WHERE fk_data_unit = :id_unit ORDER BY id_record DESC LIMIT 1"))
     }
     pub fn stmt_data_get_count<'a>(conn: &'a Connection) -> Statement<'a> {
-        unwrap(conn.prepare("SELECT COUNT(*) FROM data WHERE fk_data_unit = :id_unit"))
+        unwrap(conn.prepare("SELECT COUNT(*), IFNULL(SUM(LENGTH(val)), 0) FROM data WHERE fk_data_unit = :id_unit"))
+    }
+    pub fn stmt_data_get_count_all<'a>(conn: &'a Connection) -> Statement<'a> {
+        unwrap(conn.prepare("SELECT fk_data_unit, COUNT(*), IFNULL(SUM(LENGTH(val)), 0) FROM data GROUP BY fk_data_unit"))
+    }
+    // the newest records are kept while their running size fits bytes_keep
+    pub fn stmt_data_rm_old_bytes<'a>(conn: &'a Connection) -> Statement<'a> {
+        unwrap(conn.prepare("DELETE FROM data WHERE rowid in (SELECT rowid FROM (SELECT rowid, SUM(IFNULL(LENGTH(val), 0)) OVER (ORDER BY id_record DESC) AS bytes_newer
+            FROM data WHERE fk_data_unit = :id_unit) WHERE bytes_newer > :bytes_keep)"))
+    }
+    pub fn stmt_data_rm_old_bytes_total<'a>(conn: &'a Connection) -> Statement<'a> {
+        unwrap(conn.prepare("DELETE FROM data WHERE rowid in (SELECT rowid FROM (SELECT rowid, SUM(IFNULL(LENGTH(val), 0)) OVER (ORDER BY time DESC, id_record DESC) AS bytes_newer
+            FROM data) WHERE bytes_newer > :bytes_keep)"))
     }
     pub fn stmt_data_rm_old_count<'a>(conn: &'a Connection) -> Statement<'a> {
         // DELETE FROM data WHERE rowid in (select rowid from data WHERE fk_data_unit = :id_unit ORDER BY rowid DESC limit -1 offset :offset);
         // unwrap(conn.prepare("DELETE FROM data WHERE rowid in (SELECT rowid FROM data WHERE fk_data_unit = :id_unit ORDER BY id_record ASC LIMIT :count)"))
-        unwrap(conn.prepare("DELETE FROM data WHERE rowid in (select rowid from data WHERE fk_data_unit = :id_unit ORDER BY rowid DESC limit -1 offset :offset)"))
+        // ordered by id_record, not rowid: imported records may be inserted after the newer ones
+        unwrap(conn.prepare("DELETE FROM data WHERE rowid in (select rowid from data WHERE fk_data_unit = :id_unit ORDER BY id_record DESC limit -1 offset :offset)"))
     }
 
     // To select all records from single group
@@ -350,15 +539,53 @@ mod prepare {
             type INTEGER NOT NULL,
             val BLOB,
             fk_data_unit INTEGER NOT NULL,
+            topic TEXT,
+            qos INTEGER,
+            retain INTEGER,
+            broker TEXT,
             FOREIGN KEY (fk_data_unit) REFERENCES units(id)
         )", []));
+        // message metadata columns are missing in databases created by older versions
+        for (column, column_type) in [("topic", "TEXT"), ("qos", "INTEGER"), ("retain", "INTEGER"), ("broker", "TEXT")] {
+            let count: u32 = unwrap(conn.query_row("SELECT COUNT(*) FROM pragma_table_info('data') WHERE name = ?", [column], |row| row.get(0)));
+            if count == 0 {
+                unwrap(conn.execute(&format!("ALTER TABLE data ADD COLUMN {} {}", column, column_type), []));
+            }
+        }
+        // older versions had the index not unique, so a replayed record could be stored twice
+        let count: u32 = unwrap(conn.query_row("SELECT COUNT(*) FROM sqlite_master WHERE type = 'index' AND name = 'index_data_unit_record'", [], |row| row.get(0)));
+        if count == 0 {
+            let count_rm = unwrap(conn.execute("DELETE FROM data WHERE rowid NOT IN (SELECT MIN(rowid) FROM data GROUP BY fk_data_unit, id_record)", []));
+            if count_rm > 0 {
+                println!("[WARN] database: {} duplicated records removed", count_rm); // TODO: log this
+            }
+            unwrap(conn.execute("DROP INDEX IF EXISTS index_data_record", []));
+        }
         unwrap(conn.execute(
-        "CREATE INDEX IF NOT EXISTS index_data_record ON data
+        "CREATE UNIQUE INDEX IF NOT EXISTS index_data_unit_record ON data
         (fk_data_unit, id_record)", [])); 
         unwrap(conn.execute(
         "CREATE INDEX IF NOT EXISTS index_data_time ON data
         (fk_data_unit, time)", []));
 
+        unwrap(conn.execute(
+        "CREATE TABLE IF NOT EXISTS annotations(
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            fk_annotation_group INTEGER NOT NULL,
+            fk_annotation_unit INTEGER,
+            time_min INTEGER,
+            time_max INTEGER,
+            text TEXT NOT NULL,
+            author TEXT NOT NULL,
+            time_created INTEGER NOT NULL,
+            time_modified INTEGER NOT NULL,
+            FOREIGN KEY (fk_annotation_group) REFERENCES groups(id),
+            FOREIGN KEY (fk_annotation_unit) REFERENCES units(id)
+        )", []));
+        unwrap(conn.execute(
+        "CREATE INDEX IF NOT EXISTS index_annotation_group ON annotations
+        (fk_annotation_group, fk_annotation_unit)", []));
+
         let id_group_max = unwrap(conn.query_row("SELECT id FROM groups ORDER BY id DESC LIMIT 1", [], |row| {
             let id: u32 = row.get(0)?;
             Ok(id)
@@ -0,0 +1,101 @@
+use rusqlite::{Connection, Statement, named_params, Error as SqlErr};
+
+pub use super::repo_data::schema_is_current;
+
+use crate::model::compress::Dictionaries;
+use crate::model::dataflow::{Group, Unit, Update, Record};
+
+
+// Inclusive bounds, None means unbounded
+#[derive(Default, Debug, Clone)]
+pub struct Range {
+    pub time_min: Option<i64>,
+    pub time_max: Option<i64>,
+    pub id_min: Option<u64>,
+    pub id_max: Option<u64>,
+}
+
+pub struct RepoExport<'a> {
+    dictionaries: &'a Dictionaries,
+    stmt_unit_all: Statement<'a>,
+    stmt_data_range: Statement<'a>,
+}
+impl <'a>RepoExport<'a> {
+    // The connection may be read-only, so the schema is not made here; see schema_is_current
+    pub fn new(conn: &'a Connection, dictionaries: &'a Dictionaries) -> Self {
+        Self {
+            dictionaries,
+            stmt_unit_all: prepare::stmt_unit_all(conn),
+            stmt_data_range: prepare::stmt_data_range(conn),
+        }
+    }
+
+    // (group, unit, id_unit) of all the stored units
+    pub fn units(&mut self) -> Result<Vec<(Group, Unit, u32)>, SqlErr> {
+        self.stmt_unit_all.query_map([], |row| {
+            let id_unit: u32 = row.get(0)?;
+            let name_unit: String = row.get(1)?;
+            let name_group: String = row.get(2)?;
+            Ok((Group::new(name_group), Unit::new(name_unit), id_unit))
+        })?.collect()
+    }
+
+    // Records are passed to func one by one in id_record order, so whole unit history is never held in memory;
+    // those with values not decompressed are skipped and counted
+    pub fn data_each<F, E>(&mut self, id_unit: u32, range: &Range, mut func: F) -> Result<u64, E>
+    where
+        F: FnMut(Record<Update>) -> Result<(), E>,
+        E: From<SqlErr>,
+    {
+        let mut count_skipped: u64 = 0;
+        let mut rows = self.stmt_data_range.query(named_params! {
+            ":id_unit": &id_unit,
+            ":time_min": &range.time_min,
+            ":time_max": &range.time_max,
+            ":id_record_min": &range.id_min,
+            ":id_record_max": &range.id_max,
+        })?;
+        while let Some(row) = rows.next()? {
+            let id_record: u64 = row.get(0)?;
+            let time: i64 = row.get(1)?;
+            let upd_type: u8 = row.get(2)?;
+            let upd_val: Option<Vec<u8>> = row.get(3)?;
+            let val = match Update::from_ser(upd_type, upd_val, self.dictionaries) {
+                Ok(val) => val,
+                Err(_) => {
+                    count_skipped += 1;
+                    continue;
+                },
+            };
+            func(Record{
+                id: id_record,
+                is_saved: true,
+                time,
+                val,
+            })?;
+        }
+        Ok(count_skipped)
+    }
+}
+
+
+mod prepare {
+    use rusqlite::{Connection, Statement, Error};
+
+    pub fn stmt_unit_all<'a>(conn: &'a Connection) -> Statement<'a> {
+        unwrap(conn.prepare("SELECT u.id, u.name, g.name FROM units AS u JOIN groups AS g ON g.id = u.fk_unit_group ORDER BY g.id, u.id"))
+    }
+    pub fn stmt_data_range<'a>(conn: &'a Connection) -> Statement<'a> {
+        unwrap(conn.prepare("SELECT id_record, time, type, val FROM data WHERE fk_data_unit = :id_unit
+            AND (:time_min IS NULL OR time >= :time_min) AND (:time_max IS NULL OR time <= :time_max)
+            AND (:id_record_min IS NULL OR id_record >= :id_record_min) AND (:id_record_max IS NULL OR id_record <= :id_record_max)
+            ORDER BY id_record"))
+    }
+
+    fn unwrap(res: Result<Statement, Error>) -> Statement {
+        match res {
+            Ok(stmt) => stmt,
+            Err(err) => panic!("Rusqlite: RepoExport: prepare statement error: {}", err),
+        }
+    }
+}
@@ -0,0 +1,101 @@
+use rusqlite::{Connection, Statement, named_params, Error as SqlErr};
+
+use super::repo_data::init_schema;
+use super::repo_unit::RepoUnit;
+use super::transacrion::Transaction;
+use crate::model::dataflow::{Group, Unit, Update, Record};
+
+
+pub struct StatsUnit {
+    pub id_min: u64,
+    pub id_max: u64,
+    pub time_min: i64,
+}
+
+pub struct RepoImport<'a> {
+    repo_unit: RepoUnit<'a>,
+    transaction: Transaction<'a>,
+    stmt_data_stats: Statement<'a>,
+    stmt_data_push: Statement<'a>,
+    stmt_data_trim: Statement<'a>,
+}
+impl <'a>RepoImport<'a> {
+    pub fn new(conn: &'a Connection) -> Self {
+        init_schema(conn);
+        Self {
+            repo_unit: RepoUnit::new(conn),
+            transaction: Transaction::new(conn),
+            stmt_data_stats: prepare::stmt_data_stats(conn),
+            stmt_data_push: prepare::stmt_data_push(conn),
+            stmt_data_trim: prepare::stmt_data_trim(conn),
+        }
+    }
+
+    pub fn begin(&mut self) -> Result<(), SqlErr> {
+        self.transaction.begin()
+    }
+
+    pub fn commit(&mut self) -> Result<(), SqlErr> {
+        self.transaction.commit()
+    }
+
+    pub fn rollback(&mut self) -> Result<(), SqlErr> {
+        self.transaction.rollback()
+    }
+
+    pub fn unit_ensure(&mut self, group: &Group, unit: &Unit) -> Result<u32, SqlErr> {
+        self.repo_unit.unit_ensure(group, unit)
+    }
+
+    pub fn stats(&mut self, id_unit: u32) -> Result<Option<StatsUnit>, SqlErr> {
+        self.stmt_data_stats.query_row(named_params! {":id_unit": &id_unit}, |row| {
+            let count: u64 = row.get(0)?;
+            if count == 0 {
+                return Ok(None);
+            }
+            Ok(Some(StatsUnit {
+                id_min: row.get(1)?,
+                id_max: row.get(2)?,
+                time_min: row.get(3)?,
+            }))
+        })
+    }
+
+    pub fn push(&mut self, id_unit: u32, record: &Record<Update>) -> Result<(), SqlErr> {
+        let (upd_type, upd_val) = record.val.to_ser();
+        self.stmt_data_push.execute(named_params! {
+            ":id_unit": id_unit,
+            ":id_record": record.id,
+            ":time": record.time,
+            ":type": upd_type,
+            ":value": upd_val,
+        }).map(|_| ())
+    }
+
+    // Keeps count_max records with the greatest id_record, returns the count of deleted ones
+    pub fn trim(&mut self, id_unit: u32, count_max: u64) -> Result<usize, SqlErr> {
+        self.stmt_data_trim.execute(named_params! {":id_unit": &id_unit, ":offset": &count_max})
+    }
+}
+
+
+mod prepare {
+    use rusqlite::{Connection, Statement, Error};
+
+    pub fn stmt_data_stats<'a>(conn: &'a Connection) -> Statement<'a> {
+        unwrap(conn.prepare("SELECT COUNT(*), MIN(id_record), MAX(id_record), MIN(time) FROM data WHERE fk_data_unit = :id_unit"))
+    }
+    pub fn stmt_data_push<'a>(conn: &'a Connection) -> Statement<'a> {
+        unwrap(conn.prepare("INSERT INTO data (fk_data_unit, id_record, time, type, val) VALUES (:id_unit, :id_record, :time, :type, :value)"))
+    }
+    pub fn stmt_data_trim<'a>(conn: &'a Connection) -> Statement<'a> {
+        unwrap(conn.prepare("DELETE FROM data WHERE rowid in (SELECT rowid FROM data WHERE fk_data_unit = :id_unit ORDER BY id_record DESC LIMIT -1 OFFSET :offset)"))
+    }
+
+    fn unwrap(res: Result<Statement, Error>) -> Statement {
+        match res {
+            Ok(stmt) => stmt,
+            Err(err) => panic!("Rusqlite: RepoImport: prepare statement error: {}", err),
+        }
+    }
+}
@@ -0,0 +1,208 @@
+use std::collections::{HashMap, HashSet};
+
+use rusqlite::{Connection, Statement, named_params, Error as SqlErr};
+
+use super::repo_data::init_schema;
+use super::transacrion::Transaction;
+use crate::config::ConfigServeGroup;
+use crate::model::compress::Dictionaries;
+use crate::model::dataflow::{Group, Unit, Update, Record};
+
+
+pub struct OrphanUnit {
+    pub group: Group,
+    pub unit: Unit,
+    pub id_unit: u32,
+    pub count: u64,
+}
+
+pub struct OrphanGroup {
+    pub group: Group,
+    pub id_group: u32,
+}
+
+// Groups and units stored in the database but missing from the config
+pub struct Orphans {
+    pub groups: Vec<OrphanGroup>,
+    pub units: Vec<OrphanUnit>,
+}
+impl Orphans {
+    pub fn is_empty(&self) -> bool {
+        self.groups.is_empty() && self.units.is_empty()
+    }
+
+    pub fn count_records(&self) -> u64 {
+        self.units.iter().fold(0, |acc, orphan| acc.saturating_add(orphan.count))
+    }
+}
+
+
+pub struct RepoPrune<'a> {
+    dictionaries: &'a Dictionaries,
+    transaction: Transaction<'a>,
+    stmt_group_all: Statement<'a>,
+    stmt_group_rm: Statement<'a>,
+    stmt_unit_all: Statement<'a>,
+    stmt_unit_rm: Statement<'a>,
+    stmt_data_count: Statement<'a>,
+    stmt_data_all: Statement<'a>,
+    stmt_data_rm: Statement<'a>,
+    stmt_annotation_unit_rm: Statement<'a>,
+    stmt_annotation_group_rm: Statement<'a>,
+}
+impl <'a>RepoPrune<'a> {
+    pub fn new(conn: &'a Connection, dictionaries: &'a Dictionaries) -> Self {
+        init_schema(conn);
+        Self {
+            dictionaries,
+            transaction: Transaction::new(conn),
+            stmt_group_all: prepare::stmt_group_all(conn),
+            stmt_group_rm: prepare::stmt_group_rm(conn),
+            stmt_unit_all: prepare::stmt_unit_all(conn),
+            stmt_unit_rm: prepare::stmt_unit_rm(conn),
+            stmt_data_count: prepare::stmt_data_count(conn),
+            stmt_data_all: prepare::stmt_data_all(conn),
+            stmt_data_rm: prepare::stmt_data_rm(conn),
+            stmt_annotation_unit_rm: prepare::stmt_annotation_unit_rm(conn),
+            stmt_annotation_group_rm: prepare::stmt_annotation_group_rm(conn),
+        }
+    }
+
+    pub fn orphans(&mut self, cfg_groups: &HashMap<Group, ConfigServeGroup>) -> Result<Orphans, SqlErr> {
+        // units referenced by aliases are not renamed until the next start, so they are kept too
+        let mut set_alias: HashSet<(&Group, &Unit)> = HashSet::new();
+        for (group, cfg_group) in cfg_groups.iter() {
+            for cfg_unit in cfg_group.units.values() {
+                for alias in cfg_unit.aliases.iter() {
+                    set_alias.insert((alias.group.as_ref().unwrap_or(group), &alias.unit));
+                }
+            }
+        }
+        let groups: Vec<OrphanGroup> = self.stmt_group_all.query_map([], |row| {
+            let id_group: u32 = row.get(0)?;
+            let name: String = row.get(1)?;
+            Ok(OrphanGroup{ group: Group::new(name), id_group })
+        })?.collect::<Result<Vec<OrphanGroup>, SqlErr>>()?
+            .into_iter()
+            .filter(|orphan| !cfg_groups.contains_key(&orphan.group))
+            .filter(|orphan| !set_alias.iter().any(|(group, _)| **group == orphan.group))
+            .collect();
+
+        let units_all: Vec<(Group, Unit, u32)> = self.stmt_unit_all.query_map([], |row| {
+            let id_unit: u32 = row.get(0)?;
+            let name_unit: String = row.get(1)?;
+            let name_group: String = row.get(2)?;
+            Ok((Group::new(name_group), Unit::new(name_unit), id_unit))
+        })?.collect::<Result<Vec<(Group, Unit, u32)>, SqlErr>>()?;
+
+        let mut units = Vec::new();
+        for (group, unit, id_unit) in units_all {
+            let is_configured = cfg_groups.get(&group)
+                .map(|cfg_group| cfg_group.units.contains_key(&unit))
+                .unwrap_or(false);
+            if !is_configured && !set_alias.contains(&(&group, &unit)) {
+                let count = self.stmt_data_count.query_row(named_params! {":id_unit": &id_unit}, |row| {
+                    let count: u64 = row.get(0)?;
+                    Ok(count)
+                })?;
+                units.push(OrphanUnit{ group, unit, id_unit, count });
+            }
+        }
+        Ok(Orphans{ groups, units })
+    }
+
+    // Records with values not decompressed are skipped and counted
+    pub fn data_each<F, E>(&mut self, id_unit: u32, mut func: F) -> Result<u64, E>
+    where
+        F: FnMut(Record<Update>) -> Result<(), E>,
+        E: From<SqlErr>,
+    {
+        let mut count_skipped: u64 = 0;
+        let mut rows = self.stmt_data_all.query(named_params! {":id_unit": &id_unit})?;
+        while let Some(row) = rows.next()? {
+            let id_record: u64 = row.get(0)?;
+            let time: i64 = row.get(1)?;
+            let upd_type: u8 = row.get(2)?;
+            let upd_val: Option<Vec<u8>> = row.get(3)?;
+            let val = match Update::from_ser(upd_type, upd_val, self.dictionaries) {
+                Ok(val) => val,
+                Err(_) => {
+                    count_skipped += 1;
+                    continue;
+                },
+            };
+            func(Record{
+                id: id_record,
+                is_saved: true,
+                time,
+                val,
+            })?;
+        }
+        Ok(count_skipped)
+    }
+
+    pub fn delete(&mut self, orphans: &Orphans) -> Result<(), SqlErr> {
+        self.transaction.begin()?;
+        if let Err(err) = self.delete_inner(orphans) {
+            let _ = self.transaction.rollback();
+            return Err(err);
+        }
+        self.transaction.commit()
+    }
+
+    fn delete_inner(&mut self, orphans: &Orphans) -> Result<(), SqlErr> {
+        for orphan in orphans.units.iter() {
+            self.stmt_data_rm.execute(named_params! {":id_unit": &orphan.id_unit})?;
+            self.stmt_annotation_unit_rm.execute(named_params! {":id_unit": &orphan.id_unit})?;
+            self.stmt_unit_rm.execute(named_params! {":id_unit": &orphan.id_unit})?;
+        }
+        for orphan in orphans.groups.iter() {
+            self.stmt_annotation_group_rm.execute(named_params! {":id_group": &orphan.id_group})?;
+            self.stmt_group_rm.execute(named_params! {":id_group": &orphan.id_group})?;
+        }
+        Ok(())
+    }
+}
+
+
+mod prepare {
+    use rusqlite::{Connection, Statement, Error};
+
+    pub fn stmt_group_all<'a>(conn: &'a Connection) -> Statement<'a> {
+        unwrap(conn.prepare("SELECT id, name FROM groups ORDER BY id"))
+    }
+    pub fn stmt_group_rm<'a>(conn: &'a Connection) -> Statement<'a> {
+        unwrap(conn.prepare("DELETE FROM groups WHERE id = :id_group"))
+    }
+
+    pub fn stmt_unit_all<'a>(conn: &'a Connection) -> Statement<'a> {
+        unwrap(conn.prepare("SELECT u.id, u.name, g.name FROM units AS u JOIN groups AS g ON g.id = u.fk_unit_group ORDER BY g.id, u.id"))
+    }
+    pub fn stmt_unit_rm<'a>(conn: &'a Connection) -> Statement<'a> {
+        unwrap(conn.prepare("DELETE FROM units WHERE id = :id_unit"))
+    }
+
+    pub fn stmt_data_count<'a>(conn: &'a Connection) -> Statement<'a> {
+        unwrap(conn.prepare("SELECT COUNT(*) FROM data WHERE fk_data_unit = :id_unit"))
+    }
+    pub fn stmt_data_all<'a>(conn: &'a Connection) -> Statement<'a> {
+        unwrap(conn.prepare("SELECT id_record, time, type, val FROM data WHERE fk_data_unit = :id_unit ORDER BY id_record"))
+    }
+    pub fn stmt_data_rm<'a>(conn: &'a Connection) -> Statement<'a> {
+        unwrap(conn.prepare("DELETE FROM data WHERE fk_data_unit = :id_unit"))
+    }
+
+    pub fn stmt_annotation_unit_rm<'a>(conn: &'a Connection) -> Statement<'a> {
+        unwrap(conn.prepare("DELETE FROM annotations WHERE fk_annotation_unit = :id_unit"))
+    }
+    pub fn stmt_annotation_group_rm<'a>(conn: &'a Connection) -> Statement<'a> {
+        unwrap(conn.prepare("DELETE FROM annotations WHERE fk_annotation_group = :id_group"))
+    }
+
+    fn unwrap(res: Result<Statement, Error>) -> Statement {
+        match res {
+            Ok(stmt) => stmt,
+            Err(err) => panic!("Rusqlite: RepoPrune: prepare statement error: {}", err),
+        }
+    }
+}
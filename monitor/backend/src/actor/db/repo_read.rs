@@ -0,0 +1,146 @@
+use rusqlite::{Connection, Statement, Row, named_params, Error as SqlErr, OptionalExtension, types::Type};
+
+use super::read::RangeTime;
+use super::repo_unit::RepoUnit;
+use super::repo_annotation::annotation_from_row;
+use crate::model::annotation::Annotation;
+use crate::model::compress::Dictionaries;
+use crate::model::dataflow::{Group, Unit, Meta, Update, Record};
+
+
+pub struct RepoRead<'a> {
+    dictionaries: &'a Dictionaries,
+    repo_unit: RepoUnit<'a>,
+    stmt_data_get: Statement<'a>,
+    stmt_data_get_time: Statement<'a>,
+    stmt_annotation_list: Statement<'a>,
+    stmt_annotation_get: Statement<'a>,
+}
+impl <'a>RepoRead<'a> {
+    pub fn new(conn: &'a Connection, dictionaries: &'a Dictionaries) -> Self {
+        Self {
+            dictionaries,
+            repo_unit: RepoUnit::new(conn),
+            stmt_data_get: prepare::stmt_data_get(conn),
+            stmt_data_get_time: prepare::stmt_data_get_time(conn),
+            stmt_annotation_list: prepare::stmt_annotation_list(conn),
+            stmt_annotation_get: prepare::stmt_annotation_get(conn),
+        }
+    }
+
+    pub fn unit_id(&mut self, group: &Group, unit: &Unit) -> Result<Option<u32>, SqlErr> {
+        self.repo_unit.unit_id(group, unit)
+    }
+
+    pub fn data_get(&mut self, id_unit: u32, idx_min: u64, idx_max: u64) -> Result<Vec<Record<Update>>, SqlErr> {
+        let iter = self.stmt_data_get.query_map(named_params! {
+            ":id_unit": id_unit,
+            ":id_record_min": idx_min,
+            ":id_record_max": idx_max,
+        }, |row| record_from_row(row, self.dictionaries))?;
+        Ok(iter.filter_map(|record_res| record_res.ok()).collect())
+    }
+
+    pub fn data_get_time(&mut self, id_unit: u32, range: &RangeTime) -> Result<Vec<Record<Update>>, SqlErr> {
+        let (after_time, after_id) = match range.after {
+            Some((time, id_record)) => (Some(time), Some(id_record)),
+            None => (None, None),
+        };
+        let iter = self.stmt_data_get_time.query_map(named_params! {
+            ":id_unit": id_unit,
+            ":time_min": range.time_min,
+            ":time_max": range.time_max,
+            ":after_time": after_time,
+            ":after_id": after_id,
+            ":limit": range.limit,
+        }, |row| record_from_row(row, self.dictionaries))?;
+        Ok(iter.filter_map(|record_res| record_res.ok()).collect())
+    }
+
+    // Annotations of the group overlapping [time_min, time_max]; with a unit given, only its own and the group-level ones
+    pub fn annotation_list(&mut self, group: &Group, unit: Option<&Unit>, time_min: Option<i64>, time_max: Option<i64>) -> Result<Vec<Annotation>, SqlErr> {
+        let iter = self.stmt_annotation_list.query_map(named_params! {
+            ":group": group.to_str(),
+            ":unit": unit.map(|unit| unit.to_str()),
+            ":time_min": time_min,
+            ":time_max": time_max,
+        }, annotation_from_row)?;
+        iter.collect()
+    }
+
+    pub fn annotation_get(&mut self, id: u64) -> Result<Option<Annotation>, SqlErr> {
+        self.stmt_annotation_get.query_row(named_params! {":id": id}, annotation_from_row).optional()
+    }
+}
+
+
+// Records with values not decompressed are errors, so they are left out of the results
+fn record_from_row(row: &Row, dictionaries: &Dictionaries) -> Result<Record<Update>, SqlErr> {
+    let id: u64 = row.get(0)?;
+    let upd_type: u8 = row.get(2)?;
+    let upd_val: Option<Vec<u8>> = row.get(3)?;
+    let mut val = Update::from_ser(upd_type, upd_val, dictionaries).map_err(|err| {
+        println!("[ERR] record {} is not read: its value is not decompressed: {}", id, err); // TODO: log this
+        SqlErr::FromSqlConversionFailure(3, Type::Blob, Box::new(err))
+    })?;
+    if let Update::Value{meta, ..} = &mut val {
+        *meta = meta_from_row(row)?;
+    }
+    Ok(Record{
+        id,
+        is_saved: true,
+        time: row.get(1)?,
+        val,
+    })
+}
+
+// Metadata is either saved as a whole or not at all
+fn meta_from_row(row: &Row) -> Result<Option<Meta>, SqlErr> {
+    let topic: Option<String> = row.get(4)?;
+    match topic {
+        Some(topic) => Ok(Some(Meta{
+            topic,
+            qos: row.get(5)?,
+            retain: row.get(6)?,
+            broker: row.get(7)?,
+        })),
+        None => Ok(None),
+    }
+}
+
+
+mod prepare {
+    use rusqlite::{Connection, Statement, Error};
+
+    use super::super::repo_annotation::SELECT_ANNOTATION;
+
+    pub fn stmt_data_get<'a>(conn: &'a Connection) -> Statement<'a> {
+        unwrap(conn.prepare("SELECT id_record, time, type, val, topic, qos, retain, broker FROM data WHERE fk_data_unit = :id_unit AND id_record >= :id_record_min AND id_record <= :id_record_max"))
+    }
+    pub fn stmt_data_get_time<'a>(conn: &'a Connection) -> Statement<'a> {
+        // uses index_data_time; (time, id_record) is the paging key, so records with equal time are not lost between pages
+        unwrap(conn.prepare("SELECT id_record, time, type, val, topic, qos, retain, broker FROM data WHERE fk_data_unit = :id_unit
+            AND (:time_min IS NULL OR time >= :time_min) AND (:time_max IS NULL OR time <= :time_max)
+            AND (:after_time IS NULL OR time > :after_time OR (time = :after_time AND id_record > :after_id))
+            ORDER BY time, id_record LIMIT :limit"))
+    }
+
+    pub fn stmt_annotation_list<'a>(conn: &'a Connection) -> Statement<'a> {
+        // an annotation without time bounds is open-ended on that side
+        unwrap(conn.prepare(&format!("{} WHERE g.name = :group
+            AND (:unit IS NULL OR a.fk_annotation_unit IS NULL OR u.name = :unit)
+            AND (:time_max IS NULL OR a.time_min IS NULL OR a.time_min <= :time_max)
+            AND (:time_min IS NULL OR a.time_max IS NULL OR a.time_max >= :time_min)
+            ORDER BY a.time_min, a.id", SELECT_ANNOTATION)))
+    }
+    pub fn stmt_annotation_get<'a>(conn: &'a Connection) -> Statement<'a> {
+        unwrap(conn.prepare(&format!("{} WHERE a.id = :id", SELECT_ANNOTATION)))
+    }
+
+    fn unwrap(res: Result<Statement, Error>) -> Statement {
+        match res {
+            Ok(stmt) => stmt,
+            Err(err) => panic!("Rusqlite: RepoRead: prepare statement error: {}", err),
+        }
+    }
+}
@@ -0,0 +1,118 @@
+use rusqlite::{Connection, Statement, named_params, Error as SqlErr};
+
+use super::repo_data::init_schema;
+use super::repo_unit::RepoUnit;
+use super::transacrion::Transaction;
+use crate::model::compress::{Codec, Dictionaries};
+use crate::model::dataflow::{Group, Unit, Update};
+
+
+// Rows are read and rewritten by batches, so whole unit history is never held in memory
+const BATCH_ROWS: u32 = 1000;
+
+#[derive(Default)]
+pub struct StatsRecompress {
+    pub count: u64,
+    pub count_rewritten: u64,
+    pub count_skipped: u64,
+    pub bytes_before: u64,
+    pub bytes_after: u64,
+}
+
+pub struct RepoRecompress<'a> {
+    dictionaries: &'a Dictionaries,
+    repo_unit: RepoUnit<'a>,
+    transaction: Transaction<'a>,
+    stmt_data_values: Statement<'a>,
+    stmt_data_set: Statement<'a>,
+}
+impl <'a>RepoRecompress<'a> {
+    pub fn new(conn: &'a Connection, dictionaries: &'a Dictionaries) -> Self {
+        init_schema(conn);
+        Self {
+            dictionaries,
+            repo_unit: RepoUnit::new(conn),
+            transaction: Transaction::new(conn),
+            stmt_data_values: prepare::stmt_data_values(conn),
+            stmt_data_set: prepare::stmt_data_set(conn),
+        }
+    }
+
+    pub fn unit_id(&mut self, group: &Group, unit: &Unit) -> Result<Option<u32>, SqlErr> {
+        self.repo_unit.unit_id(group, unit)
+    }
+
+    // Values are stored again with the codec, or uncompressed without it, in a single transaction
+    pub fn recompress(&mut self, id_unit: u32, mut codec: Option<&mut Codec>) -> Result<StatsRecompress, SqlErr> {
+        self.transaction.begin()?;
+        match self.recompress_inner(id_unit, &mut codec) {
+            Ok(stats) => {
+                self.transaction.commit()?;
+                Ok(stats)
+            },
+            Err(err) => {
+                let _ = self.transaction.rollback();
+                Err(err)
+            },
+        }
+    }
+
+    fn recompress_inner(&mut self, id_unit: u32, codec: &mut Option<&mut Codec>) -> Result<StatsRecompress, SqlErr> {
+        let mut stats = StatsRecompress::default();
+        let mut rowid_last: i64 = -1;
+        loop {
+            let batch: Vec<(i64, u8, Option<Vec<u8>>)> = self.stmt_data_values.query_map(named_params! {
+                ":id_unit": &id_unit,
+                ":rowid": &rowid_last,
+                ":limit": &BATCH_ROWS,
+            }, |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?.collect::<Result<_, _>>()?;
+            if batch.is_empty() {
+                return Ok(stats);
+            }
+            for (rowid, upd_type, upd_val) in batch {
+                rowid_last = rowid;
+                stats.count += 1;
+                stats.bytes_before += upd_val.as_ref().map_or(0, |val| val.len() as u64);
+                // values which are not decompressed, e.g. without their dictionary, are left as they are
+                let update = match Update::from_ser(upd_type, upd_val.clone(), self.dictionaries) {
+                    Ok(update) => update,
+                    Err(_) => {
+                        stats.count_skipped += 1;
+                        stats.bytes_after += upd_val.as_ref().map_or(0, |val| val.len() as u64);
+                        continue;
+                    },
+                };
+                let (upd_type_new, upd_val_new) = update.to_ser_compressed(codec.as_deref_mut());
+                stats.bytes_after += upd_val_new.as_ref().map_or(0, |val| val.len() as u64);
+                if upd_type_new != upd_type || upd_val_new.as_deref() != upd_val.as_deref() {
+                    self.stmt_data_set.execute(named_params! {
+                        ":rowid": &rowid,
+                        ":type": &upd_type_new,
+                        ":value": &upd_val_new,
+                    })?;
+                    stats.count_rewritten += 1;
+                }
+            }
+        }
+    }
+}
+
+
+mod prepare {
+    use rusqlite::{Connection, Statement, Error};
+
+    // only values, online and offline records have nothing to compress
+    pub fn stmt_data_values<'a>(conn: &'a Connection) -> Statement<'a> {
+        unwrap(conn.prepare("SELECT rowid, type, val FROM data WHERE fk_data_unit = :id_unit AND type >= 2 AND rowid > :rowid ORDER BY rowid LIMIT :limit"))
+    }
+    pub fn stmt_data_set<'a>(conn: &'a Connection) -> Statement<'a> {
+        unwrap(conn.prepare("UPDATE data SET type = :type, val = :value WHERE rowid = :rowid"))
+    }
+
+    fn unwrap(res: Result<Statement, Error>) -> Statement {
+        match res {
+            Ok(stmt) => stmt,
+            Err(err) => panic!("Rusqlite: RepoRecompress: prepare statement error: {}", err),
+        }
+    }
+}
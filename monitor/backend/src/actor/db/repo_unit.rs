@@ -0,0 +1,168 @@
+use std::fmt;
+
+use rusqlite::{Connection, Statement, named_params, Error as SqlErr, OptionalExtension};
+
+use super::repo_data::init_schema;
+use super::transacrion::Transaction;
+use crate::model::dataflow::{Group, Unit};
+
+
+pub struct RepoUnit<'a> {
+    stmt_group_get: Statement<'a>,
+    stmt_group_set: Statement<'a>,
+    stmt_group_max: Statement<'a>,
+    stmt_unit_get: Statement<'a>,
+    stmt_unit_set: Statement<'a>,
+    stmt_unit_max: Statement<'a>,
+    stmt_unit_move: Statement<'a>,
+    stmt_annotation_move: Statement<'a>,
+    transaction: Transaction<'a>,
+}
+impl <'a>RepoUnit<'a> {
+    pub fn new(conn: &'a Connection) -> Self {
+        Self {
+            stmt_group_get: prepare::stmt_group_get(conn),
+            stmt_group_set: prepare::stmt_group_set(conn),
+            stmt_group_max: prepare::stmt_group_max(conn),
+            stmt_unit_get: prepare::stmt_unit_get(conn),
+            stmt_unit_set: prepare::stmt_unit_set(conn),
+            stmt_unit_max: prepare::stmt_unit_max(conn),
+            stmt_unit_move: prepare::stmt_unit_move(conn),
+            stmt_annotation_move: prepare::stmt_annotation_move(conn),
+            transaction: Transaction::new(conn),
+        }
+    }
+
+    // For commands, which may be run on a new database file before the server made its schema
+    pub fn new_init(conn: &'a Connection) -> Self {
+        init_schema(conn);
+        Self::new(conn)
+    }
+
+    pub fn group_id(&mut self, group: &Group) -> Result<Option<u32>, SqlErr> {
+        self.stmt_group_get.query_row(named_params! {":name": group.to_str()}, |row| {
+            let id: u32 = row.get(0)?;
+            Ok(id)
+        }).optional()
+    }
+
+    pub fn unit_id(&mut self, group: &Group, unit: &Unit) -> Result<Option<u32>, SqlErr> {
+        if let Some(id_group) = self.group_id(group)? {
+            self.stmt_unit_get.query_row(named_params! {":name": unit.to_str(), ":id_group": &id_group}, |row| {
+                let id: u32 = row.get(0)?;
+                Ok(id)
+            }).optional()
+        } else {
+            Ok(None)
+        }
+    }
+
+    pub fn unit_ensure(&mut self, group: &Group, unit: &Unit) -> Result<u32, SqlErr> {
+        let id_group = self.group_ensure(group)?;
+        if let Some(id_unit) = self.unit_id(group, unit)? {
+            return Ok(id_unit);
+        }
+        let id_unit_max: Option<u32> = self.stmt_unit_max.query_row([], |row| row.get(0))?;
+        let id_unit = if let Some(id) = id_unit_max { id + 1 } else { 0 };
+        self.stmt_unit_set.execute(named_params! {":id": &id_unit, ":id_group": &id_group, ":name": unit.to_str()})?;
+        Ok(id_unit)
+    }
+
+    // Rewrites the units row in place, so id_unit and all the records of the unit are preserved;
+    // annotations of the unit follow it to the new group in the same transaction
+    pub fn unit_rename(&mut self, group_from: &Group, unit_from: &Unit, group_to: &Group, unit_to: &Unit) -> Result<u32, RenameError> {
+        if group_from == group_to && unit_from == unit_to {
+            return Err(RenameError::Unchanged);
+        }
+        if self.unit_id(group_to, unit_to)?.is_some() {
+            return Err(RenameError::TargetExists);
+        }
+        let id_unit = self.unit_id(group_from, unit_from)?.ok_or(RenameError::SourceMissing)?;
+        self.transaction.begin()?;
+        if let Err(err) = self.unit_move(id_unit, group_to, unit_to) {
+            let _ = self.transaction.rollback();
+            return Err(err.into());
+        }
+        self.transaction.commit()?;
+        Ok(id_unit)
+    }
+
+    fn unit_move(&mut self, id_unit: u32, group_to: &Group, unit_to: &Unit) -> Result<(), SqlErr> {
+        let id_group = self.group_ensure(group_to)?;
+        self.stmt_unit_move.execute(named_params! {":id": &id_unit, ":id_group": &id_group, ":name": unit_to.to_str()})?;
+        self.stmt_annotation_move.execute(named_params! {":id": &id_unit, ":id_group": &id_group})?;
+        Ok(())
+    }
+
+    fn group_ensure(&mut self, group: &Group) -> Result<u32, SqlErr> {
+        if let Some(id_group) = self.group_id(group)? {
+            return Ok(id_group);
+        }
+        let id_group_max: Option<u32> = self.stmt_group_max.query_row([], |row| row.get(0))?;
+        let id_group = if let Some(id) = id_group_max { id + 1 } else { 0 };
+        self.stmt_group_set.execute(named_params! {":id": &id_group, ":name": group.to_str()})?;
+        Ok(id_group)
+    }
+}
+
+
+#[derive(Debug)]
+pub enum RenameError {
+    Sql(SqlErr),
+    Unchanged,
+    SourceMissing,
+    TargetExists,
+}
+impl From<SqlErr> for RenameError {
+    fn from(err: SqlErr) -> Self {
+        Self::Sql(err)
+    }
+}
+impl fmt::Display for RenameError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RenameError::Sql(err) => write!(f, "database error: {}", err),
+            RenameError::Unchanged => write!(f, "new group and unit names are the same as the current ones"),
+            RenameError::SourceMissing => write!(f, "unit to rename is not found in the database"),
+            RenameError::TargetExists => write!(f, "unit with the new name already exists in the database"),
+        }
+    }
+}
+
+
+mod prepare {
+    use rusqlite::{Connection, Statement, Error};
+
+    pub fn stmt_group_get<'a>(conn: &'a Connection) -> Statement<'a> {
+        unwrap(conn.prepare("SELECT id FROM groups WHERE name = :name LIMIT 1"))
+    }
+    pub fn stmt_group_set<'a>(conn: &'a Connection) -> Statement<'a> {
+        unwrap(conn.prepare("INSERT INTO groups (id, name) VALUES (:id, :name)"))
+    }
+    pub fn stmt_group_max<'a>(conn: &'a Connection) -> Statement<'a> {
+        unwrap(conn.prepare("SELECT MAX(id) FROM groups"))
+    }
+
+    pub fn stmt_unit_get<'a>(conn: &'a Connection) -> Statement<'a> {
+        unwrap(conn.prepare("SELECT id FROM units WHERE name = :name AND fk_unit_group = :id_group LIMIT 1"))
+    }
+    pub fn stmt_unit_set<'a>(conn: &'a Connection) -> Statement<'a> {
+        unwrap(conn.prepare("INSERT INTO units (id, fk_unit_group, name) VALUES (:id, :id_group, :name)"))
+    }
+    pub fn stmt_unit_max<'a>(conn: &'a Connection) -> Statement<'a> {
+        unwrap(conn.prepare("SELECT MAX(id) FROM units"))
+    }
+    pub fn stmt_unit_move<'a>(conn: &'a Connection) -> Statement<'a> {
+        unwrap(conn.prepare("UPDATE units SET fk_unit_group = :id_group, name = :name WHERE id = :id"))
+    }
+    pub fn stmt_annotation_move<'a>(conn: &'a Connection) -> Statement<'a> {
+        unwrap(conn.prepare("UPDATE annotations SET fk_annotation_group = :id_group WHERE fk_annotation_unit = :id"))
+    }
+
+    fn unwrap(res: Result<Statement, Error>) -> Statement {
+        match res {
+            Ok(stmt) => stmt,
+            Err(err) => panic!("Rusqlite: RepoUnit: prepare statement error: {}", err),
+        }
+    }
+}
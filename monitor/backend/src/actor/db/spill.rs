@@ -0,0 +1,122 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, BufRead, BufReader, Write},
+    path::PathBuf,
+};
+
+use bytes::Bytes;
+use serde::{Deserialize, Serialize};
+
+use crate::config::ConfigServeDbSpill;
+use crate::model::dataflow::{Group, Unit, Value, Meta, Update, Record};
+
+
+// Record which is not saved to the database yet, one JSON line of the spill file
+#[derive(Serialize, Deserialize)]
+pub struct SpillRecord {
+    #[serde(rename = "g")]
+    pub group: Group,
+    #[serde(rename = "u")]
+    pub unit: Unit,
+    #[serde(rename = "i")]
+    id: u64,
+    #[serde(rename = "t")]
+    time: i64,
+    #[serde(rename = "y")]
+    upd_type: u8,
+    #[serde(rename = "v", default)]
+    upd_val: Option<String>,
+    #[serde(rename = "m", default, skip_serializing_if = "Option::is_none")]
+    meta: Option<Meta>,
+}
+impl SpillRecord {
+    pub fn new(group: &Group, unit: &Unit, record: &Record<Update>) -> Self {
+        let (upd_type, upd_val) = record.val.to_ser();
+        Self {
+            group: group.clone(),
+            unit: unit.clone(),
+            id: record.id,
+            time: record.time,
+            upd_type,
+            upd_val: upd_val.map(base64::encode),
+            meta: record.val.meta().cloned(),
+        }
+    }
+
+    pub fn to_record(&self) -> Record<Update> {
+        let val = match (self.upd_type, self.upd_val.as_ref().and_then(|text| base64::decode(text).ok())) {
+            (0, _) => Update::Offline,
+            (1, _) => Update::Online,
+            (_, bytes) => Update::Value{ value: Value::new(Bytes::from(bytes.unwrap_or_default())), meta: self.meta.clone() },
+        };
+        Record{ id: self.id, is_saved: false, time: self.time, val }
+    }
+}
+
+// Bounded append-only file; it survives restarts, so the records are retried by the next process too
+pub struct Spill {
+    path: PathBuf,
+    count: usize,
+    count_max: usize,
+    count_alert: usize,
+}
+impl Spill {
+    pub fn new(cfg: ConfigServeDbSpill, file_db: &str) -> Self {
+        let path = PathBuf::from(cfg.file.unwrap_or_else(|| format!("{}.spill", file_db)));
+        let mut spill = Self { path, count: 0, count_max: cfg.count_max, count_alert: cfg.count_alert.max(1) };
+        match spill.load() {
+            Ok(vec) => spill.count = vec.len(),
+            Err(err) => println!("[ERR] spill file {} is not readable: {}", spill.path.display(), err), // TODO: log this
+        }
+        spill
+    }
+
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    // Returns the count of records dropped because the spill is full
+    pub fn push(&mut self, vec: Vec<SpillRecord>) -> io::Result<usize> {
+        let count_free = self.count_max.saturating_sub(self.count);
+        let count_dropped = vec.len().saturating_sub(count_free);
+        let mut buf: Vec<u8> = Vec::new();
+        for spill_record in vec.iter().take(count_free) {
+            serde_json::to_writer(&mut buf, spill_record)?;
+            buf.push(b'\n');
+        }
+        let mut file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+        file.write_all(&buf)?;
+        file.sync_data()?;
+        let count_prev = self.count;
+        self.count += vec.len() - count_dropped;
+        if self.count / self.count_alert > count_prev / self.count_alert {
+            println!("[WARN] spill buffer {} grows: {} of {} records are waiting to be saved", self.path.display(), self.count, self.count_max); // TODO: log this
+        }
+        Ok(count_dropped)
+    }
+
+    pub fn load(&self) -> io::Result<Vec<SpillRecord>> {
+        let file = match File::open(&self.path) {
+            Ok(file) => file,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => return Err(err),
+        };
+        let mut vec = Vec::new();
+        for line in BufReader::new(file).lines() {
+            let line = line?;
+            if !line.trim().is_empty() {
+                vec.push(serde_json::from_str(&line)?);
+            }
+        }
+        Ok(vec)
+    }
+
+    pub fn clear(&mut self) -> io::Result<()> {
+        match std::fs::remove_file(&self.path) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err),
+            _ => {},
+        }
+        self.count = 0;
+        Ok(())
+    }
+}
@@ -9,7 +9,7 @@ use crate::actor::{
     sub::Sub,
     db::{Signal as SignalDb, FromDist as FromDistDb}
 };
-use crate::model::dataflow::{Group, Unit, Value, Update, Data};
+use crate::model::dataflow::{Group, Unit, Value, Meta, Update, Data};
 use crate::config::{ConfigServeGroup, ConfigMqttClient};
 
 
@@ -20,7 +20,7 @@ pub struct Signal {
 }
 #[derive(Debug)]
 pub enum Payload {
-    Data{topic: String, message: Bytes},
+    Data{topic: String, message: Bytes, qos: u8, retain: bool},
     Offline,
     Online,
     Closed,
@@ -31,6 +31,7 @@ struct StateGroup {
     group: Group,
     map_unit: IndexMap<String, StateUnit>,
     config_client: Option<ConfigMqttClient>,
+    broker: String,
 }
 struct StateUnit {
     unit: Unit,
@@ -56,6 +57,7 @@ impl Dist {
                 group: group.clone(),
                 map_unit,
                 config_client: Some(cfg_serve.client.clone()),
+                broker: format!("{}:{}", Into::<String>::into(cfg_serve.client.host.clone()), cfg_serve.client.port),
             };
             map.insert(idx, cfg);
             idx += 1;
@@ -103,7 +105,7 @@ impl Dist {
         } else {
             while let Some(signal) = self.rx.recv().await {
                 match signal.payload {
-                    Payload::Data { topic, message } => self.serve_data(signal.id_broker, topic, message).await,
+                    Payload::Data { topic, message, qos, retain } => self.serve_data(signal.id_broker, topic, message, qos, retain).await,
                     Payload::Offline => self.serve_broker_fill(&signal.id_broker, Update::Offline).await,
                     Payload::Online => self.serve_broker_fill(&signal.id_broker, Update::Online).await,
                     Payload::Closed => self.close(),
@@ -113,15 +115,16 @@ impl Dist {
         self.destruct().await;
     }
 
-    async fn serve_data(&mut self, id_broker: u32, topic: String, message: Bytes) {
+    async fn serve_data(&mut self, id_broker: u32, topic: String, message: Bytes, qos: u8, retain: bool) {
         if let Some(state_group) = self.map.get(&id_broker) {
             if let Some(state_unit) = state_group.map_unit.get(&topic) {
                 let group = state_group.group.clone();
                 let unit = state_unit.unit.clone();
+                let meta = Meta{ topic, qos, retain, broker: state_group.broker.clone() };
                 let _ = self.send_out(FromDistDb::Data(Data::Single {
                     group, 
                     unit, 
-                    update: Update::Value{value: Value::new(message)},
+                    update: Update::Value{value: Value::new(message), meta: Some(meta)},
                 })).await;
             }
         }
@@ -102,7 +102,7 @@ impl Sub {
                         self.is_online = true;
                         let _ = self.send_dist(PayloadDist::Online).await;
                     }
-                    let _ = self.send_dist(PayloadDist::Data{topic: msg.topic, message: msg.payload}).await;
+                    let _ = self.send_dist(PayloadDist::Data{topic: msg.topic, message: msg.payload, qos: msg.qos as u8, retain: msg.retain}).await;
                 },
                 Incoming::Disconnect => if self.is_online {
                     self.is_online = false;
@@ -1,11 +1,16 @@
 use std::{
     net::SocketAddr,
+    path::PathBuf,
     error::Error,
 };
 
+use chrono::DateTime;
+use chrono_tz::Tz;
 use clap::{Parser, Subcommand, Args};
 
 use crate::config::ConfigServe;
+use crate::cmd::{export::Format as ExportFormat, import::Format as ImportFormat, password::Format as PasswordFormat};
+use crate::model::dataflow::{Group, Unit};
 
 
 #[derive(Parser, Debug)]
@@ -19,6 +24,37 @@ pub struct Cli {
 #[derive(Subcommand, Debug)]
 pub enum Commands {
     Serve(Serve),
+    /// List groups and units which are no longer configured and delete them with their records
+    Prune(Prune),
+    /// Rename a unit or move it to another group, keeping its stored history
+    RenameUnit(RenameUnit),
+    /// Export stored records to CSV, JSONL or Parquet file
+    Export(Export),
+    /// Import records from CSV or JSONL file to backfill the history
+    Import(Import),
+    /// Make a consistent snapshot of the database, also while it is being served
+    Backup(Backup),
+    /// Rewrite stored values with the current compress settings of their units
+    Recompress(Recompress),
+    /// Hash a password read from stdin for the 'password' field of a user file
+    HashPassword(HashPassword),
+    /// Manage user files of 'dir.users'
+    #[clap(subcommand)]
+    User(User),
+}
+
+#[derive(Subcommand, Debug)]
+pub enum User {
+    /// List users with their wplaces
+    List(UserList),
+    /// Add a user; the password is read from stdin
+    Add(UserAdd),
+    /// Change the password of a user; it is read from stdin
+    Passwd(UserPasswd),
+    /// Remove a user
+    Remove(UserRemove),
+    /// Change the wplace of a user
+    SetWplace(UserSetWplace),
 }
 
 
@@ -27,9 +63,168 @@ pub struct Serve {
     pub address: SocketAddr,
     #[clap(parse(try_from_str = parse_config_main))]
     pub config: ConfigServe,
+    /// Delete groups and units which are no longer configured before serving
+    #[clap(long)]
+    pub prune: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct Prune {
+    #[clap(parse(try_from_str = parse_config_main))]
+    pub config: ConfigServe,
+    /// Do not ask for confirmation
+    #[clap(short, long)]
+    pub yes: bool,
+    /// Export records of the pruned units to the JSONL file before deletion
+    #[clap(long)]
+    pub archive: Option<PathBuf>,
+}
+
+#[derive(Args, Debug)]
+#[clap(group(clap::ArgGroup::new("target").required(true).multiple(true).args(&["to-group", "to-unit"])))]
+pub struct RenameUnit {
+    #[clap(parse(try_from_str = parse_config_main))]
+    pub config: ConfigServe,
+    #[clap(parse(from_str = parse_group))]
+    pub group: Group,
+    #[clap(parse(from_str = parse_unit))]
+    pub unit: Unit,
+    /// Group to move the unit to
+    #[clap(long, parse(from_str = parse_group))]
+    pub to_group: Option<Group>,
+    /// New name of the unit
+    #[clap(long, parse(from_str = parse_unit))]
+    pub to_unit: Option<Unit>,
+}
+
+#[derive(Args, Debug)]
+pub struct Export {
+    #[clap(parse(try_from_str = parse_config_main))]
+    pub config: ConfigServe,
+    #[clap(long, arg_enum)]
+    pub format: ExportFormat,
+    /// File to write records to
+    #[clap(short, long)]
+    pub output: PathBuf,
+    /// Export only given groups; all groups if omitted
+    #[clap(long, parse(from_str = parse_group))]
+    pub group: Vec<Group>,
+    /// Export only given units; all units if omitted
+    #[clap(long, parse(from_str = parse_unit))]
+    pub unit: Vec<Unit>,
+    /// Lower time bound: RFC 3339 date-time or milliseconds since epoch
+    #[clap(long, parse(try_from_str = parse_time))]
+    pub from: Option<i64>,
+    /// Upper time bound: RFC 3339 date-time or milliseconds since epoch
+    #[clap(long, parse(try_from_str = parse_time))]
+    pub to: Option<i64>,
+    /// Lower id_record bound
+    #[clap(long)]
+    pub id_min: Option<u64>,
+    /// Upper id_record bound
+    #[clap(long)]
+    pub id_max: Option<u64>,
+    /// IANA timezone for the time_zoned column
+    #[clap(long, default_value = "UTC")]
+    pub tz: Tz,
+}
+
+#[derive(Args, Debug)]
+pub struct Import {
+    #[clap(parse(try_from_str = parse_config_main))]
+    pub config: ConfigServe,
+    #[clap(long, arg_enum)]
+    pub format: ImportFormat,
+    /// File with group, unit, time, type, value and encoding columns
+    pub input: PathBuf,
+    /// Import even if the database is locked by a running serve process
+    #[clap(long)]
+    pub force: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct Backup {
+    #[clap(parse(try_from_str = parse_config_main))]
+    pub config: ConfigServe,
+    /// Snapshot file; by default it is written to 'db.backup.dir' with rotation
+    #[clap(short, long)]
+    pub output: Option<PathBuf>,
+}
+
+#[derive(Args, Debug)]
+pub struct Recompress {
+    #[clap(parse(try_from_str = parse_config_main))]
+    pub config: ConfigServe,
+    /// Recompress even if the database is locked by a running serve process
+    #[clap(long)]
+    pub force: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct HashPassword {
+    #[clap(long, arg_enum, default_value = "argon2id")]
+    pub format: PasswordFormat,
+}
+
+#[derive(Args, Debug)]
+pub struct UserList {
+    #[clap(parse(try_from_str = parse_config_main))]
+    pub config: ConfigServe,
+}
+
+#[derive(Args, Debug)]
+pub struct UserAdd {
+    #[clap(parse(try_from_str = parse_config_main))]
+    pub config: ConfigServe,
+    pub login: String,
+    /// Name of the wplace file in 'dir.wplaces' without extension
+    pub wplace: String,
+    #[clap(long, arg_enum, default_value = "argon2id")]
+    pub format: PasswordFormat,
+}
+
+#[derive(Args, Debug)]
+pub struct UserPasswd {
+    #[clap(parse(try_from_str = parse_config_main))]
+    pub config: ConfigServe,
+    pub login: String,
+    #[clap(long, arg_enum, default_value = "argon2id")]
+    pub format: PasswordFormat,
+}
+
+#[derive(Args, Debug)]
+pub struct UserRemove {
+    #[clap(parse(try_from_str = parse_config_main))]
+    pub config: ConfigServe,
+    pub login: String,
+}
+
+#[derive(Args, Debug)]
+pub struct UserSetWplace {
+    #[clap(parse(try_from_str = parse_config_main))]
+    pub config: ConfigServe,
+    pub login: String,
+    /// Name of the wplace file in 'dir.wplaces' without extension
+    pub wplace: String,
+}
+
+fn parse_time(s: &str) -> Result<i64, Box<dyn Error + Send + Sync + 'static>> {
+    if let Ok(millis) = s.parse::<i64>() {
+        Ok(millis)
+    } else {
+        Ok(DateTime::parse_from_rfc3339(s)?.timestamp_millis())
+    }
+}
+
+fn parse_group(s: &str) -> Group {
+    Group::new(s.to_string())
+}
+
+fn parse_unit(s: &str) -> Unit {
+    Unit::new(s.to_string())
 }
 
 fn parse_config_main(s: &str) -> Result<ConfigServe, Box<dyn Error + Send + Sync + 'static>> {
     let config: ConfigServe = serde_json::from_str::<ConfigServe>( &std::fs::read_to_string(s)? )?;
     Ok(config)
-}
\ No newline at end of file
+}
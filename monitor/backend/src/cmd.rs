@@ -0,0 +1,8 @@
+pub mod prune;
+pub mod rename;
+pub mod export;
+pub mod import;
+pub mod backup;
+pub mod recompress;
+pub mod password;
+pub mod user;
@@ -0,0 +1,22 @@
+use std::path::PathBuf;
+
+use rusqlite::Connection;
+
+use crate::config::ConfigServe;
+use crate::actor::db::backup::{self, BackupError};
+
+
+// SQLite online backup does not need the serve lock, so it is safe to run next to a serving process
+pub fn run(cfg: ConfigServe, path: Option<PathBuf>) -> Result<(), BackupError> {
+    let conn = Connection::open(&cfg.db.file)?;
+    let path = match (path, cfg.db.backup) {
+        (Some(path), _) => {
+            backup::snapshot_to(&conn, &path)?;
+            path
+        },
+        (None, Some(cfg_backup)) => backup::snapshot(&conn, &cfg_backup.dir, cfg_backup.keep)?,
+        (None, None) => return Err(BackupError::NotConfigured),
+    };
+    println!("database snapshot is saved to {}", path.display());
+    Ok(())
+}
@@ -0,0 +1,303 @@
+use std::{
+    fmt,
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::PathBuf,
+    sync::Arc,
+};
+
+use chrono::{TimeZone, SecondsFormat};
+use chrono_tz::Tz;
+use clap::ArgEnum;
+use serde::Serialize;
+use rusqlite::{Connection, OpenFlags, Error as SqlErr};
+use parquet::{
+    errors::ParquetError,
+    data_type::{ByteArray, ByteArrayType, Int64Type},
+    file::{properties::WriterProperties, writer::SerializedFileWriter},
+    schema::parser::parse_message_type,
+};
+
+use crate::config::ConfigServe;
+use crate::actor::db::repo_export::{RepoExport, Range, schema_is_current};
+use crate::model::compress;
+use crate::model::dataflow::{Group, Unit, Update, Record};
+
+
+#[derive(ArgEnum, Clone, Copy, Debug)]
+pub enum Format {
+    Csv,
+    Jsonl,
+    Parquet,
+}
+
+pub struct Filter {
+    pub groups: Vec<Group>,
+    pub units: Vec<Unit>,
+    pub range: Range,
+}
+impl Filter {
+    fn check(&self, group: &Group, unit: &Unit) -> bool {
+        (self.groups.is_empty() || self.groups.contains(group)) && (self.units.is_empty() || self.units.contains(unit))
+    }
+}
+
+
+pub fn run(cfg: ConfigServe, format: Format, path: PathBuf, filter: Filter, tz: Tz) -> Result<(), ExportError> {
+    let dictionaries = compress::dictionaries(&cfg.db, &cfg.groups);
+    // read-only: export runs next to the server without taking its lock, so it never writes
+    let conn = Connection::open_with_flags(&cfg.db.file, OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX)?;
+    if !schema_is_current(&conn)? {
+        return Err(ExportError::SchemaOutdated);
+    }
+    let mut repo = RepoExport::new(&conn, &dictionaries);
+    let file = File::create(&path)?;
+    let mut writer: Box<dyn Writer> = match format {
+        Format::Csv => Box::new(WriterCsv::new(file)),
+        Format::Jsonl => Box::new(WriterJsonl::new(file)),
+        Format::Parquet => Box::new(WriterParquet::new(file)?),
+    };
+    let mut count: u64 = 0;
+    for (group, unit, id_unit) in repo.units()? {
+        if !filter.check(&group, &unit) {
+            continue;
+        }
+        let count_skipped = repo.data_each(id_unit, &filter.range, |record| {
+            count += 1;
+            writer.write(Row::new(&group, &unit, record, &tz))
+        })?;
+        if count_skipped > 0 {
+            println!("[WARN] \"{}\" / \"{}\": {} records are not decompressed and not exported; is their dictionary configured?", group.to_str(), unit.to_str(), count_skipped);
+        }
+    }
+    writer.finish()?;
+    println!("exported {} records to {}", count, path.display());
+    Ok(())
+}
+
+
+#[derive(Serialize)]
+struct Row<'a> {
+    group: &'a str,
+    unit: &'a str,
+    id: u64,
+    time: i64,
+    time_zoned: String,
+    #[serde(rename = "type")]
+    kind: &'static str,
+    value: Option<String>,
+    encoding: Option<&'static str>,
+}
+impl <'a>Row<'a> {
+    fn new(group: &'a Group, unit: &'a Unit, record: Record<Update>, tz: &Tz) -> Self {
+        let time_zoned = tz.timestamp_millis_opt(record.time).single()
+            .map(|time| time.to_rfc3339_opts(SecondsFormat::Millis, false))
+            .unwrap_or_default();
+        let (kind, value, encoding) = match record.val {
+            Update::Online => ("online", None, None),
+            Update::Offline => ("offline", None, None),
+            Update::Value{value, ..} => match std::str::from_utf8(value.as_bytes()) {
+                Ok(text) => ("value", Some(text.to_string()), Some("utf8")),
+                Err(_) => ("value", Some(value.into_base64()), Some("base64")),
+            },
+        };
+        Self {
+            group: group.to_str(),
+            unit: unit.to_str(),
+            id: record.id,
+            time: record.time,
+            time_zoned, kind, value, encoding,
+        }
+    }
+}
+
+
+trait Writer {
+    fn write(&mut self, row: Row<'_>) -> Result<(), ExportError>;
+    fn finish(self: Box<Self>) -> Result<(), ExportError>;
+}
+
+struct WriterCsv {
+    writer: csv::Writer<File>,
+}
+impl WriterCsv {
+    fn new(file: File) -> Self {
+        Self { writer: csv::Writer::from_writer(file) }
+    }
+}
+impl Writer for WriterCsv {
+    fn write(&mut self, row: Row<'_>) -> Result<(), ExportError> {
+        Ok(self.writer.serialize(row)?)
+    }
+
+    fn finish(mut self: Box<Self>) -> Result<(), ExportError> {
+        Ok(self.writer.flush()?)
+    }
+}
+
+struct WriterJsonl {
+    writer: BufWriter<File>,
+}
+impl WriterJsonl {
+    fn new(file: File) -> Self {
+        Self { writer: BufWriter::new(file) }
+    }
+}
+impl Writer for WriterJsonl {
+    fn write(&mut self, row: Row<'_>) -> Result<(), ExportError> {
+        serde_json::to_writer(&mut self.writer, &row)?;
+        Ok(self.writer.write_all(b"\n")?)
+    }
+
+    fn finish(mut self: Box<Self>) -> Result<(), ExportError> {
+        Ok(self.writer.flush()?)
+    }
+}
+
+// Rows are buffered column by column and flushed as a row group every ROW_GROUP_LEN rows
+const ROW_GROUP_LEN: usize = 64 * 1024;
+const SCHEMA_PARQUET: &str = "message record {
+    REQUIRED BYTE_ARRAY group (UTF8);
+    REQUIRED BYTE_ARRAY unit (UTF8);
+    REQUIRED INT64 id;
+    REQUIRED INT64 time (TIMESTAMP(MILLIS,true));
+    REQUIRED BYTE_ARRAY time_zoned (UTF8);
+    REQUIRED BYTE_ARRAY type (UTF8);
+    OPTIONAL BYTE_ARRAY value (UTF8);
+    OPTIONAL BYTE_ARRAY encoding (UTF8);
+}";
+
+#[derive(Default)]
+struct ColumnsParquet {
+    group: Vec<ByteArray>,
+    unit: Vec<ByteArray>,
+    id: Vec<i64>,
+    time: Vec<i64>,
+    time_zoned: Vec<ByteArray>,
+    kind: Vec<ByteArray>,
+    value: Vec<ByteArray>,
+    value_def: Vec<i16>,
+    encoding: Vec<ByteArray>,
+    encoding_def: Vec<i16>,
+}
+
+struct WriterParquet {
+    writer: SerializedFileWriter<File>,
+    columns: ColumnsParquet,
+}
+impl WriterParquet {
+    fn new(file: File) -> Result<Self, ExportError> {
+        let schema = Arc::new(parse_message_type(SCHEMA_PARQUET)?);
+        let props = Arc::new(WriterProperties::builder().build());
+        Ok(Self {
+            writer: SerializedFileWriter::new(file, schema, props)?,
+            columns: ColumnsParquet::default(),
+        })
+    }
+
+    fn flush(&mut self) -> Result<(), ExportError> {
+        if self.columns.id.is_empty() {
+            return Ok(());
+        }
+        let cols = std::mem::take(&mut self.columns);
+        let mut row_group = self.writer.next_row_group()?;
+        let mut idx = 0;
+        while let Some(mut column) = row_group.next_column()? {
+            match idx {
+                0 => column.typed::<ByteArrayType>().write_batch(&cols.group, None, None)?,
+                1 => column.typed::<ByteArrayType>().write_batch(&cols.unit, None, None)?,
+                2 => column.typed::<Int64Type>().write_batch(&cols.id, None, None)?,
+                3 => column.typed::<Int64Type>().write_batch(&cols.time, None, None)?,
+                4 => column.typed::<ByteArrayType>().write_batch(&cols.time_zoned, None, None)?,
+                5 => column.typed::<ByteArrayType>().write_batch(&cols.kind, None, None)?,
+                6 => column.typed::<ByteArrayType>().write_batch(&cols.value, Some(&cols.value_def), None)?,
+                _ => column.typed::<ByteArrayType>().write_batch(&cols.encoding, Some(&cols.encoding_def), None)?,
+            };
+            column.close()?;
+            idx += 1;
+        }
+        row_group.close()?;
+        Ok(())
+    }
+}
+impl Writer for WriterParquet {
+    fn write(&mut self, row: Row<'_>) -> Result<(), ExportError> {
+        let cols = &mut self.columns;
+        cols.group.push(ByteArray::from(row.group));
+        cols.unit.push(ByteArray::from(row.unit));
+        cols.id.push(row.id as i64);
+        cols.time.push(row.time);
+        cols.time_zoned.push(ByteArray::from(row.time_zoned.into_bytes()));
+        cols.kind.push(ByteArray::from(row.kind));
+        if let Some(value) = row.value {
+            cols.value.push(ByteArray::from(value.into_bytes()));
+            cols.value_def.push(1);
+        } else {
+            cols.value_def.push(0);
+        }
+        if let Some(encoding) = row.encoding {
+            cols.encoding.push(ByteArray::from(encoding));
+            cols.encoding_def.push(1);
+        } else {
+            cols.encoding_def.push(0);
+        }
+        if cols.id.len() >= ROW_GROUP_LEN {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    fn finish(mut self: Box<Self>) -> Result<(), ExportError> {
+        self.flush()?;
+        self.writer.close()?;
+        Ok(())
+    }
+}
+
+
+#[derive(Debug)]
+pub enum ExportError {
+    Sql(SqlErr),
+    Io(io::Error),
+    Json(serde_json::Error),
+    Csv(csv::Error),
+    Parquet(ParquetError),
+    SchemaOutdated,
+}
+impl From<SqlErr> for ExportError {
+    fn from(err: SqlErr) -> Self {
+        Self::Sql(err)
+    }
+}
+impl From<io::Error> for ExportError {
+    fn from(err: io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+impl From<serde_json::Error> for ExportError {
+    fn from(err: serde_json::Error) -> Self {
+        Self::Json(err)
+    }
+}
+impl From<csv::Error> for ExportError {
+    fn from(err: csv::Error) -> Self {
+        Self::Csv(err)
+    }
+}
+impl From<ParquetError> for ExportError {
+    fn from(err: ParquetError) -> Self {
+        Self::Parquet(err)
+    }
+}
+impl fmt::Display for ExportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExportError::Sql(err) => write!(f, "database error: {}", err),
+            ExportError::Io(err) => write!(f, "io error: {}", err),
+            ExportError::Json(err) => write!(f, "jsonl error: {}", err),
+            ExportError::Csv(err) => write!(f, "csv error: {}", err),
+            ExportError::Parquet(err) => write!(f, "parquet error: {}", err),
+            ExportError::SchemaOutdated => write!(f, "database schema is outdated or missing; run 'serve' once to migrate it"),
+        }
+    }
+}
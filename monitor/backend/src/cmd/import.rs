@@ -0,0 +1,207 @@
+use std::{
+    fmt,
+    fs::File,
+    io::{self, BufRead, BufReader},
+    path::PathBuf,
+    collections::HashMap,
+};
+
+use bytes::Bytes;
+use chrono::DateTime;
+use clap::ArgEnum;
+use serde::Deserialize;
+use rusqlite::{Connection, Error as SqlErr};
+
+use crate::fs;
+use crate::config::ConfigServe;
+use crate::actor::db::repo_import::RepoImport;
+use crate::model::dataflow::{Group, Unit, Value, Update, Record};
+
+
+#[derive(ArgEnum, Clone, Copy, Debug)]
+pub enum Format {
+    Csv,
+    Jsonl,
+}
+
+// Same columns as produced by the export command; type defaults to "value", encoding to "utf8"
+#[derive(Deserialize)]
+struct Row {
+    group: Group,
+    unit: Unit,
+    time: Time,
+    #[serde(rename = "type", default)]
+    kind: Option<String>,
+    #[serde(default)]
+    value: Option<String>,
+    #[serde(default)]
+    encoding: Option<String>,
+}
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Time {
+    Millis(i64),
+    Text(String),
+}
+
+
+pub fn run(cfg: ConfigServe, format: Format, path: PathBuf, is_forced: bool) -> Result<(), ImportError> {
+    let _lock = match fs::db_lock(&cfg.db.file) {
+        Ok(lock) => Some(lock),
+        Err(err) if is_forced => {
+            println!("[WARN] database lock is not acquired ({}), importing anyway", err);
+            None
+        },
+        Err(err) => return Err(ImportError::Locked(err)),
+    };
+
+    let mut map: HashMap<(Group, Unit), Vec<(i64, Update)>> = HashMap::new();
+    let mut count_skipped: u64 = 0;
+    let mut push_row = |row: Row| -> Result<(), ImportError> {
+        let is_configured = cfg.groups.get(&row.group)
+            .map(|cfg_group| cfg_group.units.contains_key(&row.unit))
+            .unwrap_or(false);
+        if is_configured {
+            let (time, update) = row_parse(&row)?;
+            map.entry((row.group, row.unit)).or_default().push((time, update));
+        } else {
+            count_skipped += 1;
+        }
+        Ok(())
+    };
+    match format {
+        Format::Csv => {
+            let mut reader = csv::Reader::from_path(&path)?;
+            for row in reader.deserialize::<Row>() {
+                push_row(row?)?;
+            }
+        },
+        Format::Jsonl => {
+            let reader = BufReader::new(File::open(&path)?);
+            for line in reader.lines() {
+                let line = line?;
+                if !line.trim().is_empty() {
+                    push_row(serde_json::from_str::<Row>(&line)?)?;
+                }
+            }
+        },
+    }
+    if count_skipped > 0 {
+        println!("[WARN] {} rows skipped: their units are not configured", count_skipped);
+    }
+
+    let conn = Connection::open(&cfg.db.file)?;
+    let mut repo = RepoImport::new(&conn);
+    repo.begin()?;
+    if let Err(err) = import(&mut repo, &cfg, map) {
+        let _ = repo.rollback();
+        return Err(err);
+    }
+    repo.commit()?;
+    Ok(())
+}
+
+
+fn import(repo: &mut RepoImport, cfg: &ConfigServe, map: HashMap<(Group, Unit), Vec<(i64, Update)>>) -> Result<(), ImportError> {
+    for ((group, unit), mut vec) in map {
+        let count_max = cfg.groups.get(&group)
+            .and_then(|cfg_group| cfg_group.units.get(&unit))
+            .map(|cfg_unit| cfg_unit.count_max)
+            .unwrap_or(u64::MAX);
+        vec.sort_by_key(|(time, _)| *time);
+        let count = vec.len() as u64;
+        let id_unit = repo.unit_ensure(&group, &unit)?;
+        // Backfilled records go right before the stored ones when they are older and there is room
+        // for them in the id_record sequence, otherwise the sequence is continued after the last record
+        let id_first = match repo.stats(id_unit)? {
+            None => 0,
+            Some(stats) => {
+                let time_last = vec.last().map(|(time, _)| *time).unwrap_or(i64::MIN);
+                if time_last < stats.time_min && stats.id_min >= count {
+                    stats.id_min - count
+                } else {
+                    println!("[WARN] \"{}\" / \"{}\": records are appended after id_record={}, so their ids are not in time order", group.to_str(), unit.to_str(), stats.id_max);
+                    stats.id_max + 1
+                }
+            },
+        };
+        for (idx, (time, update)) in vec.into_iter().enumerate() {
+            repo.push(id_unit, &Record{ id: id_first + idx as u64, is_saved: true, time, val: update })?;
+        }
+        let count_trimmed = repo.trim(id_unit, count_max)?;
+        println!("\"{}\" / \"{}\": imported {} records with id_record from {}", group.to_str(), unit.to_str(), count, id_first);
+        if count_trimmed > 0 {
+            println!("[WARN] \"{}\" / \"{}\": {} oldest records removed to fit count_max={}", group.to_str(), unit.to_str(), count_trimmed, count_max);
+        }
+    }
+    Ok(())
+}
+
+fn row_parse(row: &Row) -> Result<(i64, Update), ImportError> {
+    let time = match &row.time {
+        Time::Millis(millis) => *millis,
+        Time::Text(text) => if let Ok(millis) = text.parse::<i64>() {
+            millis
+        } else {
+            DateTime::parse_from_rfc3339(text).map_err(|_| ImportError::Row(format!("unrecognized time '{}'", text)))?.timestamp_millis()
+        },
+    };
+    let update = match row.kind.as_deref() {
+        Some("online") => Update::Online,
+        Some("offline") => Update::Offline,
+        None | Some("value") => {
+            let text = row.value.as_deref().unwrap_or("");
+            let bytes = match row.encoding.as_deref() {
+                None | Some("") | Some("utf8") => Bytes::copy_from_slice(text.as_bytes()),
+                Some("base64") => Bytes::from(base64::decode(text).map_err(|_| ImportError::Row(format!("value is not base64: '{}'", text)))?),
+                Some(encoding) => return Err(ImportError::Row(format!("unknown encoding '{}'", encoding))),
+            };
+            Update::Value{ value: Value::new(bytes), meta: None }
+        },
+        Some(kind) => return Err(ImportError::Row(format!("unknown type '{}'", kind))),
+    };
+    Ok((time, update))
+}
+
+
+#[derive(Debug)]
+pub enum ImportError {
+    Locked(io::Error),
+    Sql(SqlErr),
+    Io(io::Error),
+    Json(serde_json::Error),
+    Csv(csv::Error),
+    Row(String),
+}
+impl From<SqlErr> for ImportError {
+    fn from(err: SqlErr) -> Self {
+        Self::Sql(err)
+    }
+}
+impl From<io::Error> for ImportError {
+    fn from(err: io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+impl From<serde_json::Error> for ImportError {
+    fn from(err: serde_json::Error) -> Self {
+        Self::Json(err)
+    }
+}
+impl From<csv::Error> for ImportError {
+    fn from(err: csv::Error) -> Self {
+        Self::Csv(err)
+    }
+}
+impl fmt::Display for ImportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ImportError::Locked(err) => write!(f, "database is used by a running serve process ({}); stop it or pass --force", err),
+            ImportError::Sql(err) => write!(f, "database error: {}", err),
+            ImportError::Io(err) => write!(f, "io error: {}", err),
+            ImportError::Json(err) => write!(f, "jsonl error: {}", err),
+            ImportError::Csv(err) => write!(f, "csv error: {}", err),
+            ImportError::Row(err) => write!(f, "wrong row: {}", err),
+        }
+    }
+}
@@ -0,0 +1,84 @@
+use std::{
+    fmt,
+    io::{self, BufRead},
+    path::Path,
+};
+
+use clap::ArgEnum;
+
+use crate::config::ConfigUser;
+use crate::model::password::{self, Algorithm};
+
+
+#[derive(ArgEnum, Clone, Copy, Debug)]
+pub enum Format {
+    Argon2id,
+    Bcrypt,
+}
+
+pub fn run(format: Format) -> Result<(), PasswordError> {
+    println!("{}", read_hashed(format)?);
+    Ok(())
+}
+
+// The password is read from the first line of stdin, so it is not kept in the shell history
+pub fn read_hashed(format: Format) -> Result<String, PasswordError> {
+    let mut line = String::new();
+    io::stdin().lock().read_line(&mut line)?;
+    let password_text = line.trim_end_matches(['\r', '\n']);
+    if password_text.is_empty() {
+        return Err(PasswordError::Empty);
+    }
+    let algorithm = match format {
+        Format::Argon2id => Algorithm::Argon2id,
+        Format::Bcrypt => Algorithm::Bcrypt,
+    };
+    password::hash(password_text, algorithm).map_err(PasswordError::Hash)
+}
+
+// Called by `serve` on start: users with plaintext passwords still log in, but are reported
+pub fn startup(dir_users: &Path) {
+    password::init_dummy();
+    let entries = match std::fs::read_dir(dir_users) {
+        Ok(entries) => entries,
+        Err(err) => {
+            println!("[WARN] users directory is not read: {}", err); // TODO: log this
+            return;
+        },
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.extension().is_none_or(|ext| ext != "json") {
+            continue;
+        }
+        let cfg_user = std::fs::read_to_string(&path).ok()
+            .and_then(|text| serde_json::from_str::<ConfigUser>(&text).ok());
+        if let Some(cfg_user) = cfg_user {
+            if !password::is_hashed(&cfg_user.password) {
+                println!("[WARN] {} has a plaintext password; replace it with the output of 'monitor hash-password'", path.display()); // TODO: log this
+            }
+        }
+    }
+}
+
+
+#[derive(Debug)]
+pub enum PasswordError {
+    Io(io::Error),
+    Empty,
+    Hash(String),
+}
+impl From<io::Error> for PasswordError {
+    fn from(err: io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+impl fmt::Display for PasswordError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PasswordError::Io(err) => write!(f, "stdin error: {}", err),
+            PasswordError::Empty => write!(f, "password is empty; pass it as the first line of stdin"),
+            PasswordError::Hash(err) => write!(f, "password is not hashed: {}", err),
+        }
+    }
+}
@@ -0,0 +1,136 @@
+use std::{
+    fmt,
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::PathBuf,
+    collections::HashMap,
+};
+
+use serde::Serialize;
+use rusqlite::{Connection, Error as SqlErr};
+
+use crate::config::{ConfigServe, ConfigServeGroup};
+use crate::actor::db::repo_prune::{RepoPrune, Orphans};
+use crate::model::compress::{self, Dictionaries};
+use crate::model::dataflow::{Group, Unit, Update, Record};
+
+
+#[derive(Serialize)]
+struct ArchiveLine<'a> {
+    #[serde(rename = "g")]
+    group: &'a Group,
+    #[serde(rename = "u")]
+    unit: &'a Unit,
+    #[serde(flatten)]
+    record: &'a Record<Update>,
+}
+
+
+pub fn run(cfg: ConfigServe, is_confirmed: bool, path_archive: Option<PathBuf>) -> Result<(), PruneError> {
+    let dictionaries = compress::dictionaries(&cfg.db, &cfg.groups);
+    let conn = Connection::open(&cfg.db.file)?;
+    let mut repo = RepoPrune::new(&conn, &dictionaries);
+    let orphans = repo.orphans(&cfg.groups)?;
+    if orphans.is_empty() {
+        println!("nothing to prune: all stored groups and units are configured");
+        return Ok(());
+    }
+    print_orphans(&orphans);
+    if !is_confirmed && !confirm()? {
+        println!("prune cancelled");
+        return Ok(());
+    }
+    if let Some(path) = path_archive {
+        archive(&mut repo, &orphans, path)?;
+    }
+    repo.delete(&orphans)?;
+    println!("pruned {} groups, {} units and {} records", orphans.groups.len(), orphans.units.len(), orphans.count_records());
+    Ok(())
+}
+
+// Called by `serve --prune` before the Db actor starts, so nothing is asked interactively
+pub fn startup(conn: &Connection, cfg_groups: &HashMap<Group, ConfigServeGroup>, dictionaries: &Dictionaries) {
+    let mut repo = RepoPrune::new(conn, dictionaries);
+    let res = repo.orphans(cfg_groups).and_then(|orphans| {
+        if !orphans.is_empty() {
+            print_orphans(&orphans);
+            repo.delete(&orphans)?;
+            println!("[INFO] pruned {} groups, {} units and {} records", orphans.groups.len(), orphans.units.len(), orphans.count_records());
+        }
+        Ok(())
+    });
+    if let Err(err) = res {
+        println!("[WARN] startup prune failed: {}", err); // TODO: log this
+    }
+}
+
+
+fn print_orphans(orphans: &Orphans) {
+    println!("groups and units which are no longer configured:");
+    for orphan in orphans.groups.iter() {
+        let (count_units, count_records) = orphans.units.iter()
+            .filter(|orphan_unit| orphan_unit.group == orphan.group)
+            .fold((0, 0), |(units, records), orphan_unit| (units + 1, records + orphan_unit.count));
+        println!("  group \"{}\": {} units, {} records", orphan.group.to_str(), count_units, count_records);
+    }
+    for orphan in orphans.units.iter() {
+        println!("  unit \"{}\" / \"{}\": {} records", orphan.group.to_str(), orphan.unit.to_str(), orphan.count);
+    }
+}
+
+fn confirm() -> Result<bool, PruneError> {
+    print!("delete listed groups and units with all their records? [y/N] ");
+    io::stdout().flush()?;
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer)?;
+    Ok(matches!(answer.trim(), "y" | "Y" | "yes"))
+}
+
+fn archive(repo: &mut RepoPrune, orphans: &Orphans, path: PathBuf) -> Result<(), PruneError> {
+    let mut writer = BufWriter::new(File::create(&path)?);
+    for orphan in orphans.units.iter() {
+        let count_skipped = repo.data_each(orphan.id_unit, |record| {
+            serde_json::to_writer(&mut writer, &ArchiveLine{ group: &orphan.group, unit: &orphan.unit, record: &record })?;
+            writer.write_all(b"\n")?;
+            Ok::<(), PruneError>(())
+        })?;
+        if count_skipped > 0 {
+            println!("[WARN] \"{}\" / \"{}\": {} records are not decompressed and not archived; is their dictionary configured?", orphan.group.to_str(), orphan.unit.to_str(), count_skipped);
+        }
+    }
+    writer.flush()?;
+    println!("archived {} records to {}", orphans.count_records(), path.display());
+    Ok(())
+}
+
+
+#[derive(Debug)]
+pub enum PruneError {
+    Sql(SqlErr),
+    Io(io::Error),
+    Ser(serde_json::Error),
+}
+impl From<SqlErr> for PruneError {
+    fn from(err: SqlErr) -> Self {
+        Self::Sql(err)
+    }
+}
+impl From<io::Error> for PruneError {
+    fn from(err: io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+impl From<serde_json::Error> for PruneError {
+    fn from(err: serde_json::Error) -> Self {
+        Self::Ser(err)
+    }
+}
+impl fmt::Display for PruneError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PruneError::Sql(err) => write!(f, "database error: {}", err),
+            PruneError::Io(err) => write!(f, "io error: {}", err),
+            PruneError::Ser(err) => write!(f, "archive serialization error: {}", err),
+        }
+    }
+}
@@ -0,0 +1,76 @@
+use std::{fmt, io};
+
+use rusqlite::{Connection, Error as SqlErr};
+
+use crate::fs;
+use crate::config::ConfigServe;
+use crate::actor::db::repo_recompress::RepoRecompress;
+use crate::model::compress::{self, Codec};
+
+
+// Stored values of the configured units are brought to their current compress settings
+pub fn run(cfg: ConfigServe, is_forced: bool) -> Result<(), RecompressError> {
+    let _lock = match fs::db_lock(&cfg.db.file) {
+        Ok(lock) => Some(lock),
+        Err(err) if is_forced => {
+            println!("[WARN] database lock is not acquired ({}), recompressing anyway", err);
+            None
+        },
+        Err(err) => return Err(RecompressError::Locked(err)),
+    };
+    let dictionaries = compress::dictionaries(&cfg.db, &cfg.groups);
+    let conn = Connection::open(&cfg.db.file)?;
+    let mut repo = RepoRecompress::new(&conn, &dictionaries);
+    let (mut bytes_before, mut bytes_after) = (0u64, 0u64);
+    for (group, cfg_group) in cfg.groups.iter() {
+        for (unit, cfg_unit) in cfg_group.units.iter() {
+            let id_unit = match repo.unit_id(group, unit)? {
+                Some(id_unit) => id_unit,
+                None => continue,
+            };
+            let mut codec = match &cfg_unit.compress {
+                Some(cfg_compress) => Some(Codec::new(cfg_compress)?),
+                None => None,
+            };
+            let stats = repo.recompress(id_unit, codec.as_mut())?;
+            bytes_before += stats.bytes_before;
+            bytes_after += stats.bytes_after;
+            println!("\"{}\" / \"{}\": {} of {} values rewritten, {} -> {} bytes", group.to_str(), unit.to_str(), stats.count_rewritten, stats.count, stats.bytes_before, stats.bytes_after);
+            if stats.count_skipped > 0 {
+                println!("[WARN] \"{}\" / \"{}\": {} values are not decompressed and left as they are; is their dictionary configured?", group.to_str(), unit.to_str(), stats.count_skipped);
+            }
+        }
+    }
+    println!("total: {} -> {} bytes", bytes_before, bytes_after);
+    if bytes_after < bytes_before {
+        println!("run VACUUM on the database to give the freed space back to the file system");
+    }
+    Ok(())
+}
+
+
+#[derive(Debug)]
+pub enum RecompressError {
+    Locked(io::Error),
+    Sql(SqlErr),
+    Zstd(io::Error),
+}
+impl From<SqlErr> for RecompressError {
+    fn from(err: SqlErr) -> Self {
+        Self::Sql(err)
+    }
+}
+impl From<io::Error> for RecompressError {
+    fn from(err: io::Error) -> Self {
+        Self::Zstd(err)
+    }
+}
+impl fmt::Display for RecompressError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RecompressError::Locked(err) => write!(f, "database is used by a running serve process ({}); stop it or pass --force", err),
+            RecompressError::Sql(err) => write!(f, "database error: {}", err),
+            RecompressError::Zstd(err) => write!(f, "zstd compressor error: {}", err),
+        }
+    }
+}
@@ -0,0 +1,22 @@
+use rusqlite::Connection;
+
+use crate::config::ConfigServe;
+use crate::actor::db::repo_unit::{RepoUnit, RenameError};
+use crate::model::dataflow::{Group, Unit};
+
+
+pub fn run(cfg: ConfigServe, group: Group, unit: Unit, group_to: Option<Group>, unit_to: Option<Unit>) -> Result<(), RenameError> {
+    let group_to = group_to.unwrap_or_else(|| group.clone());
+    let unit_to = unit_to.unwrap_or_else(|| unit.clone());
+    let conn = Connection::open(&cfg.db.file)?;
+    let mut repo = RepoUnit::new_init(&conn);
+    let id_unit = repo.unit_rename(&group, &unit, &group_to, &unit_to)?;
+    println!("unit \"{}\" / \"{}\" (id={}) renamed to \"{}\" / \"{}\"", group.to_str(), unit.to_str(), id_unit, group_to.to_str(), unit_to.to_str());
+    let is_configured = cfg.groups.get(&group_to)
+        .map(|cfg_group| cfg_group.units.contains_key(&unit_to))
+        .unwrap_or(false);
+    if !is_configured {
+        println!("[WARN] \"{}\" / \"{}\" is not configured yet: update the config before the next start", group_to.to_str(), unit_to.to_str());
+    }
+    Ok(())
+}
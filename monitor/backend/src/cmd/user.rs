@@ -0,0 +1,162 @@
+use std::{
+    fmt,
+    io,
+    path::{Path, PathBuf},
+};
+
+use lazy_static::lazy_static;
+use regex::Regex;
+
+use crate::config::{ConfigServe, ConfigUser, ConfigWplace};
+use crate::cmd::password::{self, Format, PasswordError};
+use crate::model::password::is_hashed;
+
+
+lazy_static!{
+    // Logins are file names in dir.users
+    static ref REGEX_LOGIN: Regex = Regex::new(r"^[a-zA-Z0-9]+(?:[.\-_][a-zA-Z0-9]+)*$").unwrap();
+}
+
+
+pub fn list(cfg: ConfigServe) -> Result<(), UserError> {
+    let mut vec_user: Vec<(String, Result<ConfigUser, UserError>)> = Vec::new();
+    for entry in std::fs::read_dir(&cfg.dir.users)? {
+        let path = entry?.path();
+        if path.extension().is_none_or(|ext| ext != "json") {
+            continue;
+        }
+        if let Some(login) = path.file_stem().and_then(|stem| stem.to_str()) {
+            vec_user.push((login.to_string(), user_read(&path)));
+        }
+    }
+    vec_user.sort_by(|(login_a, _), (login_b, _)| login_a.cmp(login_b));
+    for (login, res) in vec_user {
+        match res {
+            Ok(cfg_user) => {
+                let kind = if is_hashed(&cfg_user.password) { "hashed" } else { "plaintext" };
+                let state = if cfg_user.iter_wplace().all(|wplace| wplace_check(&cfg.dir.wplaces, wplace).is_ok()) { "" } else { " [wplace is missing]" };
+                let role = cfg_user.role.map(|role| format!(", role {}", role.as_str())).unwrap_or_default();
+                let others = if cfg_user.wplaces.is_empty() { String::new() } else { format!(" (also \"{}\")", cfg_user.wplaces.join("\", \"")) };
+                println!("{}: wplace \"{}\"{}{}, {} password{}", login, cfg_user.wplace, others, role, kind, state);
+            },
+            Err(err) => println!("{}: [ERR] {}", login, err),
+        }
+    }
+    Ok(())
+}
+
+pub fn add(cfg: ConfigServe, login: String, wplace: String, format: Format) -> Result<(), UserError> {
+    let path = user_path(&cfg.dir.users, &login)?;
+    if path.exists() {
+        return Err(UserError::Exists(login));
+    }
+    wplace_check(&cfg.dir.wplaces, &wplace)?;
+    let password = password::read_hashed(format)?;
+    user_write(&path, &ConfigUser{ password, wplace, wplaces: Vec::new(), role: None })?;
+    println!("user {} is added", login);
+    Ok(())
+}
+
+pub fn passwd(cfg: ConfigServe, login: String, format: Format) -> Result<(), UserError> {
+    let path = user_path(&cfg.dir.users, &login)?;
+    let mut cfg_user = user_read(&path)?;
+    cfg_user.password = password::read_hashed(format)?;
+    user_write(&path, &cfg_user)?;
+    println!("password of user {} is changed; it is used with the next login", login);
+    Ok(())
+}
+
+pub fn set_wplace(cfg: ConfigServe, login: String, wplace: String) -> Result<(), UserError> {
+    let path = user_path(&cfg.dir.users, &login)?;
+    let mut cfg_user = user_read(&path)?;
+    wplace_check(&cfg.dir.wplaces, &wplace)?;
+    cfg_user.wplace = wplace;
+    user_write(&path, &cfg_user)?;
+    println!("wplace of user {} is changed; it is used with the next login", login);
+    Ok(())
+}
+
+pub fn remove(cfg: ConfigServe, login: String) -> Result<(), UserError> {
+    let path = user_path(&cfg.dir.users, &login)?;
+    if !path.is_file() {
+        return Err(UserError::NotFound(login));
+    }
+    std::fs::remove_file(&path)?;
+    println!("user {} is removed; a running server closes their sessions at once, and they are not restored on its next start", login);
+    Ok(())
+}
+
+
+fn user_path(dir_users: &Path, login: &str) -> Result<PathBuf, UserError> {
+    if REGEX_LOGIN.is_match(login) {
+        Ok(dir_users.join(format!("{}.json", login)))
+    } else {
+        Err(UserError::Login(login.to_string()))
+    }
+}
+
+fn user_read(path: &Path) -> Result<ConfigUser, UserError> {
+    let text = match std::fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {
+            let login = path.file_stem().map(|stem| stem.to_string_lossy().into_owned()).unwrap_or_default();
+            return Err(UserError::NotFound(login));
+        },
+        Err(err) => return Err(err.into()),
+    };
+    serde_json::from_str::<ConfigUser>(&text).map_err(|err| UserError::Json(path.to_path_buf(), err))
+}
+
+// The file is replaced at once, so a running server never reads it half written
+fn user_write(path: &Path, cfg_user: &ConfigUser) -> Result<(), UserError> {
+    let text = serde_json::to_string_pretty(cfg_user).map_err(|err| UserError::Json(path.to_path_buf(), err))?;
+    let path_tmp = path.with_extension("json.tmp");
+    std::fs::write(&path_tmp, text)?;
+    std::fs::rename(&path_tmp, path)?;
+    Ok(())
+}
+
+fn wplace_check(dir_wplaces: &Path, wplace: &str) -> Result<(), UserError> {
+    if !REGEX_LOGIN.is_match(wplace) {
+        return Err(UserError::Wplace(format!("\"{}\" is not a valid wplace name", wplace)));
+    }
+    let path = dir_wplaces.join(format!("{}.json", wplace));
+    let text = std::fs::read_to_string(&path).map_err(|err| UserError::Wplace(format!("{}: {}", path.display(), err)))?;
+    serde_json::from_str::<ConfigWplace>(&text).map_err(|err| UserError::Wplace(format!("{}: {}", path.display(), err)))?;
+    Ok(())
+}
+
+
+#[derive(Debug)]
+pub enum UserError {
+    Io(io::Error),
+    Json(PathBuf, serde_json::Error),
+    Login(String),
+    Exists(String),
+    NotFound(String),
+    Wplace(String),
+    Password(PasswordError),
+}
+impl From<io::Error> for UserError {
+    fn from(err: io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+impl From<PasswordError> for UserError {
+    fn from(err: PasswordError) -> Self {
+        Self::Password(err)
+    }
+}
+impl fmt::Display for UserError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UserError::Io(err) => write!(f, "io error: {}", err),
+            UserError::Json(path, err) => write!(f, "{}: {}", path.display(), err),
+            UserError::Login(login) => write!(f, "\"{}\" is not a valid login: use letters and digits separated by '.', '-' or '_'", login),
+            UserError::Exists(login) => write!(f, "user {} already exists", login),
+            UserError::NotFound(login) => write!(f, "user {} is not found", login),
+            UserError::Wplace(err) => write!(f, "wplace is not valid: {}", err),
+            UserError::Password(err) => write!(f, "{}", err),
+        }
+    }
+}
@@ -4,13 +4,16 @@ use std::{
     time::Duration, fmt::Debug,
 };
 
+use regex::Regex;
 use url::Host;
 use warp::filters::BoxedFilter;
-use serde::{de, Deserialize, Deserializer};
+use serde::{de, Deserialize, Deserializer, Serialize};
 
 mod deser;
 
 use crate::model::dataflow::{Group, Unit};
+use crate::model::compress::Dictionary;
+use crate::model::role::Role;
 use deser::{
     deserialize_unit_map, 
     deserialize_dir, 
@@ -18,7 +21,10 @@ use deser::{
     deserialize_path, 
     deserialize_path_opt, 
     deserialize_qos,
-    deserialize_duration_sec
+    deserialize_duration_sec,
+    deserialize_keep,
+    deserialize_dictionary_opt,
+    deserialize_dictionary_vec,
 };
 
 
@@ -28,6 +34,9 @@ pub struct ConfigServe {
     pub dir: ConfigServeDir,
     pub groups: HashMap<Group, ConfigServeGroup>,
     pub db: ConfigServeDb,
+    pub admin: Option<ConfigServeAdmin>,
+    pub sessions: ConfigServeSessions,
+    pub login: ConfigServeLogin,
 }
 impl<'de> Deserialize<'de> for ConfigServe {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error> where D: Deserializer<'de> {
@@ -42,6 +51,9 @@ impl<'de> Deserialize<'de> for ConfigServe {
                 dir: validator.dir,
                 groups: validator.groups,
                 db: validator.db,
+                admin: validator.admin,
+                sessions: validator.sessions,
+                login: validator.login,
             })
         }
     }
@@ -52,19 +64,131 @@ struct ConfigServeValidator {
     pub dir: ConfigServeDir,
     pub groups: HashMap<Group, ConfigServeGroup>,
     pub db: ConfigServeDb,
+    #[serde(default)]
+    pub admin: Option<ConfigServeAdmin>,
+    #[serde(default)]
+    pub sessions: ConfigServeSessions,
+    #[serde(default)]
+    pub login: ConfigServeLogin,
+}
+
+// Sessions are saved to the file ('<db.file>.sessions' by default) every save_interval_sec
+// and restored on start with the rest of their lifetime, so dashboards stay logged in across restarts.
+// A session is closed after idle_sec without use or lifetime_sec after login; with ws_keep_alive
+// an open WebSocket counts as use. Logins over count_max sessions close their oldest one
+#[derive(Deserialize, Debug)]
+#[serde(default)]
+pub struct ConfigServeSessions {
+    pub persist: bool,
+    pub file: Option<String>,
+    pub save_interval_sec: u64,
+    pub idle_sec: u64,
+    pub lifetime_sec: Option<u64>,
+    pub count_max: Option<usize>,
+    pub ws_keep_alive: bool,
+}
+impl Default for ConfigServeSessions {
+    fn default() -> Self {
+        Self {
+            persist: true, file: None, save_interval_sec: 30,
+            idle_sec: 60*30, lifetime_sec: None, count_max: None, ws_keep_alive: true,
+        }
+    }
+}
+
+// Failed logins are counted per login and per client address, the peer one, so clients behind a proxy share it.
+// attempts_max failures in a row lock the key for lock_sec, doubled with each next lockout up to lock_max_sec;
+// a key is forgotten after reset_sec without failures. 0 attempts turns the counter off
+#[derive(Deserialize, Debug)]
+#[serde(default)]
+pub struct ConfigServeLogin {
+    pub attempts_max: u32,
+    pub attempts_max_ip: u32,
+    pub lock_sec: u64,
+    pub lock_max_sec: u64,
+    pub reset_sec: u64,
+}
+impl Default for ConfigServeLogin {
+    fn default() -> Self {
+        Self {
+            attempts_max: 5, attempts_max_ip: 20,
+            lock_sec: 30, lock_max_sec: 60*60, reset_sec: 60*15,
+        }
+    }
 }
 
 #[derive(Deserialize, Debug)]
 pub struct ConfigServeDb {
     pub tx_count_max: usize,
+    // Time a write transaction is kept open for more records, so 0 commits as soon as the channel is drained
+    #[serde(default)]
+    pub commit_interval_ms: u64,
     pub file: String,
+    // Worker threads with read-only connections serving history queries
+    #[serde(default = "default_read_threads")]
+    pub read_threads: usize,
+    #[serde(default)]
+    pub backup: Option<ConfigServeDbBackup>,
+    #[serde(default)]
+    pub spill: ConfigServeDbSpill,
+    // Topic, QoS, retain flag and broker of each value are saved along with it; off by default as every row grows
+    #[serde(default)]
+    pub meta: bool,
+    // Cap of value bytes of all the units together, the oldest records of any unit are deleted beyond it
+    #[serde(default)]
+    pub max_bytes: Option<u64>,
+    // zstd dictionaries no longer used by units, kept to decompress the records stored with them
+    #[serde(default, deserialize_with = "deserialize_dictionary_vec")]
+    pub dictionaries: Vec<Dictionary>,
+}
+
+// Records of failed commits are kept in the spill file ('<db.file>.spill' by default) and retried;
+// a warning is printed each time count_alert more records are waiting
+#[derive(Deserialize, Debug)]
+#[serde(default)]
+pub struct ConfigServeDbSpill {
+    pub file: Option<String>,
+    pub count_max: usize,
+    pub count_alert: usize,
+}
+impl Default for ConfigServeDbSpill {
+    fn default() -> Self {
+        Self { file: None, count_max: 100_000, count_alert: 1_000 }
+    }
+}
+
+fn default_read_threads() -> usize {
+    2
+}
+
+fn default_compress_level() -> i32 {
+    3
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct ConfigServeDbBackup {
+    #[serde(deserialize_with = "deserialize_dir")]
+    pub dir: PathBuf,
+    // zero interval disables periodic snapshots, so they are only made on demand
+    #[serde(deserialize_with = "deserialize_duration_sec")]
+    pub interval: Duration,
+    #[serde(deserialize_with = "deserialize_keep")]
+    pub keep: usize,
+}
+
+// Admin routes are served only if this config is given; requests carry the token in the 'admin' header
+#[derive(Deserialize, Debug, Clone)]
+pub struct ConfigServeAdmin {
+    pub token: String,
 }
 
 #[derive(Deserialize, Debug)]
 pub struct ConfigServeGroup {
     pub client: ConfigMqttClient,
     #[serde(deserialize_with = "deserialize_unit_map")]
-    pub units: HashMap<Unit, ConfigMqttUnit> // <Topic, ConfigServeGroupUnit>
+    pub units: HashMap<Unit, ConfigMqttUnit>, // <Topic, ConfigServeGroupUnit>
+    #[serde(flatten)]
+    pub meta: ConfigMeta,
 }
 
 #[derive(Deserialize, Debug, Clone)]
@@ -85,6 +209,56 @@ pub struct ConfigMqttUnit {
     pub qos: u8,
     pub count_min: u64,
     pub count_max: u64,
+    // Value bytes kept for the unit; the oldest records are deleted beyond it as with count_max
+    #[serde(default)]
+    pub max_bytes: Option<u64>,
+    #[serde(default)]
+    pub aliases: Vec<ConfigMqttUnitAlias>,
+    #[serde(default)]
+    pub compress: Option<ConfigCompress>,
+    #[serde(flatten)]
+    pub meta: ConfigMeta,
+}
+
+// zstd compression of stored values; the dictionary file must be kept while records compressed
+// with it are stored, the recompress command moves them to the current settings
+#[derive(Deserialize, Debug)]
+pub struct ConfigCompress {
+    #[serde(default = "default_compress_level")]
+    pub level: i32,
+    // smaller values are stored as is
+    #[serde(default)]
+    pub min_bytes: usize,
+    #[serde(default, deserialize_with = "deserialize_dictionary_opt")]
+    pub dictionary: Option<Dictionary>,
+}
+
+// Presentation hints passed to the frontend as is: precision is the count of decimal digits,
+// groups and units with lower order go first
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+pub struct ConfigMeta {
+    #[serde(default, rename(serialize = "n"), skip_serializing_if = "Option::is_none")]
+    pub display_name: Option<String>,
+    #[serde(default, rename(serialize = "d"), skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(default, rename(serialize = "m"), skip_serializing_if = "Option::is_none")]
+    pub measure: Option<String>,
+    #[serde(default, rename(serialize = "p"), skip_serializing_if = "Option::is_none")]
+    pub precision: Option<u8>,
+    #[serde(default, rename(serialize = "o"), skip_serializing_if = "Option::is_none")]
+    pub order: Option<i32>,
+    #[serde(default, rename(serialize = "c"), skip_serializing_if = "Option::is_none")]
+    pub icon: Option<String>,
+    // tags of a group are the tags of all its units; wplaces select units by them
+    #[serde(default, rename(serialize = "t"), skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+}
+
+// Previous name of the unit: its stored history is taken over on startup
+#[derive(Deserialize, Debug)]
+pub struct ConfigMqttUnitAlias {
+    pub group: Option<Group>,
+    pub unit: Unit,
 }
 
 #[derive(Deserialize, Debug)]
@@ -105,16 +279,111 @@ pub struct ConfigServePath {
     pub public: Option<BoxedFilter<()>>,
 }
 
+// The role is the default one of the wplace users; a role of a user overrides it.
+// Units with any of the tags are added from all groups
 #[derive(Deserialize, Debug)]
 pub struct ConfigWplace {
-    pub groups: HashMap<String, Vec<String>>
+    #[serde(default)]
+    pub groups: HashMap<String, ConfigWplaceUnits>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub role: Option<Role>,
 }
 
-
+// Units of a group in a wplace: "*" for all of them, a list of names and patterns, or an object
+// of patterns and tags with exclusions; an object without units and tags selects all units
 #[derive(Deserialize, Debug)]
+#[serde(try_from = "ConfigWplaceUnitsValidator")]
+pub struct ConfigWplaceUnits {
+    pub is_all: bool,
+    pub units: Vec<ConfigPattern>,
+    pub exclude: Vec<ConfigPattern>,
+    pub tags: Vec<String>,
+}
+impl TryFrom<ConfigWplaceUnitsValidator> for ConfigWplaceUnits {
+    type Error = String;
+
+    fn try_from(validator: ConfigWplaceUnitsValidator) -> Result<Self, Self::Error> {
+        match validator {
+            ConfigWplaceUnitsValidator::All(text) if text == "*" => Ok(Self{ is_all: true, units: Vec::new(), exclude: Vec::new(), tags: Vec::new() }),
+            ConfigWplaceUnitsValidator::All(text) => Err(format!("units of a wplace group should be \"*\", a list or an object; given: {}", text)),
+            ConfigWplaceUnitsValidator::List(units) => Ok(Self{ is_all: false, units, exclude: Vec::new(), tags: Vec::new() }),
+            ConfigWplaceUnitsValidator::Select{ units, exclude, tags } => Ok(Self{ is_all: units.is_empty() && tags.is_empty(), units, exclude, tags }),
+        }
+    }
+}
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ConfigWplaceUnitsValidator {
+    All(String),
+    List(Vec<ConfigPattern>),
+    Select{
+        #[serde(default)]
+        units: Vec<ConfigPattern>,
+        #[serde(default)]
+        exclude: Vec<ConfigPattern>,
+        #[serde(default)]
+        tags: Vec<String>,
+    },
+}
+
+// Unit name as is, a glob with '*' and '?' or a regex between slashes: "/^cpu[0-9]+$/"
+#[derive(Debug)]
+pub enum ConfigPattern {
+    Exact(String),
+    Regex(Regex),
+}
+impl ConfigPattern {
+    pub fn is_match(&self, name: &str) -> bool {
+        match self {
+            Self::Exact(text) => text == name,
+            Self::Regex(regex) => regex.is_match(name),
+        }
+    }
+}
+impl<'de> Deserialize<'de> for ConfigPattern {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error> where D: Deserializer<'de> {
+        let text = String::deserialize(deserializer)?;
+        let regex = if text.len() > 1 && text.starts_with('/') && text.ends_with('/') {
+            text[1..text.len()-1].to_string()
+        } else if text.contains(['*', '?']) {
+            let mut regex = String::from("^");
+            for c in text.chars() {
+                match c {
+                    '*' => regex.push_str(".*"),
+                    '?' => regex.push('.'),
+                    c => regex.push_str(&regex::escape(&c.to_string())),
+                }
+            }
+            regex.push('$');
+            regex
+        } else {
+            return Ok(Self::Exact(text));
+        };
+        Regex::new(&regex)
+            .map(Self::Regex)
+            .map_err(|err| de::Error::custom(format!("unit pattern {} is not valid: {}", text, err)))
+    }
+}
+
+
+#[derive(Deserialize, Serialize, Debug)]
 pub struct ConfigUser {
     pub password: String,
+    // selected when the login does not ask for another one
     pub wplace: String,
+    // other wplaces the user may select at login or switch to, one of them or a union
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub wplaces: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub role: Option<Role>,
+}
+impl ConfigUser {
+    // The default wplace comes first
+    pub fn iter_wplace(&self) -> impl Iterator<Item = &String> {
+        std::iter::once(&self.wplace).chain(self.wplaces.iter())
+    }
 }
 
 
@@ -13,6 +13,7 @@ use serde::{de, Deserialize, Deserializer};
 
 
 use crate::model::dataflow::{Group, Unit};
+use crate::model::compress::Dictionary;
 use crate::config::{ConfigMqttUnit, ConfigServe, ConfigServePath, ConfigServeDir, ConfigServeDb, ConfigServeGroup};
 
 lazy_static!{
@@ -47,6 +48,18 @@ where D: de::Deserializer<'de>,
     }
 }
 
+// The snapshot just made is counted, so keeping none would remove it at once
+pub fn deserialize_keep<'de, D>(deserializer: D) -> Result<usize, D::Error>
+where D: de::Deserializer<'de>,
+{
+    let keep = usize::deserialize(deserializer)?;
+    if keep > 0 {
+        Ok(keep)
+    } else {
+        Err(de::Error::custom("keep should be at least 1"))
+    }
+}
+
 pub fn deserialize_duration_sec<'de, D>(deserializer: D) -> Result<Duration, D::Error>
 where D: de::Deserializer<'de>,
 {
@@ -72,6 +85,25 @@ where D: de::Deserializer<'de>,
     }
 }
 
+// The file is read along with the config, so a missing or untrained dictionary is told at once
+pub fn deserialize_dictionary_opt<'de, D>(deserializer: D) -> Result<Option<Dictionary>, D::Error>
+where D: de::Deserializer<'de>,
+{
+    let string_opt = Option::<String>::deserialize(deserializer)?;
+    if let Some(string) = string_opt {
+        Ok(Some(handle_dictionary::<D>(string)?))
+    } else {
+        Ok(None)
+    }
+}
+
+pub fn deserialize_dictionary_vec<'de, D>(deserializer: D) -> Result<Vec<Dictionary>, D::Error>
+where D: de::Deserializer<'de>,
+{
+    let strings = Vec::<String>::deserialize(deserializer)?;
+    strings.into_iter().map(handle_dictionary::<D>).collect()
+}
+
 pub fn deserialize_path<'de, D>(deserializer: D) -> Result<BoxedFilter<()>, D::Error>
 where D: de::Deserializer<'de>,
 {
@@ -103,6 +135,13 @@ where D: de::Deserializer<'de>,
     }
 }
 
+fn handle_dictionary<'de, D>(string: String) -> Result<Dictionary, D::Error>
+where D: de::Deserializer<'de>,
+{
+    let bytes = std::fs::read(&string).map_err(|err| de::Error::custom(format!("zstd dictionary {}: {}", string, err)))?;
+    Dictionary::new(bytes).map_err(de::Error::custom)
+}
+
 fn handle_path<'de, D>(string: String) -> Result<BoxedFilter<()>, D::Error>
 where D: de::Deserializer<'de>,
 {
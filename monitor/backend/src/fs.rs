@@ -3,6 +3,7 @@ use std::{
     path::{Path, PathBuf}
 };
 
+use fs2::FileExt;
 use tokio::fs;
 use serde::de::{DeserializeOwned};
 use tokio_util::codec::{BytesCodec, FramedRead};
@@ -35,4 +36,23 @@ where T: DeserializeOwned
         }
     }
     None
-}
\ No newline at end of file
+}
+
+// Errors of reading are kept, so a removed file (NotFound) is told apart from one not readable for now
+pub async fn file_deser_io<T>(path: PathBuf) -> io::Result<Result<T, ()>>
+where T: DeserializeOwned
+{
+    let text = fs::read_to_string(path).await?;
+    Ok(serde_json::from_str::<T>(&text).map_err(|_| ()))
+}
+
+
+// Advisory lock next to the database file; it is released by the OS when the holding process exits
+pub fn db_lock(file: &str) -> io::Result<std::fs::File> {
+    let lock = std::fs::OpenOptions::new()
+        .create(true)
+        .write(true)
+        .open(format!("{}.lock", file))?;
+    lock.try_lock_exclusive()?;
+    Ok(lock)
+}
@@ -1,7 +1,12 @@
-use std::net::SocketAddr;
+use std::{
+    fmt::Display,
+    future::Future,
+    net::SocketAddr,
+    path::PathBuf,
+};
 
 use clap::Parser;
-use tokio::sync::mpsc::{channel, Sender};
+use tokio::sync::mpsc::channel;
 use tokio::time::Duration;
 use rusqlite::Connection;
 
@@ -11,28 +16,82 @@ mod args;
 mod config;
 mod fs;
 mod server;
+mod cmd;
 
 use args::Cli;
 use config::*;
 use actor::{
-    comm::{Comm, Signal as SignalComm},
-    db::{Db, Signal as SignalDb}, 
+    comm::{Comm, Signal as SignalComm, Store as StoreComm, Guard as GuardComm, watch as comm_watch},
+    db::{Db, Signal as SignalDb, read::{self, Signal as SignalRead}}, 
     dist::Dist,
 };
+use model::{info, compress, session::Policy as PolicySession};
 
 
 fn main() {
     let cli = Cli::parse();
     match cli.command {
-        args::Commands::Serve(serve) => cmd_serve(serve.address, serve.config),
+        args::Commands::Serve(serve) => cmd_serve(serve.address, serve.config, serve.prune),
+        args::Commands::Prune(prune) => cmd_exit(cmd::prune::run(prune.config, prune.yes, prune.archive)),
+        args::Commands::RenameUnit(rename) => cmd_exit(cmd::rename::run(rename.config, rename.group, rename.unit, rename.to_group, rename.to_unit)),
+        args::Commands::Export(export) => {
+            let filter = cmd::export::Filter {
+                groups: export.group,
+                units: export.unit,
+                range: actor::db::repo_export::Range {
+                    time_min: export.from,
+                    time_max: export.to,
+                    id_min: export.id_min,
+                    id_max: export.id_max,
+                },
+            };
+            cmd_exit(cmd::export::run(export.config, export.format, export.output, filter, export.tz))
+        },
+        args::Commands::Import(import) => cmd_exit(cmd::import::run(import.config, import.format, import.input, import.force)),
+        args::Commands::Backup(backup) => cmd_exit(cmd::backup::run(backup.config, backup.output)),
+        args::Commands::Recompress(recompress) => cmd_exit(cmd::recompress::run(recompress.config, recompress.force)),
+        args::Commands::HashPassword(hash) => cmd_exit(cmd::password::run(hash.format)),
+        args::Commands::User(user) => match user {
+            args::User::List(list) => cmd_exit(cmd::user::list(list.config)),
+            args::User::Add(add) => cmd_exit(cmd::user::add(add.config, add.login, add.wplace, add.format)),
+            args::User::Passwd(passwd) => cmd_exit(cmd::user::passwd(passwd.config, passwd.login, passwd.format)),
+            args::User::Remove(remove) => cmd_exit(cmd::user::remove(remove.config, remove.login)),
+            args::User::SetWplace(set) => cmd_exit(cmd::user::set_wplace(set.config, set.login, set.wplace)),
+        },
+    }
+}
+
+fn cmd_exit<E: Display>(res: Result<(), E>) {
+    if let Err(err) = res {
+        println!("[ERR] {}", err);
+        std::process::exit(1);
     }
 }
 
-fn cmd_serve(addr: SocketAddr, cfg: ConfigServe) {
+fn cmd_serve(addr: SocketAddr, cfg: ConfigServe, is_prune: bool) {
+    let _lock = fs::db_lock(&cfg.db.file).unwrap_or_else(|err| {
+        println!("[ERR] database file is used by another process: {}", err);
+        std::process::exit(1);
+    });
+    cmd::password::startup(&cfg.dir.users);
     let (tx_comm, rx_comm) = channel::<SignalComm>(cfg.db.tx_count_max);
     let (tx_db, rx_db) = channel::<SignalDb>(cfg.db.tx_count_max);
+    let (tx_read, rx_read) = channel::<SignalRead>(cfg.db.tx_count_max);
     let tx_comm_db = tx_comm.clone();
-    let comm = Comm::new(rx_comm, tx_comm.clone(), tx_db.clone(), Duration::from_secs(60*30));
+    let store_comm = if cfg.sessions.persist {
+        let file = cfg.sessions.file.clone().unwrap_or_else(|| format!("{}.sessions", cfg.db.file));
+        Some(StoreComm::new(PathBuf::from(file), Duration::from_secs(cfg.sessions.save_interval_sec.max(1)), cfg.dir.users.clone(), cfg.dir.wplaces.clone()))
+    } else {
+        None
+    };
+    let policy_sess = PolicySession {
+        idle: Duration::from_secs(cfg.sessions.idle_sec.max(1)),
+        lifetime: cfg.sessions.lifetime_sec.map(|sec| Duration::from_secs(sec.max(1))),
+        count_max: cfg.sessions.count_max.map(|count| count.max(1)),
+        is_ws_alive: cfg.sessions.ws_keep_alive,
+    };
+    let guard_comm = GuardComm::new(&cfg.login);
+    let comm = Comm::new(rx_comm, tx_comm.clone(), tx_db.clone(), info::from_config(&cfg.groups), policy_sess, store_comm, guard_comm);
     let dist = Dist::new(tx_db.clone(), &cfg.groups);
 
     std::panic::set_hook(Box::new(|x| {
@@ -42,24 +101,33 @@ fn cmd_serve(addr: SocketAddr, cfg: ConfigServe) {
 
     std::thread::spawn(move || { 
         // TODO: try to move connection creation inside Db::new() method
+        let dictionaries = compress::dictionaries(&cfg.db, &cfg.groups);
         let conn = Connection::open(&cfg.db.file).expect("unable to open or create a database file with provided filename");
-        let mut db = Db::new(&conn, rx_db, tx_comm_db, cfg.db, &cfg.groups);
+        if is_prune {
+            cmd::prune::startup(&conn, &cfg.groups, &dictionaries);
+        }
+        let file = cfg.db.file.clone();
+        let read_threads = cfg.db.read_threads;
+        let mut db = Db::new(&conn, rx_db, tx_comm_db, cfg.db, &cfg.groups, &dictionaries);
+        // readers are started once the writer has made the schema and switched to WAL
+        read::spawn(&file, read_threads.max(1), rx_read, &dictionaries);
         db.serve(); 
     });
     std::thread::spawn(move || {
         cmd_serve_dist(dist);
     });
-    cmd_serve_web(comm, addr, cfg.path, cfg.dir, tx_comm, tx_db);
+    let watch = comm_watch::serve(cfg.dir.users.clone(), cfg.dir.wplaces.clone(), tx_comm.clone());
+    let server = server::serve(addr, cfg.path, cfg.dir, cfg.admin, tx_comm, tx_db, tx_read);
+    cmd_serve_web(comm, server, watch);
 }
 
 #[tokio::main(flavor = "current_thread")]
-async fn cmd_serve_web(mut comm: Comm, addr: SocketAddr, cfg_path: ConfigServePath, cfg_dir: ConfigServeDir, tx_comm: Sender<SignalComm>, tx_db: Sender<SignalDb>) {
+async fn cmd_serve_web(mut comm: Comm, server: impl Future<Output = ()> + Send + 'static, watch: impl Future<Output = ()> + Send + 'static) {
     let handle_comm = tokio::spawn(async move { 
         comm.serve().await 
     });
-    let handle_server = tokio::spawn(async move {
-        server::serve(addr, cfg_path, cfg_dir, tx_comm, tx_db).await
-    });
+    tokio::spawn(watch);
+    let handle_server = tokio::spawn(server);
     if let Err(err) = tokio::try_join!(handle_comm, handle_server) {
         panic!("cmd_serve_web finished with error: {err}");
     }
@@ -2,3 +2,8 @@ pub mod session;
 pub mod user;
 pub mod dataflow;
 pub mod wplace; 
+pub mod annotation;
+pub mod info;
+pub mod compress;
+pub mod password;
+pub mod role;
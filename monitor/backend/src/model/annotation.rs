@@ -0,0 +1,27 @@
+use serde::Serialize;
+
+use crate::model::dataflow::{Group, Unit};
+
+
+// Operator note on a group or one of its units, optionally bound to a time range
+#[derive(Serialize, Debug, Clone)]
+pub struct Annotation {
+    #[serde(rename = "i")]
+    pub id: u64,
+    #[serde(rename = "g")]
+    pub group: Group,
+    #[serde(rename = "u")]
+    pub unit: Option<Unit>,
+    #[serde(rename = "f")]
+    pub time_min: Option<i64>,
+    #[serde(rename = "t")]
+    pub time_max: Option<i64>,
+    #[serde(rename = "x")]
+    pub text: String,
+    #[serde(rename = "l")]
+    pub author: String,
+    #[serde(rename = "c")]
+    pub time_created: i64,
+    #[serde(rename = "m")]
+    pub time_modified: i64,
+}
@@ -0,0 +1,83 @@
+use std::{
+    collections::HashMap,
+    io::{self, Read},
+    sync::Arc,
+};
+
+use zstd::{bulk::Compressor, stream::read::Decoder, zstd_safe};
+
+use crate::config::{ConfigCompress, ConfigServeDb, ConfigServeGroup};
+use crate::model::dataflow::Group;
+
+
+// Frames keep the id of their dictionary, so values are decompressed without knowing their unit
+pub type Dictionaries = HashMap<u32, Dictionary>;
+
+#[derive(Debug, Clone)]
+pub struct Dictionary {
+    id: u32,
+    bytes: Arc<Vec<u8>>,
+}
+impl Dictionary {
+    // Only trained dictionaries ('zstd --train') have an id to be found by
+    pub fn new(bytes: Vec<u8>) -> Result<Self, String> {
+        match zstd_safe::get_dict_id_from_dict(&bytes) {
+            Some(id) => Ok(Self{ id: id.get(), bytes: Arc::new(bytes) }),
+            None => Err("zstd dictionary has no id; it should be made by 'zstd --train'".to_string()),
+        }
+    }
+}
+
+// Dictionaries of the units along with those kept for older records; every command reading the database takes them
+pub fn dictionaries(cfg_db: &ConfigServeDb, cfg_groups: &HashMap<Group, ConfigServeGroup>) -> Dictionaries {
+    let iter_unit = cfg_groups.values()
+        .flat_map(|cfg_group| cfg_group.units.values())
+        .filter_map(|cfg_unit| cfg_unit.compress.as_ref().and_then(|cfg_compress| cfg_compress.dictionary.as_ref()));
+    cfg_db.dictionaries.iter().chain(iter_unit)
+        .map(|dictionary| (dictionary.id, dictionary.clone()))
+        .collect()
+}
+
+
+pub struct Codec {
+    compressor: Compressor<'static>,
+    bytes_min: usize,
+}
+impl Codec {
+    pub fn new(cfg: &ConfigCompress) -> io::Result<Self> {
+        let compressor = match &cfg.dictionary {
+            Some(dictionary) => Compressor::with_dictionary(cfg.level, &dictionary.bytes)?,
+            None => Compressor::new(cfg.level)?,
+        };
+        Ok(Self{ compressor, bytes_min: cfg.min_bytes })
+    }
+
+    // None if the value is too small or does not shrink, then it is stored as is
+    pub fn compress(&mut self, bytes: &[u8]) -> Option<Vec<u8>> {
+        if bytes.len() < self.bytes_min {
+            return None;
+        }
+        match self.compressor.compress(bytes) {
+            Ok(compressed) if compressed.len() < bytes.len() => Some(compressed),
+            Ok(_) => None,
+            Err(err) => {
+                println!("[ERR] zstd compression: {}", err); // TODO: log this
+                None
+            },
+        }
+    }
+}
+
+
+pub fn decompress(bytes: &[u8], dictionaries: &Dictionaries) -> io::Result<Vec<u8>> {
+    let mut decoder = match zstd_safe::get_dict_id_from_frame(bytes) {
+        Some(id) => match dictionaries.get(&id.get()) {
+            Some(dictionary) => Decoder::with_dictionary(bytes, &dictionary.bytes)?,
+            None => return Err(io::Error::new(io::ErrorKind::NotFound, format!("zstd dictionary with id {} is not configured", id))),
+        },
+        None => Decoder::with_buffer(bytes)?,
+    };
+    let mut res = Vec::with_capacity(bytes.len() * 2);
+    decoder.read_to_end(&mut res)?;
+    Ok(res)
+}
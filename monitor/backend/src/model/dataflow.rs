@@ -1,11 +1,15 @@
 use std::{
     hash::{Hash, Hasher}, 
+    borrow::Cow,
+    io,
 };
 
 use bytes::Bytes;
 use base64::encode;
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
+use super::compress::{self, Codec, Dictionaries};
+
 
 
 #[derive(Debug)]
@@ -118,6 +122,9 @@ impl Value {
     pub fn into_base64(self) -> String {
         encode(self.bytes)
     }
+    pub fn as_bytes(&self) -> &[u8] {
+        self.bytes.as_ref()
+    }
 }
 impl Clone for Value {
     fn clone(&self) -> Self {
@@ -132,6 +139,19 @@ impl Serialize for Value {
 
 
 
+// Attributes of the MQTT message which delivered a value
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Meta {
+    #[serde(rename = "t")]
+    pub topic: String,
+    #[serde(rename = "q")]
+    pub qos: u8,
+    #[serde(rename = "r")]
+    pub retain: bool,
+    #[serde(rename = "b")]
+    pub broker: String,
+}
+
 #[derive(Serialize, Debug)]
 #[serde(tag = "y")]
 pub enum Update {
@@ -142,7 +162,9 @@ pub enum Update {
     #[serde(rename = "v")]
     Value{
         #[serde(rename = "v")]
-        value: Value
+        value: Value,
+        #[serde(rename = "m", skip_serializing_if = "Option::is_none")]
+        meta: Option<Meta>,
     },
 }
 impl Update {
@@ -150,17 +172,52 @@ impl Update {
         match self {
             Update::Offline => (0, None),
             Update::Online => (1, None),
-            Update::Value{value} => (2, Some(value.bytes.as_ref())),
+            Update::Value{value, ..} => (2, Some(value.bytes.as_ref())),
+        }
+    }
+
+    // Compressed values get their own type code, so they are stored along with uncompressed ones
+    pub fn to_ser_compressed(&self, codec: Option<&mut Codec>) -> (u8, Option<Cow<'_, [u8]>>) {
+        match (self.to_ser(), codec) {
+            ((2, Some(bytes)), Some(codec)) => match codec.compress(bytes) {
+                Some(compressed) => (3, Some(Cow::Owned(compressed))),
+                None => (2, Some(Cow::Borrowed(bytes))),
+            },
+            ((upd_type, bytes), _) => (upd_type, bytes.map(Cow::Borrowed)),
+        }
+    }
+
+    pub fn meta(&self) -> Option<&Meta> {
+        match self {
+            Update::Value{meta, ..} => meta.as_ref(),
+            _ => None,
         }
     }
 
-    pub fn from_ser(upd_type: u8, upd_bytes: Option<Vec<u8>>) -> Self {
+    pub fn without_meta(self) -> Self {
+        match self {
+            Update::Value{value, ..} => Update::Value{value, meta: None},
+            update => update,
+        }
+    }
+
+    // Values are only received while the unit is connected
+    pub fn is_online(&self) -> bool {
+        !matches!(self, Update::Offline)
+    }
+
+    // A value not decompressed, e.g. without its dictionary, is an error: its frame is never taken for the value
+    pub fn from_ser(upd_type: u8, upd_bytes: Option<Vec<u8>>, dictionaries: &Dictionaries) -> io::Result<Self> {
         match upd_type {
-            0 => Self::Offline,
-            1 => Self::Online,
+            0 => Ok(Self::Offline),
+            1 => Ok(Self::Online),
+            3 => match upd_bytes {
+                Some(b) => Ok(Self::Value{value: Value{bytes: compress::decompress(&b, dictionaries)?.into()}, meta: None}),
+                None => Ok(Self::Value{value: Value{bytes: Bytes::new()}, meta: None}),
+            },
             _ => match upd_bytes {
-                Some(b) => Self::Value{value: Value{bytes: b.into()}},
-                None => Self::Value{value: Value{bytes: Bytes::new()}}, // TODO: check conversion
+                Some(b) => Ok(Self::Value{value: Value{bytes: b.into()}, meta: None}),
+                None => Ok(Self::Value{value: Value{bytes: Bytes::new()}, meta: None}), // TODO: check conversion
             }
         }
     }
@@ -170,7 +227,7 @@ impl Clone for Update {
         match self {
             Self::Online => Self::Online,
             Self::Offline => Self::Offline,
-            Self::Value{value} => Self::Value{value: value.clone()},
+            Self::Value{value, meta} => Self::Value{value: value.clone(), meta: meta.clone()},
         }
     }
 }
@@ -0,0 +1,43 @@
+use std::collections::{HashMap, HashSet};
+
+use serde::Serialize;
+
+use crate::config::{ConfigServeGroup, ConfigMeta};
+use crate::model::dataflow::{Group, Unit};
+
+
+// Presentation metadata of a group and of its units as they are configured
+#[derive(Serialize, Debug, Clone)]
+pub struct InfoGroup {
+    #[serde(flatten)]
+    pub meta: ConfigMeta,
+    #[serde(rename = "u")]
+    pub units: HashMap<Unit, ConfigMeta>,
+}
+
+pub fn from_config(cfg_groups: &HashMap<Group, ConfigServeGroup>) -> HashMap<Group, InfoGroup> {
+    let mut map_res = HashMap::with_capacity(cfg_groups.len());
+    for (group, cfg_group) in cfg_groups {
+        let mut units = HashMap::with_capacity(cfg_group.units.len());
+        for (unit, cfg_unit) in cfg_group.units.iter() {
+            units.insert(unit.clone(), cfg_unit.meta.clone());
+        }
+        map_res.insert(group.clone(), InfoGroup{ meta: cfg_group.meta.clone(), units });
+    }
+    map_res
+}
+
+// Leaves only the groups and units of a workplace
+pub fn filter<'a>(info: &HashMap<Group, InfoGroup>, pubtop: impl Iterator<Item = (&'a Group, &'a HashSet<Unit>)>) -> HashMap<Group, InfoGroup> {
+    let mut map_res = HashMap::new();
+    for (group, set_unit) in pubtop {
+        if let Some(info_group) = info.get(group) {
+            let units = info_group.units.iter()
+                .filter(|(unit, _)| set_unit.contains(*unit))
+                .map(|(unit, meta)| (unit.clone(), meta.clone()))
+                .collect();
+            map_res.insert(group.clone(), InfoGroup{ meta: info_group.meta.clone(), units });
+        }
+    }
+    map_res
+}
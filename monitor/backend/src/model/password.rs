@@ -0,0 +1,66 @@
+use argon2::{Argon2, PasswordHash, PasswordHasher, PasswordVerifier};
+use lazy_static::lazy_static;
+use password_hash::{SaltString, rand_core::OsRng};
+use subtle::ConstantTimeEq;
+
+
+lazy_static!{
+    // Hash of a random password: unknown logins are verified against it, so they take as long as known ones
+    static ref HASH_DUMMY: String = hash(SaltString::generate(&mut OsRng).as_str(), Algorithm::Argon2id).unwrap_or_default();
+}
+
+
+#[derive(Clone, Copy, Debug)]
+pub enum Algorithm {
+    Argon2id,
+    Bcrypt,
+}
+
+// Stored passwords are PHC strings of argon2id or modular crypt strings of bcrypt;
+// anything else is a legacy plaintext entry
+pub fn is_hashed(stored: &str) -> bool {
+    stored.starts_with("$argon2") || is_bcrypt(stored)
+}
+
+// Verification takes tens of milliseconds by design: call it off the async runtime
+pub fn verify(stored: &str, given: &str) -> bool {
+    if stored.starts_with("$argon2") {
+        match PasswordHash::new(stored) {
+            Ok(hash) => Argon2::default().verify_password(given.as_bytes(), &hash).is_ok(),
+            Err(err) => {
+                println!("[ERR] password hash is not parsed: {}", err); // TODO: log this
+                false
+            },
+        }
+    } else if is_bcrypt(stored) {
+        bcrypt::verify(given, stored).unwrap_or(false)
+    } else {
+        stored.as_bytes().ct_eq(given.as_bytes()).into()
+    }
+}
+
+// Made on start, so the first unknown login takes no longer than the others
+pub fn init_dummy() {
+    lazy_static::initialize(&HASH_DUMMY);
+}
+
+// The time of a verification is spent as for a known login, the result is of no use
+pub fn verify_dummy(given: &str) {
+    std::hint::black_box(verify(&HASH_DUMMY, given));
+}
+
+pub fn hash(password: &str, algorithm: Algorithm) -> Result<String, String> {
+    match algorithm {
+        Algorithm::Argon2id => {
+            let salt = SaltString::generate(&mut OsRng);
+            Argon2::default().hash_password(password.as_bytes(), &salt)
+                .map(|hash| hash.to_string())
+                .map_err(|err| err.to_string())
+        },
+        Algorithm::Bcrypt => bcrypt::hash(password, bcrypt::DEFAULT_COST).map_err(|err| err.to_string()),
+    }
+}
+
+fn is_bcrypt(stored: &str) -> bool {
+    stored.starts_with("$2a$") || stored.starts_with("$2b$") || stored.starts_with("$2y$")
+}
@@ -0,0 +1,47 @@
+use serde::{Deserialize, Serialize};
+
+
+// Roles are ordered: each one is allowed what the previous ones are
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    Viewer,
+    // users without a role keep what every user could do before roles
+    #[default]
+    Operator,
+    Admin,
+}
+impl Role {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Viewer => "viewer",
+            Self::Operator => "operator",
+            Self::Admin => "admin",
+        }
+    }
+
+    pub fn permits(&self, action: Action) -> bool {
+        *self >= action.role_min()
+    }
+}
+
+
+// What a session asks to do; Comm checks it against the role of the session's user
+#[derive(Clone, Copy, Debug)]
+pub enum Action {
+    // wplace units, their history and live updates
+    View,
+    // changes seen by other users: annotations, acknowledgements
+    Write,
+    // admin routes
+    Admin,
+}
+impl Action {
+    fn role_min(&self) -> Role {
+        match self {
+            Self::View => Role::Viewer,
+            Self::Write => Role::Operator,
+            Self::Admin => Role::Admin,
+        }
+    }
+}
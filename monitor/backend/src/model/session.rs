@@ -15,14 +15,16 @@ use futures_util::{
 };
 
 use crate::actor::{
-    chan::TxConn,
-    conn::{Conn},
+    chan::{TxConn, Frame},
+    conn::{Conn, Scope, FRAME_EXPIRED},
     comm::{Signal as SignalComm, FromSession},
     db::{Signal as SignalDb},
 };
 use crate::model::{
     user::{Login},
-    dataflow::{Group, Unit, Value, Update, Data, Record},
+    wplace::{Name as NameWplace},
+    dataflow::{Value, Update, Data, Record},
+    annotation::Annotation,
 };
 
 
@@ -71,9 +73,20 @@ impl Eq for Token {}
 
 
 
+// Session limits: idle time since the last use, lifetime since login, sessions per user;
+// with is_ws_alive an open connection is a use that lasts while it is open
+#[derive(Clone, Copy, Debug)]
+pub struct Policy {
+    pub idle: Duration,
+    pub lifetime: Option<Duration>,
+    pub count_max: Option<usize>,
+    pub is_ws_alive: bool,
+}
+
+
 enum State {
     Closed,
-    Offline(Instant),
+    Offline,
     Online(HashMap<u64, TxConn>),
 }
 
@@ -82,22 +95,35 @@ pub struct Session {
     idx_conn: u64,
     token: Token,
     login: Login,
+    name_wplace: NameWplace,
     tx_comm: Sender<SignalComm>,
     is_reminded: bool,
-    dur: Duration,
+    policy: Policy,
+    time_created: Instant,
+    time_active: Instant,
 
     state: State,
 }
 impl Session {
 
-    pub fn new(login: Login, token: Token, dur: Duration, tx_comm: Sender<SignalComm>) -> Self {
+    pub fn new(login: Login, token: Token, name_wplace: NameWplace, policy: Policy, tx_comm: Sender<SignalComm>) -> Self {
+        Self::restore(login, token, name_wplace, policy, tx_comm, Duration::ZERO, Duration::ZERO)
+    }
+
+    // Session saved before a restart: it is closed after the rest of its lifetime unless used
+    pub fn restore(login: Login, token: Token, name_wplace: NameWplace, policy: Policy, tx_comm: Sender<SignalComm>, age: Duration, inactive: Duration) -> Self {
+        let now = Instant::now();
         let mut obj = Self {
-            token, login, tx_comm, dur,
+            token, login, name_wplace, tx_comm, policy,
             idx_conn: 0,
             is_reminded: false,
-            state: State::Offline(Instant::now()),
+            time_created: now.checked_sub(age).unwrap_or(now),
+            time_active: now.checked_sub(inactive).unwrap_or(now),
+            state: State::Offline,
         };
-        obj.remind(obj.dur.clone());
+        if let Some(dur) = obj.expires_in() {
+            obj.remind(dur);
+        }
         obj
     }
 
@@ -122,6 +148,48 @@ impl Session {
         }
     }
 
+    pub async fn send_annotation(&mut self, annotation: Annotation, is_removed: bool) {
+        if let State::Online(map) = &mut self.state {
+            let len = map.len();
+            let mut vec_fut = Vec::with_capacity(len);
+            let mut vec_id = Vec::with_capacity(len);
+            for (id_conn, tx_conn) in map.iter(){
+                vec_fut.push( tx_conn.send_annotation(annotation.clone(), is_removed));
+                vec_id.push(id_conn.to_owned());
+            }
+            let res_vec = join_all(vec_fut).await;
+            for (res, id_conn) in res_vec.iter().zip(vec_id.iter()) {
+                if res.is_err() {
+                    map.remove(id_conn);
+                }
+            }
+            if map.is_empty() {
+                self.go_offline();
+            }
+        }
+    }
+
+    pub async fn send_scope(&mut self, scope: Scope) {
+        if let State::Online(map) = &mut self.state {
+            let len = map.len();
+            let mut vec_fut = Vec::with_capacity(len);
+            let mut vec_id = Vec::with_capacity(len);
+            for (id_conn, tx_conn) in map.iter(){
+                vec_fut.push( tx_conn.send_scope(scope.clone()));
+                vec_id.push(id_conn.to_owned());
+            }
+            let res_vec = join_all(vec_fut).await;
+            for (res, id_conn) in res_vec.iter().zip(vec_id.iter()) {
+                if res.is_err() {
+                    map.remove(id_conn);
+                }
+            }
+            if map.is_empty() {
+                self.go_offline();
+            }
+        }
+    }
+
     pub async fn conn_close(&mut self, id: &u64) {
         if let State::Online(map) = &mut self.state {
             if let Some(tx) = map.remove(id) {
@@ -133,9 +201,18 @@ impl Session {
         }
     }
 
-    pub fn conn_add(&mut self, ws: WebSocket, map: HashMap<Group, Vec<Unit>>, tx_db: Sender<SignalDb>) {
+    // Connections are closed with the frame and the session is not kept anymore
+    pub async fn close(&mut self, frame: Frame) {
+        if let State::Online(map) = &self.state {
+            join_all(map.values().map(|tx| tx.send_close_with(frame))).await;
+        }
+        self.state = State::Closed;
+    }
+
+    pub fn conn_add(&mut self, ws: WebSocket, scope: Scope, tx_db: Sender<SignalDb>) {
         self.idx_conn += 1;
-        let conn = Conn::new(self.login.clone(), self.token.clone(), self.idx_conn, ws, self.tx_comm.clone(), map, tx_db);
+        self.time_active = Instant::now();
+        let conn = Conn::new(self.login.clone(), self.token.clone(), self.idx_conn, ws, self.tx_comm.clone(), scope, tx_db);
         if let State::Online(map) = &mut self.state {
             map.insert(self.idx_conn, conn.get_tx());
         } else {
@@ -146,23 +223,61 @@ impl Session {
         tokio::spawn(async move { conn.serve().await });  
     }
 
-    pub fn heartbeat(&mut self) -> Result<(), ()> {
+    pub async fn heartbeat(&mut self) -> Result<(), ()> {
         self.is_reminded = false;
-        if let State::Offline(inst) = self.state {
-            let elapsed = inst.elapsed();
-            if elapsed >= self.dur {
-                self.state = State::Closed;
-                return Err(());
-            } else {
-                self.remind(self.dur - elapsed);
-            }
+        match self.expires_in() {
+            Some(Duration::ZERO) => {
+                self.close(FRAME_EXPIRED).await;
+                Err(())
+            },
+            Some(dur) => {
+                self.remind(dur);
+                Ok(())
+            },
+            None => Ok(()),
         }
-        Ok(())
+    }
+
+    pub fn get_name_wplace(&self) -> &NameWplace {
+        &self.name_wplace
+    }
+
+    pub fn set_name_wplace(&mut self, name_wplace: NameWplace) {
+        self.name_wplace = name_wplace;
     }
 
     pub fn refresh(&mut self) {
-        if let State::Offline(_) = self.state {
-            self.state = State::Offline(Instant::now());
+        self.time_active = Instant::now();
+    }
+
+    pub fn is_expired(&self) -> bool {
+        self.expires_in() == Some(Duration::ZERO)
+    }
+
+    // None if the session is closed
+    pub fn inactive(&self) -> Option<Duration> {
+        match &self.state {
+            State::Closed => None,
+            State::Online(_) if self.policy.is_ws_alive => Some(Duration::ZERO),
+            _ => Some(self.time_active.elapsed()),
+        }
+    }
+
+    pub fn age(&self) -> Duration {
+        self.time_created.elapsed()
+    }
+
+    // Time left till the nearest limit; None if no limit applies now
+    fn expires_in(&self) -> Option<Duration> {
+        let idle = match self.state {
+            State::Closed => return Some(Duration::ZERO),
+            State::Online(_) if self.policy.is_ws_alive => None,
+            _ => Some(self.policy.idle.saturating_sub(self.time_active.elapsed())),
+        };
+        let lifetime = self.policy.lifetime.map(|lifetime| lifetime.saturating_sub(self.time_created.elapsed()));
+        match (idle, lifetime) {
+            (Some(idle), Some(lifetime)) => Some(idle.min(lifetime)),
+            (idle, lifetime) => idle.or(lifetime),
         }
     }
 
@@ -187,9 +302,12 @@ impl Session {
     }
 
     fn go_offline(&mut self) {
-        self.state = State::Offline(Instant::now());
+        self.state = State::Offline;
+        self.time_active = Instant::now();
         if !self.is_reminded {
-            self.remind(self.dur);
+            if let Some(dur) = self.expires_in() {
+                self.remind(dur);
+            }
         }
     }
 }
@@ -1,6 +1,6 @@
 use std::{
     hash::{Hash, Hasher}, 
-    collections::{HashMap},
+    collections::{HashMap, HashSet},
     str::FromStr,
 };
 
@@ -15,12 +15,17 @@ use tokio::{
 
 
 use crate::model::{
-    session::{Session, Token},
-    dataflow::{Group, Unit, Value, Update, Data, Record},
+    session::{Session, Token, Policy},
+    role::{Role, Action},
+    dataflow::{Group, Value, Update, Data, Record},
+    annotation::Annotation,
+    info::InfoGroup,
     wplace::{Name as NameWplace},
 };
 use crate::actor::{
+    chan::Frame,
     comm::{Signal as SignalComm},
+    conn::{Scope, FRAME_EVICTED},
     db::{Signal as SignalDb},
 };
 
@@ -78,85 +83,188 @@ impl Eq for Login {}
 
 
 pub struct User {
-    dur_sess: Duration,
+    policy: Policy,
+    role: Option<Role>,
     login: Login,
-    name_wplace: NameWplace,
     tx_comm: Sender<SignalComm>,
     map: HashMap<Token, Session>,
 }
 impl User {
-    pub fn new(login: Login, name_wplace: NameWplace, dur_sess: Duration, tx_comm: Sender<SignalComm>) -> Self {
+    pub fn new(login: Login, role: Option<Role>, policy: Policy, tx_comm: Sender<SignalComm>) -> Self {
         Self {
-            login, tx_comm, dur_sess, name_wplace,
+            login, tx_comm, policy, role,
             map: HashMap::new(),
         }
     }
 
-    pub fn sess_heartbeat(&mut self, token: &Token) -> Result<(), ()> {
+    // Err if the session is expired and closed
+    pub async fn sess_heartbeat(&mut self, token: &Token) -> Result<(), ()> {
         if let Some(session) = self.map.get_mut(token) {
-            if let Err(_) = session.heartbeat() {
+            if let Err(_) = session.heartbeat().await {
                 self.map.remove(token);       
+                return Err(());
             }
         }
-        if self.map.is_empty() {
-            Err(())
-        } else {
-            Ok(())
-        }
+        Ok(())
     }
 
-    pub fn sess_make(&mut self) -> Token {
+    // The oldest sessions above the per user limit are closed
+    pub async fn sess_make(&mut self, name_wplace: NameWplace) -> Token {
         let mut token = Token::new();
         while self.map.contains_key(&token) {
             token = Token::new();
         }
-        let sesssion: Session = Session::new(self.login.clone(), token.clone(), self.dur_sess, self.tx_comm.clone());
+        let sesssion: Session = Session::new(self.login.clone(), token.clone(), name_wplace, self.policy, self.tx_comm.clone());
         self.map.insert(token.clone(), sesssion);
+        while let Some(mut session) = self.sess_evict() {
+            session.close(FRAME_EVICTED).await;
+        }
         token
     }
 
-    pub fn sess_check(&mut self, token: &Token) -> bool {
+    pub fn sess_restore(&mut self, token: Token, name_wplace: NameWplace, age: Duration, inactive: Duration) {
+        if !self.map.contains_key(&token) {
+            let session = Session::restore(self.login.clone(), token.clone(), name_wplace, self.policy, self.tx_comm.clone(), age, inactive);
+            self.map.insert(token, session);
+            while self.sess_evict().is_some() {}
+        }
+    }
+
+    // Token, wplace, age and inactive time of every open session
+    pub fn iter_sess_inactive(&self) -> impl Iterator<Item = (&Token, &NameWplace, Duration, Duration)> {
+        self.map.iter().filter_map(|(token, session)| session.inactive().map(|inactive| (token, session.get_name_wplace(), session.age(), inactive)))
+    }
+
+    pub fn sess_wplace(&self, token: &Token) -> Option<&NameWplace> {
+        self.map.get(token).map(|session| session.get_name_wplace())
+    }
+
+    // Wplaces of all sessions of the user
+    pub fn iter_wplace(&self) -> impl Iterator<Item = &NameWplace> {
+        self.map.values().map(|session| session.get_name_wplace())
+    }
+
+    // Connections of the session get the units of its new wplace; false if there is no such session
+    pub async fn sess_switch(&mut self, token: &Token, wplace: &Wplace, info: HashMap<Group, InfoGroup>) -> bool {
         if let Some(session) = self.map.get_mut(token) {
-            session.refresh();
+            session.set_name_wplace(wplace.get_name().clone());
+            session.send_scope(scope_make(wplace, info)).await;
             true
         } else {
             false
         }
     }
 
-    pub async fn send_data(&mut self, data: Data<Record<Update>>) {
+    pub fn sess_check(&mut self, token: &Token) -> bool {
+        match self.map.get_mut(token) {
+            Some(session) if !session.is_expired() => {
+                session.refresh();
+                true
+            },
+            _ => false,
+        }
+    }
+
+    // false if there is no such session
+    pub async fn sess_close(&mut self, token: &Token, frame: Frame) -> bool {
+        if let Some(mut session) = self.map.remove(token) {
+            session.close(frame).await;
+            true
+        } else {
+            false
+        }
+    }
+
+    // Count of the closed sessions
+    pub async fn sess_close_all(&mut self, frame: Frame) -> usize {
+        let count = self.map.len();
+        for (_, mut session) in self.map.drain() {
+            session.close(frame).await;
+        }
+        count
+    }
+
+    // Sessions in the wplaces of the set are closed; count of the closed sessions
+    pub async fn sess_close_wplace(&mut self, set_name: &HashSet<NameWplace>, frame: Frame) -> usize {
+        let vec_token: Vec<Token> = self.map.iter()
+            .filter(|(_, session)| set_name.contains(session.get_name_wplace()))
+            .map(|(token, _)| token.clone())
+            .collect();
+        for token in vec_token.iter() {
+            if let Some(mut session) = self.map.remove(token) {
+                session.close(frame).await;
+            }
+        }
+        vec_token.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    pub async fn send_data(&mut self, name_wplace: &NameWplace, data: Data<Record<Update>>) {
         for (_, session) in self.map.iter_mut() {
-            if session.is_online() {
+            if session.is_online() && session.get_name_wplace() == name_wplace {
                 session.send_data(data.clone()).await;
             }
         }
     }
 
+    pub async fn send_annotation(&mut self, name_wplace: &NameWplace, annotation: Annotation, is_removed: bool) {
+        for (_, session) in self.map.iter_mut() {
+            if session.is_online() && session.get_name_wplace() == name_wplace {
+                session.send_annotation(annotation.clone(), is_removed).await;
+            }
+        }
+    }
+
     pub async fn conn_close(&mut self, token: &Token, id: &u64) {
         if let Some(session) = self.map.get_mut(token) {
             session.conn_close(id).await;
         }
     }
 
-    pub fn conn_add(&mut self, token: &Token, wplace: &Wplace, ws: WebSocket, tx_db: Sender<SignalDb> ) -> Result<(), WebSocket> {
-        if let Some(session) = self.map.get_mut(token) {
-            let mut map: HashMap<Group, Vec<Unit>> = HashMap::with_capacity(wplace.len_pubtop());
-            for (group, vec_unit) in wplace.iter_pubtop() {
-                let mut vec = Vec::with_capacity(vec_unit.len());
-                for unit in vec_unit {
-                    vec.push(unit.clone())
-                }
-                map.insert(group.clone(), vec);
-            }
-            session.conn_add(ws, map, tx_db);
+    pub fn conn_add(&mut self, token: &Token, wplace: &Wplace, info: HashMap<Group, InfoGroup>, ws: WebSocket, tx_db: Sender<SignalDb> ) -> Result<(), WebSocket> {
+        if let Some(session) = self.map.get_mut(token).filter(|session| session.get_name_wplace() == wplace.get_name()) {
+            session.conn_add(ws, scope_make(wplace, info), tx_db);
             Ok(())
         } else {
             Err(ws)
         }
     }
 
-    pub fn get_name_wplace(&self) -> &NameWplace {
-        &self.name_wplace
+    // Open connections in the reloaded wplace get its units
+    pub async fn send_scope(&mut self, wplace: &Wplace, info: HashMap<Group, InfoGroup>) {
+        let scope = scope_make(wplace, info);
+        for (_, session) in self.map.iter_mut() {
+            if session.is_online() && session.get_name_wplace() == wplace.get_name() {
+                session.send_scope(scope.clone()).await;
+            }
+        }
+    }
+
+    pub fn set_role(&mut self, role: Option<Role>) {
+        self.role = role;
+    }
+
+    // A role of the user overrides the one of its wplace
+    pub fn permits(&self, role_wplace: Option<Role>, action: Action) -> bool {
+        self.role.or(role_wplace).unwrap_or_default().permits(action)
+    }
+
+    fn sess_evict(&mut self) -> Option<Session> {
+        let count_max = self.policy.count_max?;
+        if self.map.len() <= count_max {
+            return None;
+        }
+        let token = self.map.iter().max_by_key(|(_, session)| session.age()).map(|(token, _)| token.clone())?;
+        println!("[INFO] the oldest session of \"{}\" is closed: limit of {} sessions", self.login.as_str(), count_max); // TODO: log this
+        self.map.remove(&token)
     }
     
 }
+
+
+fn scope_make(wplace: &Wplace, info: HashMap<Group, InfoGroup>) -> Scope {
+    Scope{ map: wplace.map_pubtop(), info }
+}
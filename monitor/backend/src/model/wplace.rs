@@ -1,14 +1,19 @@
 use core::slice::{Iter};
 use std::{
     hash::{Hash, Hasher}, 
-    collections::{HashSet, hash_set::Iter as IterSet, HashMap, hash_map::Iter as IterMap},
+    collections::{HashSet, hash_set::Iter as IterSet, HashMap, hash_map::{Iter as IterMap, Keys}},
 };
 
 use crate::model::{
     user::{Login},
     dataflow::{Group, Unit},
+    role::Role,
+    info::InfoGroup,
 };
+use crate::config::{ConfigWplace, ConfigMeta, ConfigPattern};
 
+// Joins the names of a united wplace, so it is not taken in names of wplace files
+const SEP_UNION: char = '+';
 
 #[derive(Debug)]
 pub struct Name {
@@ -18,9 +23,23 @@ impl Name {
     pub fn new(val: String) -> Self {
         Self { val }
     }
+    // Name of the wplace united from several ones: the same for any order of the names
+    pub fn union(names: &[String]) -> Self {
+        let mut vec: Vec<&str> = names.iter().map(|name| name.as_str()).collect();
+        vec.sort_unstable();
+        vec.dedup();
+        Self { val: vec.join(&SEP_UNION.to_string()) }
+    }
+    // A file named as a union would share the wplace of the union
+    pub fn is_file(name: &str) -> bool {
+        !name.contains(SEP_UNION)
+    }
     pub fn into_string(self) -> String {
         self.val
     }
+    pub fn as_str(&self) -> &str {
+        &self.val
+    }
 }
 impl Clone for Name {
     fn clone(&self) -> Self {
@@ -41,18 +60,111 @@ impl Eq for Name {}
 
 
 
+// Units and role of one of the wplaces united in another
+type Part = (HashMap<Group, HashSet<Unit>>, Option<Role>);
+
 #[derive(Debug)]
 pub struct Wplace {
     name: Name,
     set_login: HashSet<Login>,
     pubtop: HashMap<Group, HashSet<Unit>>,
+    role: Option<Role>,
+    // Wplaces united in this one with their own units and roles; a single wplace is its only part
+    parts: HashMap<Name, Part>,
 }
 impl Wplace {
-    pub fn new(name: Name, pubtop: HashMap<Group, HashSet<Unit>>) -> Self {
+    pub fn new(name: Name, pubtop: HashMap<Group, HashSet<Unit>>, role: Option<Role>) -> Self {
+        let mut parts = HashMap::with_capacity(1);
+        parts.insert(name.clone(), (pubtop.clone(), role));
         Self {
-            name, pubtop,
+            name, pubtop, role, parts,
+            set_login: HashSet::new(),
+        }
+    }
+
+    // Units of the union are those of any part; its role is the lowest one given by the parts
+    pub fn union(name: Name, vec_wplace: Vec<Wplace>) -> Self {
+        let mut obj = Self {
+            name,
             set_login: HashSet::new(),
+            pubtop: HashMap::new(),
+            role: None,
+            parts: HashMap::with_capacity(vec_wplace.len()),
+        };
+        for wplace in vec_wplace {
+            obj.parts.extend(wplace.parts);
+        }
+        obj.pubtop = obj.unite();
+        obj.role = obj.parts.values().filter_map(|(_, role)| *role).min();
+        obj
+    }
+
+    // Patterns, tags and "*" are resolved against the configured units; plain names are taken as is
+    // Exclusions of a group apply last, also to the units given by tags of the wplace
+    pub fn from_config(name: Name, cfg: ConfigWplace, info: &HashMap<Group, InfoGroup>) -> Self {
+        let mut pubtop: HashMap<Group, HashSet<Unit>> = HashMap::with_capacity(cfg.groups.len());
+        let mut map_exclude: HashMap<Group, Vec<ConfigPattern>> = HashMap::with_capacity(cfg.groups.len());
+        for (group_string, select) in cfg.groups {
+            let group = Group::new(group_string);
+            let mut set_unit: HashSet<Unit> = HashSet::new();
+            for pattern in select.units.iter() {
+                if let ConfigPattern::Exact(unit) = pattern {
+                    set_unit.insert(Unit::new(unit.clone()));
+                }
+            }
+            if let Some(info_group) = info.get(&group) {
+                for (unit, meta) in info_group.units.iter() {
+                    if select.is_all
+                        || select.units.iter().any(|pattern| pattern.is_match(unit.to_str()))
+                        || is_tagged(&select.tags, &info_group.meta, meta) {
+                        set_unit.insert(unit.clone());
+                    }
+                }
+            }
+            if !select.exclude.is_empty() {
+                map_exclude.insert(group.clone(), select.exclude);
+            }
+            pubtop.entry(group).or_default().extend(set_unit);
+        }
+        if !cfg.tags.is_empty() {
+            for (group, info_group) in info.iter() {
+                for (unit, meta) in info_group.units.iter() {
+                    if is_tagged(&cfg.tags, &info_group.meta, meta) {
+                        pubtop.entry(group.clone()).or_default().insert(unit.clone());
+                    }
+                }
+            }
+        }
+        for (group, vec_exclude) in map_exclude {
+            if let Some(set_unit) = pubtop.get_mut(&group) {
+                set_unit.retain(|unit| !vec_exclude.iter().any(|pattern| pattern.is_match(unit.to_str())));
+            }
+        }
+        Self::new(name, pubtop, cfg.role)
+    }
+
+    // Parts are taken from the reloaded wplace, the logins are kept; the old units are returned
+    pub fn update(&mut self, other: &Wplace) -> HashMap<Group, HashSet<Unit>> {
+        for (name, part) in other.parts.iter() {
+            if let Some(part_old) = self.parts.get_mut(name) {
+                *part_old = part.clone();
+            }
         }
+        self.role = self.parts.values().filter_map(|(_, role)| *role).min();
+        let pubtop = self.unite();
+        std::mem::replace(&mut self.pubtop, pubtop)
+    }
+
+    pub fn has_part(&self, name: &Name) -> bool {
+        self.parts.contains_key(name)
+    }
+
+    pub fn iter_part(&self) -> Keys<'_, Name, Part> {
+        self.parts.keys()
+    }
+
+    pub fn get_role(&self) -> Option<Role> {
+        self.role
     }
 
     pub fn remove_login(&mut self, login: &Login) {
@@ -67,6 +179,22 @@ impl Wplace {
         self.pubtop.iter()
     }
 
+    pub fn map_pubtop(&self) -> HashMap<Group, Vec<Unit>> {
+        let mut map = HashMap::with_capacity(self.pubtop.len());
+        for (group, set_unit) in self.pubtop.iter() {
+            map.insert(group.clone(), set_unit.iter().cloned().collect());
+        }
+        map
+    }
+
+    // The group is in the wplace with every one of the units
+    pub fn check_units<'a>(&self, group: &Group, mut iter_unit: impl Iterator<Item = &'a Unit>) -> bool {
+        match self.pubtop.get(group) {
+            Some(set) => iter_unit.all(|unit| set.contains(unit)),
+            None => false,
+        }
+    }
+
     pub fn check_unit(&self, group: &Group, unit: &Unit) -> bool {
         if let Some(set) = self.pubtop.get(group) {
             set.contains(unit)
@@ -79,6 +207,10 @@ impl Wplace {
         self.set_login.iter()
     }
 
+    pub fn has_login(&self, login: &Login) -> bool {
+        self.set_login.contains(login)
+    }
+
     pub fn get_name(&self) -> &Name {
         &self.name
     }
@@ -87,11 +219,22 @@ impl Wplace {
         self.set_login.len()
     }
 
-    pub fn len_pubtop(&self) -> usize {
-        self.pubtop.len()
-    }
-
     pub fn is_empty(&self) -> bool {
         self.set_login.is_empty()
     }
-}
\ No newline at end of file
+
+    fn unite(&self) -> HashMap<Group, HashSet<Unit>> {
+        let mut pubtop: HashMap<Group, HashSet<Unit>> = HashMap::new();
+        for (pubtop_part, _) in self.parts.values() {
+            for (group, set_unit) in pubtop_part.iter() {
+                pubtop.entry(group.clone()).or_default().extend(set_unit.iter().cloned());
+            }
+        }
+        pubtop
+    }
+}
+
+
+fn is_tagged(tags: &[String], meta_group: &ConfigMeta, meta_unit: &ConfigMeta) -> bool {
+    tags.iter().any(|tag| meta_group.tags.contains(tag) || meta_unit.tags.contains(tag))
+}
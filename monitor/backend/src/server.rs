@@ -1,6 +1,6 @@
 use std::{
-    collections::{HashSet, HashMap},
-    path::{Path as FsPath, PathBuf as FsPathBuf},
+    collections::HashMap,
+    path::PathBuf as FsPathBuf,
     net::SocketAddr, 
     str::FromStr,
     sync::Arc,
@@ -11,6 +11,7 @@ use serde_json::{from_str as deser, to_string as ser};
 use http::StatusCode;
 use futures_util::{FutureExt, stream::StreamExt, SinkExt};
 use serde::{Deserialize, Serialize};
+use subtle::ConstantTimeEq;
 use tokio::time::{timeout, Duration};
 use tokio::sync::{
     Mutex,
@@ -31,45 +32,85 @@ mod model;
 
 use reject::{handle as reject_handle, ErrorServer};
 use adapter::{Comm as AdapterComm, Db as AdapterDb};
-use model::{Sess, Auth, QueryHist, DtoRecord, DtoUpdate};
+use model::{Sess, Auth, BodyWplace, DtoWplaces, QueryWplace, DtoWplaceInfo, QueryHist, QueryAggregate, QueryAnnotation, BodyAnnotationAdd, BodyAnnotationEdit, DtoRecord, DtoUpdate, DtoBackup, DtoRevoke, DtoHistTime};
 use crate::fs;
-use crate::config::{ConfigServePath, ConfigServeDir, ConfigUser, ConfigWplace};
+use crate::config::{ConfigServePath, ConfigServeDir, ConfigServeAdmin, ConfigUser, ConfigWplace};
 use crate::model::{
     session::Token, 
     user::Login,
     dataflow::{Group, Unit},
-    wplace::{Wplace, Name as NameWplace},
+    wplace::Name as NameWplace,
+    password,
+    role::Action,
 };
 use crate::actor::{
     comm::{Signal as SignalComm, FromServer as FromServerComm},
-    db::{Signal as SignalDb},
+    db::{Signal as SignalDb, repo_aggregate::Window},
+    db::read::{Signal as SignalRead, RangeTime},
 };
 
 
-pub async fn serve(addr: SocketAddr, paths: ConfigServePath, dirs: ConfigServeDir, tx_comm: Sender<SignalComm>, tx_db: Sender<SignalDb>) {
+const HIST_LIMIT_DEFAULT: u32 = 100;
+const HIST_LIMIT_MAX: u32 = 1000;
+const AGGREGATE_BUCKETS_MAX: i64 = 1000;
+const AGGREGATE_PERCENTILES_MAX: usize = 10;
+const AGGREGATE_PERCENTILES_DEFAULT: [f64; 3] = [50.0, 95.0, 99.0];
+const ANNOTATION_TEXT_MAX: usize = 4096;
+
+
+pub async fn serve(addr: SocketAddr, paths: ConfigServePath, dirs: ConfigServeDir, admin: Option<ConfigServeAdmin>, tx_comm: Sender<SignalComm>, tx_db: Sender<SignalDb>, tx_read: Sender<SignalRead>) {
     let adapter_comm = AdapterComm::new(tx_comm);
-    let adapter_db = AdapterDb::new(tx_db);
+    let adapter_db = AdapterDb::new(tx_db, tx_read);
 
     let dir_public_opt = dirs.public.clone();
     let path_public_opt = paths.public;
     let dirs_arc = Arc::new(Mutex::new(dirs));
+    let admin_arc = Arc::new(admin);
 
     let path_app_login = warp::post()
         .and( warp::path("login") )
         .and( warp::body::content_length_limit(1024 * 2) )
         .and( warp::body::json() )
+        .and( warp::addr::remote() )
         .and( with(dirs_arc.clone()) )
         .and( with(adapter_comm.clone()) )
         .and_then( act_login );
 
+    let path_app_logout = warp::post()
+        .and( warp::path("logout") )
+        .and( warp::path::end() )
+        .and( warp::header::<String>("sess").and_then(handle_sess_parse) )
+        .and( with(adapter_comm.clone()) )
+        .and_then( act_logout );
+
     let path_app_ws = warp::path("ws")
         .and( warp::ws() )
         .and( with(adapter_comm.clone()) )
         .map( act_ws );
 
-    let path_app_wplace = warp::path("wplace")
+    let path_app_wplace_switch = warp::post()
+        .and( warp::path("wplace") )
+        .and( warp::path::end() )
+        .and( warp::header::<String>("sess").and_then(handle_sess_parse) )
+        .and( warp::body::content_length_limit(1024 * 2) )
+        .and( warp::body::json() )
+        .and( with(dirs_arc.clone()) )
+        .and( with(adapter_comm.clone()) )
+        .and_then( act_wplace_switch );
+
+    let path_app_wplaces = warp::get()
+        .and( warp::path("wplaces") )
+        .and( warp::path::end() )
+        .and( warp::header::<String>("sess").and_then(handle_sess_parse) )
+        .and( with(dirs_arc.clone()) )
+        .and( with(adapter_comm.clone()) )
+        .and_then( act_wplaces );
+
+    let path_app_wplace = warp::get()
+        .and( warp::path("wplace") )
         .and( warp::header::<String>("sess").and_then(handle_sess_parse) )
         .and( with(adapter_comm.clone()) )
+        .and( warp::query::<QueryWplace>() )
         .and_then(act_wplace);
 
     let path_app_wplace_last = warp::path("wplace-last")
@@ -78,19 +119,99 @@ pub async fn serve(addr: SocketAddr, paths: ConfigServePath, dirs: ConfigServeDi
         .and( with(adapter_db.clone()) )
         .and_then(act_wplace_last);
 
+    let path_app_aggregate = warp::path("aggregate")
+        .and( warp::header::<String>("sess").and_then(handle_sess_parse) )
+        .and( with(adapter_comm.clone()) )
+        .and( with(adapter_db.clone()) )
+        .and( warp::query::<QueryAggregate>() )
+        .and_then( act_aggregate );
+
+    let path_app_annotation_get = warp::get()
+        .and( warp::path("annotation") )
+        .and( warp::path::end() )
+        .and( warp::header::<String>("sess").and_then(handle_sess_parse) )
+        .and( with(adapter_comm.clone()) )
+        .and( with(adapter_db.clone()) )
+        .and( warp::query::<QueryAnnotation>() )
+        .and_then( act_annotation_get );
+
+    let path_app_annotation_add = warp::post()
+        .and( warp::path("annotation") )
+        .and( warp::path::end() )
+        .and( warp::header::<String>("sess").and_then(handle_sess_parse) )
+        .and( warp::body::content_length_limit(1024 * 16) )
+        .and( warp::body::json() )
+        .and( with(adapter_comm.clone()) )
+        .and( with(adapter_db.clone()) )
+        .and_then( act_annotation_add );
+
+    let path_app_annotation_edit = warp::put()
+        .and( warp::path("annotation") )
+        .and( warp::path::param::<u64>() )
+        .and( warp::path::end() )
+        .and( warp::header::<String>("sess").and_then(handle_sess_parse) )
+        .and( warp::body::content_length_limit(1024 * 16) )
+        .and( warp::body::json() )
+        .and( with(adapter_comm.clone()) )
+        .and( with(adapter_db.clone()) )
+        .and_then( act_annotation_edit );
+
+    let path_app_annotation_remove = warp::delete()
+        .and( warp::path("annotation") )
+        .and( warp::path::param::<u64>() )
+        .and( warp::path::end() )
+        .and( warp::header::<String>("sess").and_then(handle_sess_parse) )
+        .and( with(adapter_comm.clone()) )
+        .and( with(adapter_db.clone()) )
+        .and_then( act_annotation_remove );
+
     let path_app_hist = warp::path("hist")
         .and( warp::header::<String>("sess").and_then(handle_sess_parse) )
-        .and( with(adapter_comm) )
-        .and( with(adapter_db) )
+        .and( with(adapter_comm.clone()) )
+        .and( with(adapter_db.clone()) )
         .and( warp::query::<QueryHist>() )
         .and_then( act_hist );
+
+    let filter_admin = warp::header::optional::<String>("admin")
+        .and( warp::header::optional::<String>("sess") )
+        .and( with(admin_arc.clone()) )
+        .and( with(adapter_comm.clone()) )
+        .and_then( handle_admin_auth )
+        .untuple_one();
+
+    let path_app_admin_backup = warp::post()
+        .and( warp::path("admin") )
+        .and( warp::path("backup") )
+        .and( warp::path::end() )
+        .and( filter_admin.clone() )
+        .and( with(adapter_db) )
+        .and_then( act_admin_backup );
+
+    let path_app_admin_revoke = warp::post()
+        .and( warp::path("admin") )
+        .and( warp::path("revoke") )
+        .and( warp::path::param::<String>() )
+        .and( warp::path::end() )
+        .and( filter_admin )
+        .and( with(adapter_comm) )
+        .and_then( act_admin_revoke );
     
     let path_app = paths.app.and(
             path_app_login
+            .or(path_app_logout)
             .or(path_app_ws)
+            .or(path_app_wplace_switch)
+            .or(path_app_wplaces)
             .or(path_app_wplace)
             .or(path_app_hist)
+            .or(path_app_aggregate)
             .or(path_app_wplace_last)
+            .or(path_app_admin_backup)
+            .or(path_app_admin_revoke)
+            .or(path_app_annotation_get)
+            .or(path_app_annotation_add)
+            .or(path_app_annotation_edit)
+            .or(path_app_annotation_remove)
         );
     
     if let Some(path_public) = path_public_opt {
@@ -122,55 +243,178 @@ fn act_ws(handle_ws: warp::ws::Ws, adapter_comm: AdapterComm) -> impl Reply {
 }
 
 async fn act_hist((login, token): (Login, Token), adapter_comm: AdapterComm, mut adapter_db: AdapterDb, query: QueryHist) -> Result<impl Reply, Rejection> {
-    let (idx_min, idx_max) = handle_min_max(query.min, query.max)?;
+    // ids come in pairs, one of them is not taken for a time query
+    if query.min.is_some() != query.max.is_some() {
+        return Err(reject_custom(ErrorServer::BadRequest));
+    }
+    if let (Some(min), Some(max)) = (query.min, query.max) {
+        let (idx_min, idx_max) = handle_min_max(min, max)?;
+        let (group, unit) = adapter_comm.unit_check(login, token, query.group, query.unit).await?;
+        let records = adapter_db.get_data(group.clone(), unit.clone(), idx_min, idx_max).await?;
+        // let mut vec_res = Vec::with_capacity(records.len());
+        // for record in records {
+        //     vec_res.push(DtoRecord::new(record));
+        // }
+        // annotations are those over the time of the records, as with the time query
+        let time_min = records.iter().map(|record| record.time).min();
+        let time_max = records.iter().map(|record| record.time).max();
+        let annotations = match (time_min, time_max) {
+            (Some(time_min), Some(time_max)) => adapter_db.annotations(group, Some(unit), Some(time_min), Some(time_max)).await?,
+            _ => Vec::new(),
+        };
+        return Ok( warp::reply::json(&DtoHistTime{ records, cursor: None, annotations }) );
+    }
+    let limit = handle_limit(query.limit)?;
+    let after = query.cursor.as_deref().map(help_cursor_parse).transpose().map_err(|_| reject_custom(ErrorServer::BadRequest))?;
+    // one record more than the limit tells whether there is a next page
+    let range = RangeTime{ time_min: query.from, time_max: query.to, after, limit: limit + 1 };
     let (group, unit) = adapter_comm.unit_check(login, token, query.group, query.unit).await?;
-    let records = adapter_db.get_data(group, unit, idx_min, idx_max).await?;
-    // let mut vec_res = Vec::with_capacity(records.len());
-    // for record in records {
-    //     vec_res.push(DtoRecord::new(record));
-    // }
-    Ok( warp::reply::json(&records) )
+    let annotations = adapter_db.annotations(group.clone(), Some(unit.clone()), query.from, query.to).await?;
+    let mut records = adapter_db.get_data_time(group, unit, range).await?;
+    let cursor = if records.len() > limit as usize {
+        records.truncate(limit as usize);
+        records.last().map(|record| format!("{}_{}", record.time, record.id))
+    } else {
+        None
+    };
+    Ok( warp::reply::json(&DtoHistTime{ records, cursor, annotations }) )
+}
+
+async fn act_aggregate((login, token): (Login, Token), adapter_comm: AdapterComm, adapter_db: AdapterDb, query: QueryAggregate) -> Result<impl Reply, Rejection> {
+    let window = handle_window(query.from, query.to, query.bucket)?;
+    let percentiles = handle_percentiles(query.percentiles.as_deref())?;
+    let mut wplace = adapter_comm.wplace_get(login, token, Action::View).await?;
+    let units_wplace = wplace.remove(&query.group).ok_or_else(|| reject_custom(ErrorServer::Unauthorized))?;
+    let units = match query.unit {
+        Some(unit) if units_wplace.contains(&unit) => vec![unit],
+        Some(_) => return Err(reject_custom(ErrorServer::Unauthorized)),
+        None => units_wplace,
+    };
+    let map_res = adapter_db.aggregate(query.group, units, window, percentiles).await?;
+    Ok(warp::reply::json(&map_res))
+}
+
+async fn act_annotation_get((login, token): (Login, Token), adapter_comm: AdapterComm, adapter_db: AdapterDb, query: QueryAnnotation) -> Result<impl Reply, Rejection> {
+    let wplace = adapter_comm.wplace_get(login, token, Action::View).await?;
+    if !help_annotation_visible(&wplace, &query.group, query.unit.as_ref()) {
+        return Err(reject_custom(ErrorServer::Unauthorized));
+    }
+    let mut annotations = adapter_db.annotations(query.group, query.unit, query.from, query.to).await?;
+    annotations.retain(|annotation| help_annotation_visible(&wplace, &annotation.group, annotation.unit.as_ref()));
+    Ok(warp::reply::json(&annotations))
 }
 
-async fn act_login(auth: Auth, dirs: Arc<Mutex<ConfigServeDir>>, adapter_comm: AdapterComm) -> Result<impl Reply, Rejection> {
+async fn act_annotation_add((login, token): (Login, Token), body: BodyAnnotationAdd, adapter_comm: AdapterComm, adapter_db: AdapterDb) -> Result<impl Reply, Rejection> {
+    handle_annotation(body.from, body.to, &body.text)?;
+    let wplace = adapter_comm.wplace_get(login.clone(), token.clone(), Action::Write).await?;
+    if !help_annotation_visible(&wplace, &body.group, body.unit.as_ref()) {
+        return Err(reject_custom(ErrorServer::Unauthorized));
+    }
+    if body.unit.is_none() {
+        adapter_comm.group_check(login.clone(), token, body.group.clone()).await?;
+    }
+    let annotation = adapter_db.annotation_add(body.group, body.unit, body.from, body.to, body.text, login).await?;
+    Ok(warp::reply::json(&annotation))
+}
+
+async fn act_annotation_edit(id: u64, (login, token): (Login, Token), body: BodyAnnotationEdit, adapter_comm: AdapterComm, adapter_db: AdapterDb) -> Result<impl Reply, Rejection> {
+    handle_annotation(body.from, body.to, &body.text)?;
+    handle_annotation_access(id, login, token, &adapter_comm, &adapter_db).await?;
+    let annotation = adapter_db.annotation_edit(id, body.from, body.to, body.text).await?;
+    Ok(warp::reply::json(&annotation))
+}
+
+async fn act_annotation_remove(id: u64, (login, token): (Login, Token), adapter_comm: AdapterComm, adapter_db: AdapterDb) -> Result<impl Reply, Rejection> {
+    handle_annotation_access(id, login, token, &adapter_comm, &adapter_db).await?;
+    let annotation = adapter_db.annotation_remove(id).await?;
+    Ok(warp::reply::json(&annotation))
+}
+
+// Passwords are not checked while the login or the client address is locked after failures
+async fn act_login(mut auth: Auth, addr: Option<SocketAddr>, dirs: Arc<Mutex<ConfigServeDir>>, adapter_comm: AdapterComm) -> Result<impl Reply, Rejection> {
+    let ip = addr.map(|addr| addr.ip());
+    let login_tried = Login::new(auth.login.clone());
+    adapter_comm.login_check(login_tried.clone(), ip).await?;
     let dirs_guard = dirs.lock().await;
     let dir_users = dirs_guard.users.clone();
     drop(dirs_guard);
-    let (login, cfg_user) = handle_user_auth(auth, &dir_users).await?;
-    let (login, token_opt) = adapter_comm.sess_make(login, None).await?;
+    let wplaces = std::mem::take(&mut auth.wplaces);
+    let res_auth = handle_user_auth(auth, &dir_users).await;
+    adapter_comm.login_result(login_tried, ip, res_auth.is_ok()).await;
+    let (login, cfg_user) = res_auth?;
+    let wplaces = handle_wplace_select(wplaces, &cfg_user)?;
+    let name_wplace = NameWplace::union(&wplaces);
+    let (login, token_opt) = adapter_comm.sess_make(login, name_wplace.clone(), None, cfg_user.role).await?;
     if let Some(token) = token_opt {
         handle_sess_set(login, token)
     } else {
-        let dirs_guard = dirs.lock().await;
-        let mut path_res = dirs_guard.wplaces.clone();
-        drop(dirs_guard);
-        let file_name = format!("{}.json", cfg_user.wplace);
-        let path_file = fs::path_extend(&mut path_res, file_name).map_err(|_| reject_custom(ErrorServer::NotFound))?;
-        if let Some(Ok(cfg_wplace)) = fs::file_deser::<ConfigWplace>(path_file).await {
-            let name_wplace = NameWplace::new(cfg_user.wplace);
-            let wplace = help_wplace_make(name_wplace, cfg_wplace);
-            if let (login, Some(token)) = adapter_comm.sess_make(login, Some(wplace)).await? {
-                handle_sess_set(login, token)
-            } else {
-                Err(reject_custom(ErrorServer::InternalServerError))
-            }
+        let parts = handle_wplace_load(wplaces, &dirs).await?;
+        if let (login, Some(token)) = adapter_comm.sess_make(login, name_wplace, Some(parts), cfg_user.role).await? {
+            handle_sess_set(login, token)
         } else {
-            Err(reject_custom(ErrorServer::NotFound))
+            Err(reject_custom(ErrorServer::InternalServerError))
         }
     }
 }
 
-async fn act_wplace((login, token): (Login, Token), adapter_comm: AdapterComm) -> Result<impl Reply, Rejection> {
-    let wplace_cfg = adapter_comm.wplace_get(login, token).await?;
+// The session goes on in the selected wplaces; the user file is read again, so the choice follows its changes
+async fn act_wplace_switch((login, token): (Login, Token), body: BodyWplace, dirs: Arc<Mutex<ConfigServeDir>>, adapter_comm: AdapterComm) -> Result<impl Reply, Rejection> {
+    let dirs_guard = dirs.lock().await;
+    let dir_users = dirs_guard.users.clone();
+    drop(dirs_guard);
+    let cfg_user = handle_user_read(&login, &dir_users).await?;
+    let wplaces = handle_wplace_select(body.wplaces, &cfg_user)?;
+    let name_wplace = NameWplace::union(&wplaces);
+    if let Some(wplace) = adapter_comm.sess_wplace(login.clone(), token.clone(), name_wplace.clone(), None).await? {
+        return Ok(warp::reply::json(&wplace));
+    }
+    let parts = handle_wplace_load(wplaces, &dirs).await?;
+    if let Some(wplace) = adapter_comm.sess_wplace(login, token, name_wplace, Some(parts)).await? {
+        Ok(warp::reply::json(&wplace))
+    } else {
+        Err(reject_custom(ErrorServer::InternalServerError))
+    }
+}
+
+async fn act_wplaces((login, token): (Login, Token), dirs: Arc<Mutex<ConfigServeDir>>, adapter_comm: AdapterComm) -> Result<impl Reply, Rejection> {
+    adapter_comm.sess_check(login.clone(), token, Action::View).await?;
+    let dirs_guard = dirs.lock().await;
+    let dir_users = dirs_guard.users.clone();
+    drop(dirs_guard);
+    let cfg_user = handle_user_read(&login, &dir_users).await?;
+    Ok(warp::reply::json(&DtoWplaces{ wplaces: cfg_user.iter_wplace().cloned().collect() }))
+}
+
+async fn act_logout((login, token): (Login, Token), adapter_comm: AdapterComm) -> Result<impl Reply, Rejection> {
+    adapter_comm.sess_close(login, token).await?;
+    Ok(warp::reply())
+}
+
+async fn act_wplace((login, token): (Login, Token), adapter_comm: AdapterComm, query: QueryWplace) -> Result<impl Reply, Rejection> {
+    let wplace_cfg = adapter_comm.wplace_get(login.clone(), token.clone(), Action::View).await?;
+    if query.info {
+        let info = adapter_comm.wplace_info(login, token).await?;
+        return Ok(warp::reply::json(&DtoWplaceInfo{ groups: wplace_cfg, info }));
+    }
     Ok(warp::reply::json(&wplace_cfg))
 }
 
 async fn act_wplace_last((login, token): (Login, Token), adapter_comm: AdapterComm, adapter_db: AdapterDb) -> Result<impl Reply, Rejection> {
-    let wplace_cfg = adapter_comm.wplace_get(login, token).await?;
+    let wplace_cfg = adapter_comm.wplace_get(login, token, Action::View).await?;
     let wplace_last = adapter_db.get_last(wplace_cfg).await?;
     Ok(warp::reply::json(&wplace_last))
 }
 
+async fn act_admin_backup(adapter_db: AdapterDb) -> Result<impl Reply, Rejection> {
+    let path = adapter_db.backup().await?;
+    Ok(warp::reply::json(&DtoBackup{ file: path.display().to_string() }))
+}
+
+async fn act_admin_revoke(login: String, adapter_comm: AdapterComm) -> Result<impl Reply, Rejection> {
+    let count = adapter_comm.sess_revoke(Login::new(login)).await?;
+    Ok(warp::reply::json(&DtoRevoke{ count }))
+}
+
 
 // HANDLERS
 fn handle_min_max(min: u64, max: u64) -> Result<(u64, u64), Rejection> {
@@ -182,19 +426,123 @@ fn handle_min_max(min: u64, max: u64) -> Result<(u64, u64), Rejection> {
     Err(reject_custom(ErrorServer::BadRequest))
 }
 
+fn handle_limit(limit: Option<u32>) -> Result<u32, Rejection> {
+    match limit {
+        None => Ok(HIST_LIMIT_DEFAULT),
+        Some(limit) if limit > 0 && limit <= HIST_LIMIT_MAX => Ok(limit),
+        Some(_) => Err(reject_custom(ErrorServer::BadRequest)),
+    }
+}
+
+fn handle_window(from: i64, to: i64, bucket: Option<i64>) -> Result<Window, Rejection> {
+    let len = to.checked_sub(from).filter(|len| *len > 0).ok_or_else(|| reject_custom(ErrorServer::BadRequest))?;
+    let window = Window{ time_min: from, time_max: to, bucket: bucket.unwrap_or(len) };
+    if window.bucket > 0 && window.bucket <= len && window.count_buckets() <= AGGREGATE_BUCKETS_MAX {
+        Ok(window)
+    } else {
+        Err(reject_custom(ErrorServer::BadRequest))
+    }
+}
+
+fn handle_percentiles(percentiles: Option<&str>) -> Result<Vec<f64>, Rejection> {
+    let text = match percentiles {
+        Some(text) => text,
+        None => return Ok(AGGREGATE_PERCENTILES_DEFAULT.to_vec()),
+    };
+    let mut vec_res = Vec::new();
+    for item in text.split(',').filter(|item| !item.is_empty()) {
+        match item.trim().parse::<f64>() {
+            Ok(p) if p > 0.0 && p <= 100.0 => vec_res.push(p),
+            _ => return Err(reject_custom(ErrorServer::BadRequest)),
+        }
+    }
+    if vec_res.len() > AGGREGATE_PERCENTILES_MAX {
+        return Err(reject_custom(ErrorServer::BadRequest));
+    }
+    Ok(vec_res)
+}
+
+fn handle_annotation(from: Option<i64>, to: Option<i64>, text: &str) -> Result<(), Rejection> {
+    if text.trim().is_empty() || text.chars().count() > ANNOTATION_TEXT_MAX {
+        return Err(reject_custom(ErrorServer::BadRequest));
+    }
+    match (from, to) {
+        (Some(from), Some(to)) if from > to => Err(reject_custom(ErrorServer::BadRequest)),
+        _ => Ok(()),
+    }
+}
+
+// Any operator who sees the annotation may change it, not only its author;
+// group-level ones are changed by admins or by operators who see the whole group
+async fn handle_annotation_access(id: u64, login: Login, token: Token, adapter_comm: &AdapterComm, adapter_db: &AdapterDb) -> Result<(), Rejection> {
+    let wplace = adapter_comm.wplace_get(login.clone(), token.clone(), Action::Write).await?;
+    let annotation = adapter_db.annotation_get(id).await?;
+    if !help_annotation_visible(&wplace, &annotation.group, annotation.unit.as_ref()) {
+        return Err(reject_custom(ErrorServer::Unauthorized));
+    }
+    if annotation.unit.is_none() {
+        adapter_comm.group_check(login, token, annotation.group).await?;
+    }
+    Ok(())
+}
+
 async fn handle_user_auth(auth: Auth, dir_users: &FsPathBuf) -> Result<(Login, ConfigUser), Rejection> {
     let login = Login::from_str(&auth.login).map_err(|_| reject_custom(ErrorServer::BadRequest))?;
+    let cfg_user = match handle_user_read(&login, dir_users).await {
+        Ok(cfg_user) => cfg_user,
+        Err(rejection) => {
+            let _ = tokio::task::spawn_blocking(move || password::verify_dummy(&auth.password)).await;
+            return Err(rejection);
+        },
+    };
+    let password_stored = cfg_user.password.clone();
+    let is_valid = tokio::task::spawn_blocking(move || password::verify(&password_stored, &auth.password)).await
+        .unwrap_or(false);
+    if is_valid {
+        Ok((login, cfg_user))
+    } else {
+        Err(reject_custom(ErrorServer::Unauthorized))
+    }
+}
+
+async fn handle_user_read(login: &Login, dir_users: &FsPathBuf) -> Result<ConfigUser, Rejection> {
     let file_name = format!("{}.json", login.as_str());
     if let Ok(path_file) = fs::path_extend(dir_users, file_name) {
         if let Some(Ok(cfg_user)) = fs::file_deser::<ConfigUser>(path_file).await {
-            if cfg_user.password == auth.password {
-                return Ok((login, cfg_user));
-            }
+            return Ok(cfg_user);
         }
     }
     Err(reject_custom(ErrorServer::Unauthorized))
 }
 
+// Only wplaces of the user are selected; none is the default one
+fn handle_wplace_select(wplaces: Vec<String>, cfg_user: &ConfigUser) -> Result<Vec<String>, Rejection> {
+    let wplaces = if wplaces.is_empty() { vec![cfg_user.wplace.clone()] } else { wplaces };
+    if !wplaces.iter().all(|wplace| cfg_user.iter_wplace().any(|allowed| allowed == wplace)) {
+        return Err(reject_custom(ErrorServer::Unauthorized));
+    }
+    if let Some(wplace) = wplaces.iter().find(|wplace| !NameWplace::is_file(wplace)) {
+        println!("[WARN] wplace \"{}\" is not loaded: \"+\" is not allowed in names of wplace files", wplace); // TODO: log this
+        return Err(reject_custom(ErrorServer::NotFound));
+    }
+    Ok(wplaces)
+}
+
+async fn handle_wplace_load(wplaces: Vec<String>, dirs: &Mutex<ConfigServeDir>) -> Result<Vec<(NameWplace, ConfigWplace)>, Rejection> {
+    let dirs_guard = dirs.lock().await;
+    let path_res = dirs_guard.wplaces.clone();
+    drop(dirs_guard);
+    let mut parts = Vec::with_capacity(wplaces.len());
+    for wplace in wplaces {
+        let file_name = format!("{}.json", wplace);
+        let path_file = fs::path_extend(&path_res, file_name).map_err(|_| reject_custom(ErrorServer::NotFound))?;
+        match fs::file_deser::<ConfigWplace>(path_file).await {
+            Some(Ok(cfg_wplace)) => parts.push((NameWplace::new(wplace), cfg_wplace)),
+            _ => return Err(reject_custom(ErrorServer::NotFound)),
+        }
+    }
+    Ok(parts)
+}
 
 fn handle_sess_set(login: Login, token: Token) -> Result<reply::WithStatus<std::string::String>, Rejection> {
     let sess = Sess{login: login.to_string(), token: token.to_string()};
@@ -207,6 +555,19 @@ async fn handle_sess_parse(sess: String) -> Result<(Login, Token), Rejection> {
     Ok(help_sess_parse(&sess).map_err(|_| reject_custom(ErrorServer::Unauthorized))?)
 }
 
+// A session of a user with the admin role, otherwise the admin token if one is configured
+async fn handle_admin_auth(token: Option<String>, sess: Option<String>, admin: Arc<Option<ConfigServeAdmin>>, adapter_comm: AdapterComm) -> Result<(), Rejection> {
+    if let Some((login, token_sess)) = sess.as_deref().and_then(|sess| help_sess_parse(sess).ok()) {
+        if adapter_comm.sess_check(login, token_sess, Action::Admin).await.is_ok() {
+            return Ok(());
+        }
+    }
+    match (admin.as_ref(), token) {
+        (Some(cfg_admin), Some(token)) if bool::from(cfg_admin.token.as_bytes().ct_eq(token.as_bytes())) => Ok(()),
+        _ => Err(reject_custom(ErrorServer::Unauthorized)),
+    }
+}
+
 
 // HELPERS
 fn help_sess_parse(sess: &str) -> Result<(Login, Token), ()> {
@@ -220,16 +581,19 @@ fn help_sess_parse(sess: &str) -> Result<(Login, Token), ()> {
     }
 }
 
-fn help_wplace_make(name: NameWplace, cfg_wplace: ConfigWplace) -> Wplace {
-    let mut map_res: HashMap<Group, HashSet<Unit>> = HashMap::with_capacity(cfg_wplace.groups.len());
-    for (group_string, vec_unit_string) in cfg_wplace.groups {
-        let mut set_unit: HashSet<Unit> = HashSet::new();
-        for unit_string in vec_unit_string {
-            set_unit.insert(Unit::new(unit_string));
-        }
-        map_res.insert(Group::new(group_string), set_unit);
+// Cursor is "<time>_<id_record>" of the last record of the previous page
+fn help_cursor_parse(cursor: &str) -> Result<(i64, u64), ()> {
+    let (time, id_record) = cursor.split_once('_').ok_or(())?;
+    Ok((time.parse().map_err(|_| ())?, id_record.parse().map_err(|_| ())?))
+}
+
+// Group-level annotations are visible with any unit of the group in the workplace
+fn help_annotation_visible(wplace: &HashMap<Group, Vec<Unit>>, group: &Group, unit: Option<&Unit>) -> bool {
+    match (wplace.get(group), unit) {
+        (Some(vec_unit), Some(unit)) => vec_unit.contains(unit),
+        (Some(_), None) => true,
+        (None, _) => false,
     }
-    Wplace::new(name, map_res)
 }
 
 async fn help_sess_recv(ws: &mut WebSocket) -> Result<(Login, Token), ()> {
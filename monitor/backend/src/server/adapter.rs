@@ -1,10 +1,14 @@
-use std::collections::HashMap;
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    path::PathBuf,
+};
 
 use futures_util::SinkExt;
 use tokio::time::{Duration, timeout};
 use tokio::sync::{
     mpsc::{Sender, error::SendError},
-    oneshot::channel as channel_one,
+    oneshot::{channel as channel_one, error::RecvError},
 };
 use warp::{
     reject::{custom as reject_custom, Rejection},
@@ -12,16 +16,21 @@ use warp::{
 };
 
 use crate::actor::{
-    comm::{Signal as SignalComm, FromServer as FromServerComm},
-    db::{Signal as SignalDb, FromServer as FromServerDb},
+    comm::{Signal as SignalComm, FromServer as FromServerComm, UnitsWplace},
+    db::{Signal as SignalDb, FromServer as FromServerDb, repo_aggregate::{Window, Bucket}},
+    db::read::{Signal as SignalRead, FromServer as FromServerRead, RangeTime},
 };
 use crate::model::{
+    annotation::Annotation,
+    info::InfoGroup,
     user::Login,
     session::Token,
-    wplace::Wplace,
+    wplace::Name as NameWplace,
     dataflow::{Group, Unit, Record, Update},
+    role::{Role, Action},
 };
 use crate::server::reject::ErrorServer;
+use crate::config::ConfigWplace;
 
 
 #[derive(Clone)]
@@ -60,11 +69,11 @@ impl Comm {
         }
     }
 
-    pub async fn sess_make(&self, login: Login, wplace: Option<Wplace>) -> Result<(Login, Option<Token>), Rejection> {
+    pub async fn sess_make(&self, login: Login, wplace: NameWplace, parts: Option<Vec<(NameWplace, ConfigWplace)>>, role: Option<Role>) -> Result<(Login, Option<Token>), Rejection> {
         let (tx, rx) = channel_one::<Result<(Login, Token), Login>>();
-        if let Err(err) = self.send_actor(FromServerComm::SessionMake { login, wplace, tx } ).await {
-            if let Some(FromServerComm::SessionMake { login, wplace, tx: _ }) = err {
-                println!("[CommAdapter] Actor unreached: SessionMake: login={}, wplace={:?}", login.to_string(), wplace); // TODO: log this
+        if let Err(err) = self.send_actor(FromServerComm::SessionMake { login, wplace, parts, role, tx } ).await {
+            if let Some(FromServerComm::SessionMake { login, wplace, .. }) = err {
+                println!("[CommAdapter] Actor unreached: SessionMake: login={}, wplace={}", login.to_string(), wplace.as_str()); // TODO: log this
                 Err(reject_custom(ErrorServer::InternalServerError))
             } else {
                 println!("[CommAdapter] Actor unreached: SessionMake: wrong responce"); // TODO: log this
@@ -84,10 +93,10 @@ impl Comm {
         }
     }
 
-    pub async fn wplace_get(&self, login: Login, token: Token) -> Result<HashMap<Group, Vec<Unit>>, Rejection> {
+    pub async fn wplace_get(&self, login: Login, token: Token, action: Action) -> Result<HashMap<Group, Vec<Unit>>, Rejection> {
         let (tx, rx) = channel_one::<Result<HashMap<Group, Vec<Unit>>, ()>>();
-        if let Err(err) = self.send_actor(FromServerComm::WplaceGet { login, token, tx } ).await {
-            if let Some(FromServerComm::WplaceGet { login, token, tx: _ }) = err {
+        if let Err(err) = self.send_actor(FromServerComm::WplaceGet { login, token, action, tx } ).await {
+            if let Some(FromServerComm::WplaceGet { login, token, .. }) = err {
                 println!("[CommAdapter] Actor unreached: WplaceGet: login={}, token={}", login.to_string(), token.to_string()); // TODO: log this
                 Err(reject_custom(ErrorServer::InternalServerError))
             } else {
@@ -108,6 +117,30 @@ impl Comm {
         }
     }
 
+    pub async fn wplace_info(&self, login: Login, token: Token) -> Result<HashMap<Group, InfoGroup>, Rejection> {
+        let (tx, rx) = channel_one::<Result<HashMap<Group, InfoGroup>, ()>>();
+        if let Err(err) = self.send_actor(FromServerComm::WplaceInfo { login, token, tx } ).await {
+            if let Some(FromServerComm::WplaceInfo { login, token, tx: _ }) = err {
+                println!("[CommAdapter] Actor unreached: WplaceInfo: login={}, token={}", login.to_string(), token.to_string()); // TODO: log this
+                Err(reject_custom(ErrorServer::InternalServerError))
+            } else {
+                println!("[CommAdapter] Actor unreached: WplaceInfo: wrong responce"); // TODO: log this
+                Err(reject_custom(ErrorServer::InternalServerError))
+            }
+        } else {
+            match rx.await {
+                Ok(res) => match res {
+                    Ok(map) => Ok(map),
+                    Err(_) => Err(reject_custom(ErrorServer::Unauthorized)),
+                },
+                Err(_) => {
+                    println!("[CommAdapter] Actor unresponded: WplaceInfo"); // TODO: log this
+                    Err(reject_custom(ErrorServer::InternalServerError))
+                },
+            }
+        }
+    }
+
     pub async fn unit_check(&self, login: Login, token: Token, group: Group, unit: Unit) -> Result<(Group, Unit), Rejection> {
         let (tx, rx) = channel_one::<Result<(Group, Unit), ()>>();
         if let Err(err) = self.send_actor(FromServerComm::UnitCheck { login, token, group, unit, tx } ).await {
@@ -132,6 +165,141 @@ impl Comm {
         }
     }
 
+    pub async fn group_check(&self, login: Login, token: Token, group: Group) -> Result<(), Rejection> {
+        let (tx, rx) = channel_one::<Result<(), ()>>();
+        if let Err(err) = self.send_actor(FromServerComm::GroupCheck { login, token, group, tx } ).await {
+            if let Some(FromServerComm::GroupCheck { login, token, group, tx: _ }) = err {
+                println!("[CommAdapter] Actor unreached: GroupCheck: login={}, token={}, group={}", login.to_string(), token.to_string(), group.to_string()); // TODO: log this
+                Err(reject_custom(ErrorServer::InternalServerError))
+            } else {
+                println!("[CommAdapter] Actor unreached: GroupCheck: wrong responce"); // TODO: log this
+                Err(reject_custom(ErrorServer::InternalServerError))
+            }
+        } else {
+            match rx.await {
+                Ok(res) => res.map_err(|_| reject_custom(ErrorServer::Unauthorized)),
+                Err(_) => {
+                    println!("[CommAdapter] Actor unresponded: GroupCheck"); // TODO: log this
+                    Err(reject_custom(ErrorServer::InternalServerError))
+                },
+            }
+        }
+    }
+
+    pub async fn sess_check(&self, login: Login, token: Token, action: Action) -> Result<(), Rejection> {
+        let (tx, rx) = channel_one::<Result<(), ()>>();
+        if let Err(err) = self.send_actor(FromServerComm::SessionCheck { login, token, action, tx } ).await {
+            if let Some(FromServerComm::SessionCheck { login, token, .. }) = err {
+                println!("[CommAdapter] Actor unreached: SessionCheck: login={}, token={}", login.to_string(), token.to_string()); // TODO: log this
+                Err(reject_custom(ErrorServer::InternalServerError))
+            } else {
+                println!("[CommAdapter] Actor unreached: SessionCheck: wrong responce"); // TODO: log this
+                Err(reject_custom(ErrorServer::InternalServerError))
+            }
+        } else {
+            match rx.await {
+                Ok(res) => res.map_err(|_| reject_custom(ErrorServer::Unauthorized)),
+                Err(_) => {
+                    println!("[CommAdapter] Actor unresponded: SessionCheck"); // TODO: log this
+                    Err(reject_custom(ErrorServer::InternalServerError))
+                },
+            }
+        }
+    }
+
+    pub async fn sess_close(&self, login: Login, token: Token) -> Result<(), Rejection> {
+        let (tx, rx) = channel_one::<Result<(), ()>>();
+        if let Err(err) = self.send_actor(FromServerComm::SessionClose { login, token, tx } ).await {
+            if let Some(FromServerComm::SessionClose { login, token, tx: _ }) = err {
+                println!("[CommAdapter] Actor unreached: SessionClose: login={}, token={}", login.to_string(), token.to_string()); // TODO: log this
+                Err(reject_custom(ErrorServer::InternalServerError))
+            } else {
+                println!("[CommAdapter] Actor unreached: SessionClose: wrong responce"); // TODO: log this
+                Err(reject_custom(ErrorServer::InternalServerError))
+            }
+        } else {
+            match rx.await {
+                Ok(res) => res.map_err(|_| reject_custom(ErrorServer::Unauthorized)),
+                Err(_) => {
+                    println!("[CommAdapter] Actor unresponded: SessionClose"); // TODO: log this
+                    Err(reject_custom(ErrorServer::InternalServerError))
+                },
+            }
+        }
+    }
+
+    // None if the wplace is not loaded and its parts are needed
+    pub async fn sess_wplace(&self, login: Login, token: Token, wplace: NameWplace, parts: Option<Vec<(NameWplace, ConfigWplace)>>) -> Result<Option<UnitsWplace>, Rejection> {
+        let (tx, rx) = channel_one::<Result<Option<UnitsWplace>, ()>>();
+        if let Err(err) = self.send_actor(FromServerComm::SessionWplace { login, token, wplace, parts, tx } ).await {
+            if let Some(FromServerComm::SessionWplace { login, token, wplace, .. }) = err {
+                println!("[CommAdapter] Actor unreached: SessionWplace: login={}, token={}, wplace={}", login.to_string(), token.to_string(), wplace.as_str()); // TODO: log this
+                Err(reject_custom(ErrorServer::InternalServerError))
+            } else {
+                println!("[CommAdapter] Actor unreached: SessionWplace: wrong responce"); // TODO: log this
+                Err(reject_custom(ErrorServer::InternalServerError))
+            }
+        } else {
+            match rx.await {
+                Ok(res) => res.map_err(|_| reject_custom(ErrorServer::Unauthorized)),
+                Err(_) => {
+                    println!("[CommAdapter] Actor unresponded: SessionWplace"); // TODO: log this
+                    Err(reject_custom(ErrorServer::InternalServerError))
+                },
+            }
+        }
+    }
+
+    // Rejected with 429 while the login or the address is locked
+    pub async fn login_check(&self, login: Login, ip: Option<IpAddr>) -> Result<(), Rejection> {
+        let (tx, rx) = channel_one::<Result<(), ()>>();
+        if let Err(err) = self.send_actor(FromServerComm::LoginCheck { login, ip, tx } ).await {
+            if let Some(FromServerComm::LoginCheck { login, .. }) = err {
+                println!("[CommAdapter] Actor unreached: LoginCheck: login={}", login.to_string()); // TODO: log this
+                Err(reject_custom(ErrorServer::InternalServerError))
+            } else {
+                println!("[CommAdapter] Actor unreached: LoginCheck: wrong responce"); // TODO: log this
+                Err(reject_custom(ErrorServer::InternalServerError))
+            }
+        } else {
+            match rx.await {
+                Ok(res) => res.map_err(|_| reject_custom(ErrorServer::TooManyRequests)),
+                Err(_) => {
+                    println!("[CommAdapter] Actor unresponded: LoginCheck"); // TODO: log this
+                    Err(reject_custom(ErrorServer::InternalServerError))
+                },
+            }
+        }
+    }
+
+    pub async fn login_result(&self, login: Login, ip: Option<IpAddr>, is_valid: bool) {
+        if let Err(err) = self.send_actor(FromServerComm::LoginResult { login, ip, is_valid } ).await {
+            if let Some(FromServerComm::LoginResult { login, .. }) = err {
+                println!("[CommAdapter] Actor unreached: LoginResult: login={}", login.to_string()); // TODO: log this
+            } else {
+                println!("[CommAdapter] Actor unreached: LoginResult: wrong responce"); // TODO: log this
+            }
+        }
+    }
+
+    pub async fn sess_revoke(&self, login: Login) -> Result<usize, Rejection> {
+        let (tx, rx) = channel_one::<usize>();
+        if let Err(err) = self.send_actor(FromServerComm::SessionRevoke { login, tx } ).await {
+            if let Some(FromServerComm::SessionRevoke { login, tx: _ }) = err {
+                println!("[CommAdapter] Actor unreached: SessionRevoke: login={}", login.to_string()); // TODO: log this
+                Err(reject_custom(ErrorServer::InternalServerError))
+            } else {
+                println!("[CommAdapter] Actor unreached: SessionRevoke: wrong responce"); // TODO: log this
+                Err(reject_custom(ErrorServer::InternalServerError))
+            }
+        } else {
+            rx.await.map_err(|_| {
+                println!("[CommAdapter] Actor unresponded: SessionRevoke"); // TODO: log this
+                reject_custom(ErrorServer::InternalServerError)
+            })
+        }
+    }
+
     async fn send_actor(&self, cmd: FromServerComm) -> Result<(), Option<FromServerComm>> {
         if let Err(err) = self.tx_actor.send(SignalComm::FromServer(cmd)).await {
             if let SendError(SignalComm::FromServer(cmd)) = err {
@@ -149,19 +317,21 @@ impl Comm {
 #[derive(Clone)]
 pub struct Db {
     tx_actor: Sender<SignalDb>,
+    tx_read: Sender<SignalRead>,
 }
 
 impl Db {
-    pub fn new(tx_actor: Sender<SignalDb>) -> Self {
+    pub fn new(tx_actor: Sender<SignalDb>, tx_read: Sender<SignalRead>) -> Self {
         Self{
-            tx_actor
+            tx_actor,
+            tx_read,
         }
     }
 
     pub async fn get_data(&mut self, group: Group, unit: Unit, idx_min: u64, idx_max: u64) -> Result<Vec<Record<Update>>, Rejection> {
         let (tx, rx) = channel_one::<Result<Vec<Record<Update>>, ()>>();
-        if let Err(err) = self.send_actor(FromServerDb::Get { group, unit, idx_min, idx_max, tx_resp: tx } ).await {
-            if let Some(FromServerDb::Get { group, unit, idx_min, idx_max, tx_resp: _ }) = err {
+        if let Err(err) = self.send_read(FromServerRead::Get { group, unit, idx_min, idx_max, tx_resp: tx } ).await {
+            if let Some(FromServerRead::Get { group, unit, idx_min, idx_max, tx_resp: _ }) = err {
                 println!("[DBAdapter] Actor unreached: Get: group={}, unit={}, idx_min={}, idx_max={}", group.to_str(), unit.to_str(), idx_min, idx_max); // TODO: log this
                 Err(reject_custom(ErrorServer::InternalServerError))
             } else {
@@ -182,6 +352,138 @@ impl Db {
         }
     }
 
+    pub async fn get_data_time(&mut self, group: Group, unit: Unit, range: RangeTime) -> Result<Vec<Record<Update>>, Rejection> {
+        let (tx, rx) = channel_one::<Result<Vec<Record<Update>>, ()>>();
+        if let Err(err) = self.send_read(FromServerRead::GetTime { group, unit, range, tx_resp: tx } ).await {
+            if let Some(FromServerRead::GetTime { group, unit, range, tx_resp: _ }) = err {
+                println!("[DBAdapter] Actor unreached: GetTime: group={}, unit={}, range={:?}", group.to_str(), unit.to_str(), range); // TODO: log this
+                Err(reject_custom(ErrorServer::InternalServerError))
+            } else {
+                println!("[DBAdapter] Actor unreached: GetTime: wrong responce"); // TODO: log this
+                Err(reject_custom(ErrorServer::InternalServerError))
+            }
+        } else {
+            match rx.await {
+                Ok(res) => match res {
+                    Ok(vec_record) => Ok(vec_record),
+                    Err(_) => Err(reject_custom(ErrorServer::BadRequest)),
+                },
+                Err(_) => {
+                    println!("[DBAdapter] Actor unresponded: GetTime"); // TODO: log this
+                    Err(reject_custom(ErrorServer::InternalServerError))
+                },
+            }
+        }
+    }
+
+    pub async fn aggregate(&self, group: Group, units: Vec<Unit>, window: Window, percentiles: Vec<f64>) -> Result<HashMap<Unit, Vec<Bucket>>, Rejection> {
+        let (tx, rx) = channel_one::<Result<HashMap<Unit, Vec<Bucket>>, ()>>();
+        if let Err(err) = self.send_read(FromServerRead::Aggregate { group, units, window, percentiles, tx_resp: tx }).await {
+            if let Some(FromServerRead::Aggregate { group, units, window, percentiles: _, tx_resp: _ }) = err {
+                println!("[DBAdapter] Actor unreached: Aggregate: group={}, units={:?}, window={:?}", group.to_str(), units, window); // TODO: log this
+                Err(reject_custom(ErrorServer::InternalServerError))
+            } else {
+                println!("[DBAdapter] Actor unreached: Aggregate: wrong responce"); // TODO: log this
+                Err(reject_custom(ErrorServer::InternalServerError))
+            }
+        } else {
+            match rx.await {
+                Ok(res) => match res {
+                    Ok(map) => Ok(map),
+                    Err(_) => Err(reject_custom(ErrorServer::BadRequest)),
+                },
+                Err(_) => {
+                    println!("[DBAdapter] Actor unresponded: Aggregate"); // TODO: log this
+                    Err(reject_custom(ErrorServer::InternalServerError))
+                },
+            }
+        }
+    }
+
+    pub async fn annotations(&self, group: Group, unit: Option<Unit>, time_min: Option<i64>, time_max: Option<i64>) -> Result<Vec<Annotation>, Rejection> {
+        let (tx, rx) = channel_one::<Result<Vec<Annotation>, ()>>();
+        if let Err(err) = self.send_read(FromServerRead::Annotations { group, unit, time_min, time_max, tx_resp: tx }).await {
+            if let Some(FromServerRead::Annotations { group, unit, time_min, time_max, tx_resp: _ }) = err {
+                println!("[DBAdapter] Actor unreached: Annotations: group={}, unit={:?}, time_min={:?}, time_max={:?}", group.to_str(), unit, time_min, time_max); // TODO: log this
+                Err(reject_custom(ErrorServer::InternalServerError))
+            } else {
+                println!("[DBAdapter] Actor unreached: Annotations: wrong responce"); // TODO: log this
+                Err(reject_custom(ErrorServer::InternalServerError))
+            }
+        } else {
+            match rx.await {
+                Ok(res) => match res {
+                    Ok(vec_annotation) => Ok(vec_annotation),
+                    Err(_) => Err(reject_custom(ErrorServer::InternalServerError)),
+                },
+                Err(_) => {
+                    println!("[DBAdapter] Actor unresponded: Annotations"); // TODO: log this
+                    Err(reject_custom(ErrorServer::InternalServerError))
+                },
+            }
+        }
+    }
+
+    pub async fn annotation_get(&self, id: u64) -> Result<Annotation, Rejection> {
+        let (tx, rx) = channel_one::<Result<Option<Annotation>, ()>>();
+        if let Err(err) = self.send_read(FromServerRead::AnnotationGet { id, tx_resp: tx }).await {
+            if let Some(FromServerRead::AnnotationGet { id, tx_resp: _ }) = err {
+                println!("[DBAdapter] Actor unreached: AnnotationGet: id={}", id); // TODO: log this
+                Err(reject_custom(ErrorServer::InternalServerError))
+            } else {
+                println!("[DBAdapter] Actor unreached: AnnotationGet: wrong responce"); // TODO: log this
+                Err(reject_custom(ErrorServer::InternalServerError))
+            }
+        } else {
+            help_annotation_resp(rx.await, "AnnotationGet")
+        }
+    }
+
+    pub async fn annotation_add(&self, group: Group, unit: Option<Unit>, time_min: Option<i64>, time_max: Option<i64>, text: String, author: Login) -> Result<Annotation, Rejection> {
+        let (tx, rx) = channel_one::<Result<Option<Annotation>, ()>>();
+        if let Err(err) = self.send_actor(FromServerDb::AnnotationAdd { group, unit, time_min, time_max, text, author, tx_resp: tx }).await {
+            if let Some(FromServerDb::AnnotationAdd { group, unit, author, .. }) = err {
+                println!("[DBAdapter] Actor unreached: AnnotationAdd: group={}, unit={:?}, author={}", group.to_str(), unit, author.as_str()); // TODO: log this
+                Err(reject_custom(ErrorServer::InternalServerError))
+            } else {
+                println!("[DBAdapter] Actor unreached: AnnotationAdd: wrong responce"); // TODO: log this
+                Err(reject_custom(ErrorServer::InternalServerError))
+            }
+        } else {
+            help_annotation_resp(rx.await, "AnnotationAdd")
+        }
+    }
+
+    pub async fn annotation_edit(&self, id: u64, time_min: Option<i64>, time_max: Option<i64>, text: String) -> Result<Annotation, Rejection> {
+        let (tx, rx) = channel_one::<Result<Option<Annotation>, ()>>();
+        if let Err(err) = self.send_actor(FromServerDb::AnnotationEdit { id, time_min, time_max, text, tx_resp: tx }).await {
+            if let Some(FromServerDb::AnnotationEdit { id, .. }) = err {
+                println!("[DBAdapter] Actor unreached: AnnotationEdit: id={}", id); // TODO: log this
+                Err(reject_custom(ErrorServer::InternalServerError))
+            } else {
+                println!("[DBAdapter] Actor unreached: AnnotationEdit: wrong responce"); // TODO: log this
+                Err(reject_custom(ErrorServer::InternalServerError))
+            }
+        } else {
+            help_annotation_resp(rx.await, "AnnotationEdit")
+        }
+    }
+
+    pub async fn annotation_remove(&self, id: u64) -> Result<Annotation, Rejection> {
+        let (tx, rx) = channel_one::<Result<Option<Annotation>, ()>>();
+        if let Err(err) = self.send_actor(FromServerDb::AnnotationRemove { id, tx_resp: tx }).await {
+            if let Some(FromServerDb::AnnotationRemove { id, tx_resp: _ }) = err {
+                println!("[DBAdapter] Actor unreached: AnnotationRemove: id={}", id); // TODO: log this
+                Err(reject_custom(ErrorServer::InternalServerError))
+            } else {
+                println!("[DBAdapter] Actor unreached: AnnotationRemove: wrong responce"); // TODO: log this
+                Err(reject_custom(ErrorServer::InternalServerError))
+            }
+        } else {
+            help_annotation_resp(rx.await, "AnnotationRemove")
+        }
+    }
+
     // map: HashMap<Group, Vec<Unit>>, tx_resp: OneSender<HashMap<Group, Vec<(Unit, Option<Record<Update>>)>>>
     // HashMap<Group, Vec<(Unit, Option<Record<Update>>)>>
     pub async fn get_last(&self, map: HashMap<Group, Vec<Unit>>) -> Result<HashMap<Group, Vec<(Unit, Option<Record<Update>>)>>, Rejection> {
@@ -205,6 +507,30 @@ impl Db {
         }
     }
 
+    pub async fn backup(&self) -> Result<PathBuf, Rejection> {
+        let (tx, rx) = channel_one::<Result<PathBuf, ()>>();
+        if let Err(err) = self.send_actor(FromServerDb::Backup { tx_resp: tx }).await {
+            if let Some(FromServerDb::Backup{tx_resp: _}) = err {
+                println!("[DBAdapter] Actor unreached: Backup"); // TODO: log this
+                Err(reject_custom(ErrorServer::InternalServerError))
+            } else {
+                println!("[DBAdapter] Actor unreached: Backup: wrong responce"); // TODO: log this
+                Err(reject_custom(ErrorServer::InternalServerError))
+            }
+        } else {
+            match rx.await {
+                Ok(res) => match res {
+                    Ok(path) => Ok(path),
+                    Err(_) => Err(reject_custom(ErrorServer::InternalServerError)),
+                },
+                Err(_) => {
+                    println!("[DBAdapter] Actor unresponded: Backup"); // TODO: log this
+                    Err(reject_custom(ErrorServer::InternalServerError))
+                },
+            }
+        }
+    }
+
     async fn send_actor(&self, cmd: FromServerDb) -> Result<(), Option<FromServerDb>> {
         if let Err(err) = self.tx_actor.send(SignalDb::FromServer(cmd)).await {
             if let SendError(SignalDb::FromServer(cmd)) = err {
@@ -216,4 +542,27 @@ impl Db {
             Ok(())
         }   
     }
+
+    async fn send_read(&self, cmd: FromServerRead) -> Result<(), Option<FromServerRead>> {
+        if let Err(err) = self.tx_read.send(SignalRead::FromServer(cmd)).await {
+            let SendError(SignalRead::FromServer(cmd)) = err;
+            Err(Some(cmd))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+
+// Missing annotation, group or unit is NotFound
+fn help_annotation_resp(res: Result<Result<Option<Annotation>, ()>, RecvError>, name: &str) -> Result<Annotation, Rejection> {
+    match res {
+        Ok(Ok(Some(annotation))) => Ok(annotation),
+        Ok(Ok(None)) => Err(reject_custom(ErrorServer::NotFound)),
+        Ok(Err(_)) => Err(reject_custom(ErrorServer::InternalServerError)),
+        Err(_) => {
+            println!("[DBAdapter] Actor unresponded: {}", name); // TODO: log this
+            Err(reject_custom(ErrorServer::InternalServerError))
+        },
+    }
 }
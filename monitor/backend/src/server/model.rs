@@ -1,6 +1,10 @@
+use std::collections::HashMap;
+
 use serde::{Deserialize, Serialize};
 
 use crate::model::dataflow::{Group, Unit, Record, Update}; 
+use crate::model::annotation::Annotation;
+use crate::model::info::InfoGroup;
 
 
 
@@ -19,20 +23,126 @@ pub struct Auth {
     pub login: String,
     #[serde(rename = "p")]
     pub password: String,
+    // wplaces to select, united if several; the default one of the user if empty
+    #[serde(rename = "w", default)]
+    pub wplaces: Vec<String>,
+}
+
+#[derive(Deserialize)]
+pub struct BodyWplace {
+    #[serde(rename = "w")]
+    pub wplaces: Vec<String>,
+}
+
+// Wplaces the user may select, the default one first
+#[derive(Serialize)]
+pub struct DtoWplaces {
+    #[serde(rename = "w")]
+    pub wplaces: Vec<String>,
+}
+
+// With 'i' the units come along with their metadata; the plain map is kept for older clients
+#[derive(Deserialize)]
+pub struct QueryWplace {
+    #[serde(rename = "i", default)]
+    pub info: bool,
+}
+
+#[derive(Serialize)]
+pub struct DtoWplaceInfo {
+    #[serde(rename = "g")]
+    pub groups: HashMap<Group, Vec<Unit>>,
+    #[serde(rename = "i")]
+    pub info: HashMap<Group, InfoGroup>,
 }
 
+// Either by id_record with 'i' and 'a', or by time with 'f', 't', 'l' and the 'c' cursor from the previous page
 #[derive(Deserialize)]
 pub struct QueryHist {
     #[serde(rename = "i")]
-    pub min: u64,
+    pub min: Option<u64>,
     #[serde(rename = "a")]
-    pub max: u64,
+    pub max: Option<u64>,
+    #[serde(rename = "f")]
+    pub from: Option<i64>,
+    #[serde(rename = "t")]
+    pub to: Option<i64>,
+    #[serde(rename = "l")]
+    pub limit: Option<u32>,
+    #[serde(rename = "c")]
+    pub cursor: Option<String>,
     #[serde(rename = "g")]
     pub group: Group,
     #[serde(rename = "u")]
     pub unit: Unit,
 }
 
+// Window is [f, t) split into buckets of 'b' ms (the whole window by default); 'p' lists percentiles, e.g. "50,95,99";
+// without 'u' all the units of the group from the workplace are aggregated
+#[derive(Deserialize)]
+pub struct QueryAggregate {
+    #[serde(rename = "g")]
+    pub group: Group,
+    #[serde(rename = "u")]
+    pub unit: Option<Unit>,
+    #[serde(rename = "f")]
+    pub from: i64,
+    #[serde(rename = "t")]
+    pub to: i64,
+    #[serde(rename = "b")]
+    pub bucket: Option<i64>,
+    #[serde(rename = "p")]
+    pub percentiles: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct DtoHistTime {
+    #[serde(rename = "r")]
+    pub records: Vec<Record<Update>>,
+    #[serde(rename = "c")]
+    pub cursor: Option<String>,
+    #[serde(rename = "n")]
+    pub annotations: Vec<Annotation>,
+}
+
+// Annotations of the group overlapping [f, t]; with 'u' only the unit's own and the group-level ones
+#[derive(Deserialize)]
+pub struct QueryAnnotation {
+    #[serde(rename = "g")]
+    pub group: Group,
+    #[serde(rename = "u")]
+    pub unit: Option<Unit>,
+    #[serde(rename = "f")]
+    pub from: Option<i64>,
+    #[serde(rename = "t")]
+    pub to: Option<i64>,
+}
+
+// Without 'u' the annotation belongs to the whole group, without 'f' / 't' its time range is open on that side
+#[derive(Deserialize)]
+pub struct BodyAnnotationAdd {
+    #[serde(rename = "g")]
+    pub group: Group,
+    #[serde(rename = "u", default)]
+    pub unit: Option<Unit>,
+    #[serde(rename = "f", default)]
+    pub from: Option<i64>,
+    #[serde(rename = "t", default)]
+    pub to: Option<i64>,
+    #[serde(rename = "x")]
+    pub text: String,
+}
+
+#[derive(Deserialize)]
+pub struct BodyAnnotationEdit {
+    #[serde(rename = "f", default)]
+    pub from: Option<i64>,
+    #[serde(rename = "t", default)]
+    pub to: Option<i64>,
+    #[serde(rename = "x")]
+    pub text: String,
+}
+
 #[derive(Serialize, Debug)]
 pub struct DtoRecord {
     #[serde(rename = "i")]
@@ -66,7 +176,18 @@ impl DtoUpdate {
         match update {
             Update::Online => Self::Online,
             Update::Offline => Self::Offline,
-            Update::Value{value} => Self::Value{v: value.into_base64()},
+            Update::Value{value, ..} => Self::Value{v: value.into_base64()},
         }
     }
 }
+
+
+#[derive(Serialize)]
+pub struct DtoBackup {
+    pub file: String,
+}
+
+#[derive(Serialize)]
+pub struct DtoRevoke {
+    pub count: usize,
+}
@@ -11,6 +11,7 @@ pub enum ErrorServer {
     BadRequest,
     InternalServerError,
     NotFound,
+    TooManyRequests,
 }
 impl ErrorServer {
     pub fn status(&self) -> StatusCode {
@@ -19,6 +20,7 @@ impl ErrorServer {
             Self::BadRequest => StatusCode::BAD_REQUEST,
             Self::InternalServerError => StatusCode::INTERNAL_SERVER_ERROR,
             Self::NotFound => StatusCode::NOT_FOUND,
+            Self::TooManyRequests => StatusCode::TOO_MANY_REQUESTS,
         }
     }
 }